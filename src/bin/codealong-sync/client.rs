@@ -0,0 +1,554 @@
+use crate::protocol::{
+    self,
+    UserActivity,
+    ServerActivity,
+    DirectoryUpdated,
+    RenameItem,
+    FileLines,
+    FileChanged,
+    LockLine,
+    CreateLine
+};
+use crate::mirror::{self, SyncedFile, LineEdit};
+use crate::local;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::{self, Message};
+
+
+pub type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub enum SyncError {
+    Socket(Box<tungstenite::Error>),
+    /// The server closed the connection, such as when the user was kicked. 
+    Closed,
+    /// The server refused an activity, contains its `DirError`. 
+    Refused(Value),
+    /// The session doesn't use `EditEngine::LineLock`, so local edits 
+    /// can't be sent as line operations. 
+    WrongEngine(Value),
+    Io(io::Error)
+}
+
+impl From<tungstenite::Error> for SyncError {
+    fn from(e: tungstenite::Error) -> Self {
+        SyncError::Socket(Box::new(e))
+    }
+}
+
+impl From<io::Error> for SyncError {
+    fn from(e: io::Error) -> Self {
+        SyncError::Io(e)
+    }
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Socket(e) => write!(f, "connection failed: {}", e),
+            SyncError::Closed => write!(f, "the server closed the connection"),
+            SyncError::Refused(err) => write!(f, "the server refused a change: {}", err),
+            SyncError::WrongEngine(err) =>
+                write!(f, "the session doesn't use line locking, local edits can't be sent: {}", err),
+            SyncError::Io(e) => write!(f, "couldn't sync the folder: {}", e)
+        }
+    }
+}
+
+/// A connection to a session keeping a folder in sync with its project. 
+pub struct SyncClient {
+    socket: Socket,
+    root: PathBuf,
+    user_id: String,
+    files: HashMap<Vec<String>, SyncedFile>,
+    dirs: HashSet<Vec<String>>,
+    /// Files whose lines must be requested again, such as after their 
+    /// lines were all replaced 
+    stale: HashSet<Vec<String>>
+}
+
+impl SyncClient {
+    /// Waits for the server to say which user the connection joined as. 
+    pub async fn join(socket: Socket, root: PathBuf) -> Result<Self, SyncError> {
+        let mut client = SyncClient {
+            socket,
+            root,
+            user_id: String::new(),
+            files: HashMap::new(),
+            dirs: HashSet::new(),
+            stale: HashSet::new()
+        };
+        loop {
+            if let ServerActivity::ResumeToken(token) = client.next_activity().await? {
+                client.user_id = token.user_id;
+                return Ok(client);
+            }
+        }
+    }
+
+    /// Loads the session's project, writing files only in the session to 
+    /// the folder, then uploads the folder into it. 
+    pub async fn start(&mut self) -> Result<(), SyncError> {
+        let reply = self.call(UserActivity::RequestSync,
+            |a| matches!(a, ServerActivity::CurrentProject(_))).await?;
+        let project = match reply {
+            ServerActivity::CurrentProject(v) => v,
+            _ => return Ok(())
+        };
+        let (mut dirs, mut files) = (vec![], vec![]);
+        project.paths(&[], &mut dirs, &mut files);
+        for dir in dirs.iter() {
+            local::create_dir(&self.root, dir)?;
+        }
+        self.dirs = dirs.into_iter().collect();
+        for path in files {
+            self.load_file(&path).await?;
+        }
+        self.push_local().await
+    }
+
+    /// Waits for the next activity from the server, activities the client 
+    /// doesn't act on are skipped. 
+    pub async fn next_activity(&mut self) -> Result<ServerActivity, SyncError> {
+        while let Some(msg) = self.socket.next().await {
+            match msg? {
+                Message::Text(text) => if let Some(activity) = protocol::parse(&text) {
+                    return Ok(activity);
+                },
+                Message::Close(_) => break,
+                _ => ()
+            }
+        }
+        Err(SyncError::Closed)
+    }
+
+    /// Applies an activity that arrived while the client wasn't waiting 
+    /// for a reply. 
+    pub async fn handle(&mut self, activity: ServerActivity) -> Result<(), SyncError> {
+        match activity {
+            ServerActivity::DirectoryErr(err) => {
+                log::warn!("{}", SyncError::Refused(err));
+                Ok(())
+            },
+            activity => {
+                self.apply(&activity).await?;
+                self.reload_stale().await
+            }
+        }
+    }
+
+    /// Sends the changes made to the folder since it was last synced, new 
+    /// files are created, deleted files erased and edited files have their 
+    /// lines updated. The merged text of each edited file is written back. 
+    pub async fn push_local(&mut self) -> Result<(), SyncError> {
+        self.reload_stale().await?;
+        let local = local::scan(&self.root)?;
+        let erased: Vec<Vec<String>> = self.files.keys()
+            .filter(|path| !local.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in erased {
+            self.files.remove(&path);
+            tolerate(self.change_dir(DirectoryUpdated::ErasedFile(path)).await)?;
+        }
+        for (path, text) in local {
+            if !self.files.contains_key(&path) && !self.create_file(&path).await? {
+                continue;
+            }
+            self.push_file(&path, &text).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, activity: UserActivity) -> Result<(), SyncError> {
+        let text = serde_json::to_string(&activity).expect("activities serialise to JSON");
+        self.socket.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// Sends an activity and waits for the server's reply, applying any 
+    /// other activity that arrives first. Every request that may be refused 
+    /// is sent through here, so a `DirectoryErr` is always the reply. 
+    async fn call<F>(&mut self, activity: UserActivity, is_reply: F) -> Result<ServerActivity, SyncError>
+    where
+        F: Fn(&ServerActivity) -> bool
+    {
+        self.send(activity).await?;
+        loop {
+            let activity = self.next_activity().await?;
+            if let ServerActivity::DirectoryErr(err) = activity {
+                return Err(refusal(err));
+            }
+            self.apply(&activity).await?;
+            if is_reply(&activity) {
+                return Ok(activity);
+            }
+        }
+    }
+
+    async fn apply(&mut self, activity: &ServerActivity) -> Result<(), SyncError> {
+        match activity {
+            ServerActivity::LineAdded(added) => {
+                if let Some(file) = self.files.get_mut(&added.filepath) {
+                    file.insert(added.at, added.add_no);
+                }
+                self.flush(&added.filepath)
+            },
+            ServerActivity::LineUpdated(updated) => {
+                if let Some(file) = self.files.get_mut(&updated.filepath) {
+                    file.update(updated.add_no, &updated.line);
+                }
+                self.flush(&updated.filepath)
+            },
+            ServerActivity::LinesRemoved(removed) => {
+                if let Some(file) = self.files.get_mut(&removed.filepath) {
+                    file.remove(&removed.add_nos);
+                }
+                self.flush(&removed.filepath)
+            },
+            ServerActivity::FileLines(lines) => self.file_loaded(lines),
+            // Every line was given a new `add_no`, so the lines are loaded again.
+            ServerActivity::FileRestored(restored) => {
+                self.stale.insert(restored.path.clone());
+                Ok(())
+            },
+            ServerActivity::CrdtApplied(edited) | ServerActivity::OtApplied(edited) => {
+                self.stale.insert(edited.filepath.clone());
+                Ok(())
+            },
+            ServerActivity::DirectoryUpdate(update) => self.dir_updated(update).await,
+            ServerActivity::CurrentProject(_) |
+            ServerActivity::DirectoryErr(_) |
+            ServerActivity::LineLocked(_) |
+            ServerActivity::LineUnlocked(_) |
+            ServerActivity::ResumeToken(_) => Ok(())
+        }
+    }
+
+    /// Writes the session's copy of a file to disk, unless the file's been 
+    /// edited since it was last synced, the edits are then sent first. 
+    fn flush(&mut self, path: &[String]) -> Result<(), SyncError> {
+        let file = match self.files.get_mut(path) {
+            Some(v) => v,
+            None => return Ok(())
+        };
+        if local::read(&self.root, path) != Some(file.base_text()) {
+            return Ok(());
+        }
+        file.rebase();
+        local::write(&self.root, path, &file.base_text())?;
+        Ok(())
+    }
+
+    fn file_loaded(&mut self, lines: &FileLines) -> Result<(), SyncError> {
+        let path = &lines.filepath;
+        let file = SyncedFile::new(lines.lines.clone());
+        let unchanged = match (local::read(&self.root, path), self.files.get(path)) {
+            (Some(text), Some(synced)) => text == synced.base_text(),
+            (None, None) => !local::disk_path(&self.root, path)?.exists(),
+            // A new file in the folder wins over the session's, a file
+            // deleted from the folder is erased from the session.
+            _ => false
+        };
+        if unchanged {
+            local::write(&self.root, path, &file.remote_text())?;
+        }
+        self.files.insert(path.clone(), file);
+        Ok(())
+    }
+
+    async fn dir_updated(&mut self, update: &DirectoryUpdated) -> Result<(), SyncError> {
+        match update {
+            DirectoryUpdated::CreatedDir(path) => {
+                self.dirs.insert(path.clone());
+                local::create_dir(&self.root, path)?;
+            },
+            DirectoryUpdated::ErasedDir(path) => {
+                self.files.retain(|p, _| !p.starts_with(path));
+                self.dirs.retain(|p| !p.starts_with(path));
+                local::remove(&self.root, path)?;
+            },
+            DirectoryUpdated::RenameDir(rename) => {
+                let to = renamed(rename);
+                self.files = self.files.drain()
+                    .map(|(p, f)| (moved(p, &rename.path, &to), f))
+                    .collect();
+                self.dirs = self.dirs.drain()
+                    .map(|p| moved(p, &rename.path, &to))
+                    .collect();
+                local::rename(&self.root, &rename.path, &to)?;
+            },
+            DirectoryUpdated::CreatedFile(path) => {
+                self.stale.insert(path.clone());
+            },
+            DirectoryUpdated::ErasedFile(path) => {
+                self.files.remove(path);
+                local::remove(&self.root, path)?;
+            },
+            DirectoryUpdated::RenameFile(rename) => {
+                let to = renamed(rename);
+                if let Some(file) = self.files.remove(&rename.path) {
+                    self.files.insert(to.clone(), file);
+                }
+                local::rename(&self.root, &rename.path, &to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the files whose lines were replaced since they were last loaded. 
+    async fn reload_stale(&mut self) -> Result<(), SyncError> {
+        while let Some(path) = self.stale.iter().next().cloned() {
+            self.stale.remove(&path);
+            self.load_file(&path).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a change to the session's directory. The server only replies 
+    /// to the sender if the change is refused, so the project is requested 
+    /// after it, any refusal arrives before the project. 
+    async fn change_dir(&mut self, update: DirectoryUpdated) -> Result<(), SyncError> {
+        self.send(UserActivity::DirUpdated(update)).await?;
+        self.call(UserActivity::RequestSync,
+            |a| matches!(a, ServerActivity::CurrentProject(_))).await?;
+        Ok(())
+    }
+
+    /// Requests a file's lines, adding it to the files kept in sync. 
+    /// 
+    /// # Returns 
+    /// If the file was loaded. 
+    async fn load_file(&mut self, path: &[String]) -> Result<bool, SyncError> {
+        let request = UserActivity::RequestFileLines(path.to_vec());
+        let reply = self.call(request,
+            |a| matches!(a, ServerActivity::FileLines(f) if f.filepath == path)).await;
+        Ok(tolerate(reply)?.is_some())
+    }
+
+    /// Creates a file from the folder in the session, along with any 
+    /// directories missing on the way. 
+    /// 
+    /// # Returns 
+    /// If the file was created. 
+    async fn create_file(&mut self, path: &[String]) -> Result<bool, SyncError> {
+        for depth in 1..path.len() {
+            let dir = path[..depth].to_vec();
+            if self.dirs.contains(&dir) {
+                continue;
+            }
+            if tolerate(self.change_dir(DirectoryUpdated::CreatedDir(dir.clone())).await)?.is_some() {
+                self.dirs.insert(dir);
+            }
+        }
+        // A file already in the session is loaded rather than created.
+        tolerate(self.change_dir(DirectoryUpdated::CreatedFile(path.to_vec())).await)?;
+        self.load_file(path).await
+    }
+
+    /// Sends the edits made to a file in the folder as line operations, 
+    /// then writes back its text merged with any edits made in the session. 
+    async fn push_file(&mut self, path: &[String], text: &str) -> Result<(), SyncError> {
+        let edits = match self.files.get(path) {
+            Some(file) if file.base_text() != text => mirror::local_edits(&file.base, text),
+            _ => return Ok(())
+        };
+        let mut refused = false;
+        for edit in edits {
+            refused |= tolerate(self.push_edit(path, edit).await)?.is_none();
+        }
+        // Refused edits are left out of the synced lines so they're sent 
+        // again next time, the folder keeps them until the session has them.
+        if refused {
+            return Ok(());
+        }
+        let file = match self.files.get_mut(path) {
+            Some(v) => v,
+            None => return Ok(())
+        };
+        file.rebase();
+        let merged = file.base_text();
+        // If the file's been edited again since it was read the edits
+        // are sent next time, rather than being overwritten.
+        if merged != text && local::read(&self.root, path).as_deref() == Some(text) {
+            local::write(&self.root, path, &merged)?;
+        }
+        Ok(())
+    }
+
+    /// Sends an edit made in the folder, each part the session accepts is 
+    /// taken as synced. 
+    async fn push_edit(&mut self, path: &[String], edit: LineEdit) -> Result<(), SyncError> {
+        let user_id = self.user_id.clone();
+        match edit {
+            LineEdit::Change { add_no, new } => {
+                let old = match self.files.get(path) {
+                    Some(file) => file.line(add_no).map(str::to_owned),
+                    None => return Ok(())
+                };
+                match old {
+                    Some(old) if old != new => {
+                        self.lock_line(path, add_no).await?;
+                        let changed = self.change_line(path, add_no, old, new).await;
+                        self.unlock_line(path, add_no).await?;
+                        changed
+                    },
+                    Some(_) => {
+                        self.accepted(path, |file| file.accept_change(add_no, &new));
+                        Ok(())
+                    },
+                    // The line was deleted in the session, it's added back 
+                    // rather than losing the edit.
+                    None => {
+                        let after = self.files.get(path).and_then(|f| f.base_before(add_no));
+                        self.insert_lines(path, after, vec![new]).await?;
+                        self.accepted(path, |file| file.accept_delete(add_no));
+                        Ok(())
+                    }
+                }
+            },
+            LineEdit::Insert { after, lines } => self.insert_lines(path, after, lines).await,
+            LineEdit::Delete { add_no } => {
+                match self.files.get(path) {
+                    Some(file) if file.index_of(add_no).is_some() => (),
+                    Some(_) => {
+                        self.accepted(path, |file| file.accept_delete(add_no));
+                        return Ok(());
+                    },
+                    None => return Ok(())
+                }
+                let delete = LockLine { filepath: path.to_vec(), line_no: add_no };
+                self.call(UserActivity::DeleteLine(delete), |a| matches!(a,
+                    ServerActivity::LinesRemoved(r) if r.filepath == path && r.user_id == user_id)).await?;
+                self.accepted(path, |file| file.accept_delete(add_no));
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds lines to a file after the line `after`, `None` for the start 
+    /// of the file. 
+    async fn insert_lines(
+        &mut self, 
+        path: &[String], 
+        after: Option<usize>, 
+        lines: Vec<String>
+    ) -> Result<(), SyncError> {
+        let user_id = self.user_id.clone();
+        let mut at = match (self.files.get(path), after) {
+            (None, _) => return Ok(()),
+            (Some(_), None) => 0,
+            (Some(file), Some(add_no)) => file.index_of(add_no)
+                .map(|i| i + 1)
+                .unwrap_or(file.remote.len())
+        };
+        let mut after = after;
+        for text in lines {
+            let create = CreateLine { filepath: path.to_vec(), at };
+            let reply = self.call(UserActivity::CreateLine(create), |a| matches!(a,
+                ServerActivity::LineAdded(l) if l.filepath == path && l.user_id == user_id)).await?;
+            let (add_no, inserted_at) = match reply {
+                ServerActivity::LineAdded(added) => (added.add_no, added.at),
+                _ => return Ok(())
+            };
+            self.accepted(path, |file| file.accept_insert(after, add_no));
+            // New lines are locked to the user who added them.
+            let changed = if text.is_empty() {
+                Ok(())
+            }
+            else {
+                self.change_line(path, add_no, String::new(), text).await
+            };
+            self.unlock_line(path, add_no).await?;
+            changed?;
+            after = Some(add_no);
+            at = inserted_at + 1;
+        }
+        Ok(())
+    }
+
+    /// Updates the synced lines of a file after the session accepted an edit. 
+    fn accepted<F>(&mut self, path: &[String], accept: F)
+    where
+        F: FnOnce(&mut SyncedFile)
+    {
+        if let Some(file) = self.files.get_mut(path) {
+            accept(file);
+        }
+    }
+
+    async fn lock_line(&mut self, path: &[String], add_no: usize) -> Result<(), SyncError> {
+        let user_id = self.user_id.clone();
+        let lock = LockLine { filepath: path.to_vec(), line_no: add_no };
+        self.call(UserActivity::LockLine(lock), |a| matches!(a,
+            ServerActivity::LineLocked(l) if l.add_no == add_no && l.user_id == user_id)).await?;
+        Ok(())
+    }
+
+    async fn unlock_line(&mut self, path: &[String], add_no: usize) -> Result<(), SyncError> {
+        let user_id = self.user_id.clone();
+        let lock = LockLine { filepath: path.to_vec(), line_no: add_no };
+        self.call(UserActivity::UnlockLine(lock), |a| matches!(a,
+            ServerActivity::LineUnlocked(l) if l.add_no == add_no && l.user_id == user_id)).await?;
+        Ok(())
+    }
+
+    async fn change_line(
+        &mut self,
+        path: &[String],
+        add_no: usize,
+        old: String,
+        new: String
+    ) -> Result<(), SyncError> {
+        let user_id = self.user_id.clone();
+        let change = FileChanged { path: path.to_vec(), line: add_no, old, new: new.clone() };
+        self.call(UserActivity::FileChanged(change), |a| matches!(a,
+            ServerActivity::LineUpdated(u) if u.filepath == path && u.add_no == add_no && u.user_id == user_id)).await?;
+        self.accepted(path, |file| file.accept_change(add_no, &new));
+        Ok(())
+    }
+}
+
+fn refusal(err: Value) -> SyncError {
+    match err.get("WrongEngine") {
+        Some(_) => SyncError::WrongEngine(err),
+        None => SyncError::Refused(err)
+    }
+}
+
+/// Logs an activity the server refused rather than stopping the sync, 
+/// such as an edit to a line locked by another user. 
+fn tolerate<T>(res: Result<T, SyncError>) -> Result<Option<T>, SyncError> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(SyncError::Refused(err)) => {
+            log::warn!("{}", SyncError::Refused(err));
+            Ok(None)
+        },
+        Err(e) => Err(e)
+    }
+}
+
+fn renamed(rename: &RenameItem) -> Vec<String> {
+    let mut to = rename.path.clone();
+    if let Some(name) = to.last_mut() {
+        *name = rename.name.clone();
+    }
+    to
+}
+
+/// Moves a path under directory `from` to under `to`. 
+fn moved(path: Vec<String>, from: &[String], to: &[String]) -> Vec<String> {
+    match path.strip_prefix(from) {
+        Some(rest) => [to, rest].concat(),
+        None => path
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+/// Gets where a session path is on disk, refusing names that would 
+/// reach outside the folder. 
+pub fn disk_path(root: &Path, path: &[String]) -> io::Result<PathBuf> {
+    let unsafe_name = |name: &String| name.is_empty() 
+        || name == "." 
+        || name == ".." 
+        || name.contains(['/', '\\']);
+    if path.is_empty() || path.iter().any(unsafe_name) {
+        let msg = format!("{} can't be synced to disk", path.join("/"));
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+    }
+    Ok(path.iter().fold(root.to_path_buf(), |p, name| p.join(name)))
+}
+
+/// Reads every text file under the folder, keyed by their session path. 
+/// Hidden entries, symlinks and files that aren't UTF-8 are skipped. 
+pub fn scan(root: &Path) -> io::Result<BTreeMap<Vec<String>, String>> {
+    let mut files = BTreeMap::new();
+    scan_dir(root, &[], &mut files)?;
+    Ok(files)
+}
+
+fn scan_dir(
+    dir: &Path,
+    parent: &[String],
+    files: &mut BTreeMap<Vec<String>, String>
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(v) if !v.starts_with('.') => v,
+            _ => continue
+        };
+        let mut path = parent.to_vec();
+        path.push(name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            scan_dir(&entry.path(), &path, files)?;
+        }
+        else if file_type.is_file() {
+            if let Ok(text) = fs::read_to_string(entry.path()) {
+                files.insert(path, text);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads a file's text, `None` if it doesn't exist or isn't UTF-8. 
+pub fn read(root: &Path, path: &[String]) -> Option<String> {
+    fs::read_to_string(disk_path(root, path).ok()?).ok()
+}
+
+/// Writes a file's text, creating any missing directories on the way. 
+pub fn write(root: &Path, path: &[String], text: &str) -> io::Result<()> {
+    let target = disk_path(root, path)?;
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(target, text)
+}
+
+pub fn create_dir(root: &Path, path: &[String]) -> io::Result<()> {
+    fs::create_dir_all(disk_path(root, path)?)
+}
+
+/// Removes a file or directory, it not existing isn't an error. 
+pub fn remove(root: &Path, path: &[String]) -> io::Result<()> {
+    let target = disk_path(root, path)?;
+    let res = if target.is_dir() {
+        fs::remove_dir_all(target)
+    }
+    else {
+        fs::remove_file(target)
+    };
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res
+    }
+}
+
+/// Renames a file or directory, it not existing isn't an error. 
+pub fn rename(root: &Path, path: &[String], to: &[String]) -> io::Result<()> {
+    match fs::rename(disk_path(root, path)?, disk_path(root, to)?) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res
+    }
+}
@@ -0,0 +1,175 @@
+mod client;
+mod local;
+mod mirror;
+mod protocol;
+
+use client::{SyncClient, SyncError};
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use tokio::time::{self, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+
+const USAGE: &str = "usage: codealong-sync [OPTIONS] <SERVER> <SESSION_ID> <FOLDER>
+
+Uploads a folder into a session's project, then keeps them in sync. Edits to
+the folder are sent as line operations, which only sessions using line
+locking accept, and edits made in the session are written back to it.
+
+Arguments:
+  <SERVER>      Address of the server, such as ws://localhost:8080
+  <SESSION_ID>  The session to join
+  <FOLDER>      The folder kept in sync with the session's project
+
+Options:
+  --name <NAME>            Name shown to other users [default: codealong-sync]
+  --password <PASSWORD>    Password of a password protected session
+  --invite <TOKEN>         Invite token of an invite only session
+  --token <ACCESS_TOKEN>   Bearer token if the server requires authentication
+  --interval-ms <MS>       How often the folder is checked for edits [default: 500]";
+
+/// The header the session's password is sent in, rather than the URL. 
+const PASSWORD_HEADER: &str = "x-session-password";
+
+/// Characters escaped in the path and query of the join URL. 
+const URL_ESCAPED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Where to join and which folder to keep in sync. 
+struct Options {
+    server: String,
+    session_id: String,
+    folder: PathBuf,
+    name: String,
+    password: Option<String>,
+    invite: Option<String>,
+    access_token: Option<String>,
+    interval: Duration
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut name = "codealong-sync".to_owned();
+        let (mut password, mut invite, mut access_token) = (None, None, None);
+        let mut interval_ms = 500;
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+            match arg.as_str() {
+                "--name" => name = value,
+                "--password" => password = Some(value),
+                "--invite" => invite = Some(value),
+                "--token" => access_token = Some(value),
+                "--interval-ms" => interval_ms = value.parse::<u64>()
+                    .map_err(|_| format!("invalid value for --interval-ms: {}", value))?,
+                _ => return Err(format!("unknown option {}", arg))
+            }
+        }
+        let [server, session_id, folder]: [String; 3] = positional.try_into()
+            .map_err(|_| "expected <SERVER> <SESSION_ID> <FOLDER>".to_owned())?;
+        Ok(Options {
+            server,
+            session_id,
+            folder: PathBuf::from(folder),
+            name,
+            password,
+            invite,
+            access_token,
+            interval: Duration::from_millis(interval_ms.max(1))
+        })
+    }
+
+    /// The websocket URL joining the session, `http` addresses are 
+    /// taken as `ws`. 
+    fn join_url(&self) -> String {
+        let server = self.server.trim_end_matches('/');
+        let server = match server.strip_prefix("http://") {
+            Some(host) => format!("ws://{}", host),
+            None => server.to_owned()
+        };
+        let mut url = format!("{}/users/join/{}/{}",
+            server, escape(&self.session_id), escape(&self.name));
+        let query: Vec<String> = [
+            ("invite", &self.invite),
+            ("access_token", &self.access_token)
+        ].iter()
+            .filter_map(|(key, value)| value.as_ref().map(|v| format!("{}={}", key, escape(v))))
+            .collect();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        url
+    }
+
+    /// The request joining the session, giving the password in a header. 
+    fn join_request(&self) -> Result<Request, SyncError> {
+        let mut request = self.join_url().into_client_request()?;
+        if let Some(password) = &self.password {
+            let value = HeaderValue::from_str(password)
+                .map_err(|e| WsError::HttpFormat(e.into()))?;
+            request.headers_mut().insert(PASSWORD_HEADER, value);
+        }
+        Ok(request)
+    }
+}
+
+fn escape(s: &str) -> String {
+    utf8_percent_encode(s, URL_ESCAPED).to_string()
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::formatted_builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_filters(&env::var("RUST_LOG").unwrap_or_default())
+        .init();
+
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if !options.folder.is_dir() {
+        eprintln!("{} isn't a folder", options.folder.display());
+        process::exit(2);
+    }
+
+    if let Err(e) = run(options).await {
+        log::error!("{}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(options: Options) -> Result<(), SyncError> {
+    let (socket, _) = tokio_tungstenite::connect_async(options.join_request()?).await?;
+    let mut client = SyncClient::join(socket, options.folder.clone()).await?;
+    client.start().await?;
+    log::info!("syncing {} with session {}", options.folder.display(), options.session_id);
+
+    let mut ticker = time::interval(options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            activity = client.next_activity() => client.handle(activity?).await?,
+            _ = ticker.tick() => client.push_local().await?
+        }
+    }
+}
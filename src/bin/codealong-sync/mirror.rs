@@ -0,0 +1,223 @@
+use crate::protocol::NumberedLine;
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+
+/// A file being kept in sync, with its lines in the session and as they 
+/// were when last written to, or read from, disk. 
+pub struct SyncedFile {
+    /// The file's lines in the session, kept up to date from the server's 
+    /// activities 
+    pub remote: Vec<NumberedLine>,
+    /// The lines on disk when the file was last synced, local edits are 
+    /// made against these 
+    pub base: Vec<NumberedLine>
+}
+
+impl SyncedFile {
+    pub fn new(lines: Vec<NumberedLine>) -> Self {
+        SyncedFile { remote: lines.clone(), base: lines }
+    }
+
+    pub fn remote_text(&self) -> String {
+        join_lines(&self.remote)
+    }
+
+    pub fn base_text(&self) -> String {
+        join_lines(&self.base)
+    }
+
+    /// Takes the session's lines as synced to disk. 
+    pub fn rebase(&mut self) {
+        self.base = self.remote.clone();
+    }
+
+    /// Takes a line's text in the session as synced, once the session 
+    /// accepted the edit. 
+    pub fn accept_change(&mut self, add_no: usize, text: &str) {
+        if let Some(line) = self.base.iter_mut().find(|l| l.add_no == add_no) {
+            line.line = text.to_owned();
+        }
+    }
+
+    /// Takes a line added to the session as synced, empty until its 
+    /// text is accepted. 
+    pub fn accept_insert(&mut self, after: Option<usize>, add_no: usize) {
+        let at = match after {
+            None => 0,
+            Some(after) => self.base.iter()
+                .position(|l| l.add_no == after)
+                .map(|i| i + 1)
+                .unwrap_or(self.base.len())
+        };
+        self.base.insert(at, NumberedLine { add_no, line: String::new() });
+    }
+
+    /// Takes a line removed from the session as synced. 
+    pub fn accept_delete(&mut self, add_no: usize) {
+        self.base.retain(|l| l.add_no != add_no);
+    }
+
+    /// The line before a line as last synced, `None` for the first line. 
+    pub fn base_before(&self, add_no: usize) -> Option<usize> {
+        let at = self.base.iter().position(|l| l.add_no == add_no)?;
+        at.checked_sub(1).map(|i| self.base[i].add_no)
+    }
+
+    pub fn index_of(&self, add_no: usize) -> Option<usize> {
+        self.remote.iter().position(|l| l.add_no == add_no)
+    }
+
+    pub fn line(&self, add_no: usize) -> Option<&str> {
+        self.remote.iter()
+            .find(|l| l.add_no == add_no)
+            .map(|l| l.line.as_str())
+    }
+
+    pub fn insert(&mut self, at: usize, add_no: usize) {
+        let at = at.min(self.remote.len());
+        self.remote.insert(at, NumberedLine { add_no, line: String::new() });
+    }
+
+    pub fn update(&mut self, add_no: usize, text: &str) {
+        if let Some(line) = self.remote.iter_mut().find(|l| l.add_no == add_no) {
+            line.line = text.to_owned();
+        }
+    }
+
+    pub fn remove(&mut self, add_nos: &[usize]) {
+        self.remote.retain(|l| !add_nos.contains(&l.add_no));
+    }
+}
+
+fn join_lines(lines: &[NumberedLine]) -> String {
+    lines.iter()
+        .map(|l| l.line.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// A change made to a file on disk, addressed to the lines it was made 
+/// against so it can be applied to the session's copy. 
+pub enum LineEdit {
+    /// Replaces the text of a line. 
+    Change { add_no: usize, new: String },
+    /// Adds lines after a line, `None` for the start of the file. 
+    Insert { after: Option<usize>, lines: Vec<String> },
+    Delete { add_no: usize }
+}
+
+/// Diffs a file's text on disk against the lines it was last synced with. 
+pub fn local_edits(base: &[NumberedLine], text: &str) -> Vec<LineEdit> {
+    let old: Vec<&str> = base.iter().map(|l| l.line.as_str()).collect();
+    let new: Vec<&str> = text.split('\n').collect();
+    let mut edits = vec![];
+    for op in capture_diff_slices(Algorithm::Myers, &old, &new) {
+        match op {
+            DiffOp::Equal { .. } => (),
+            DiffOp::Delete { old_index, old_len, .. } => {
+                deletes(&base[old_index..old_index + old_len], &mut edits);
+            },
+            DiffOp::Insert { old_index, new_index, new_len } => {
+                let after = old_index.checked_sub(1).map(|i| base[i].add_no);
+                let lines = to_owned(&new[new_index..new_index + new_len]);
+                edits.push(LineEdit::Insert { after, lines });
+            },
+            DiffOp::Replace { old_index, old_len, new_index, new_len } => {
+                let paired = old_len.min(new_len);
+                for i in 0..paired {
+                    let add_no = base[old_index + i].add_no;
+                    edits.push(LineEdit::Change { add_no, new: new[new_index + i].to_owned() });
+                }
+                deletes(&base[old_index + paired..old_index + old_len], &mut edits);
+                if new_len > paired {
+                    let after = Some(base[old_index + paired - 1].add_no);
+                    let lines = to_owned(&new[new_index + paired..new_index + new_len]);
+                    edits.push(LineEdit::Insert { after, lines });
+                }
+            }
+        }
+    }
+    edits
+}
+
+fn deletes(lines: &[NumberedLine], edits: &mut Vec<LineEdit>) {
+    edits.extend(lines.iter().map(|l| LineEdit::Delete { add_no: l.add_no }));
+}
+
+fn to_owned(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|s| (*s).to_owned()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(text: &str) -> SyncedFile {
+        SyncedFile::new(text.split('\n')
+            .enumerate()
+            .map(|(add_no, line)| NumberedLine { add_no, line: line.to_owned() })
+            .collect())
+    }
+
+    /// Accepts each edit as the session would, numbering new lines from `next_add_no`. 
+    fn accept_all(file: &mut SyncedFile, edits: Vec<LineEdit>, mut next_add_no: usize) {
+        for edit in edits {
+            match edit {
+                LineEdit::Change { add_no, new } => file.accept_change(add_no, &new),
+                LineEdit::Insert { mut after, lines } => for line in lines {
+                    file.accept_insert(after, next_add_no);
+                    file.accept_change(next_add_no, &line);
+                    after = Some(next_add_no);
+                    next_add_no += 1;
+                },
+                LineEdit::Delete { add_no } => file.accept_delete(add_no)
+            }
+        }
+    }
+
+    #[test]
+    fn accepted_edits_reach_the_local_text() {
+        let cases = [
+            ("one\ntwo\nthree", "zero\none\nTWO\nthree\nfour"),
+            ("one\ntwo\nthree", "three"),
+            ("one\ntwo\nthree", "a\nb\nc\nd\ne"),
+            ("one", ""),
+            ("", "one\ntwo")
+        ];
+        for (base, text) in cases {
+            let mut file = synced(base);
+            let edits = local_edits(&file.base, text);
+            accept_all(&mut file, edits, 100);
+            assert_eq!(file.base_text(), text);
+            assert!(local_edits(&file.base, text).is_empty());
+        }
+    }
+
+    #[test]
+    fn unaccepted_edits_are_diffed_again() {
+        let mut file = synced("one\ntwo\nthree");
+        let text = "ONE\ntwo\nTHREE";
+        let edits: Vec<LineEdit> = local_edits(&file.base, text).into_iter()
+            .filter(|e| !matches!(e, LineEdit::Change { add_no: 0, .. }))
+            .collect();
+        accept_all(&mut file, edits, 100);
+        assert_eq!(file.base_text(), "one\ntwo\nTHREE");
+        let retried = local_edits(&file.base, text);
+        assert!(matches!(retried.as_slice(), [LineEdit::Change { add_no: 0, new }] if new == "ONE"));
+    }
+
+    #[test]
+    fn remote_lines_follow_the_session() {
+        let mut file = synced("one\ntwo");
+        file.insert(1, 5);
+        file.update(5, "added");
+        file.remove(&[0]);
+        assert_eq!(file.remote_text(), "added\ntwo");
+        assert_eq!(file.base_text(), "one\ntwo");
+        assert_eq!(file.base_before(1), Some(0));
+        assert_eq!(file.base_before(0), None);
+        file.rebase();
+        assert_eq!(file.base_text(), "added\ntwo");
+    }
+}
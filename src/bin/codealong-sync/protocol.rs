@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+
+/// The activities the sync client sends, serialised the same as the 
+/// server's `UserActivity`. 
+#[derive(Serialize)]
+pub enum UserActivity {
+    DirUpdated(DirectoryUpdated),
+    FileChanged(FileChanged),
+    LockLine(LockLine),
+    UnlockLine(LockLine),
+    CreateLine(CreateLine),
+    DeleteLine(LockLine),
+    RequestFileLines(Vec<String>),
+    RequestSync
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum DirectoryUpdated {
+    ErasedDir(Vec<String>),
+    CreatedDir(Vec<String>),
+    RenameDir(RenameItem),
+    CreatedFile(Vec<String>),
+    ErasedFile(Vec<String>),
+    RenameFile(RenameItem)
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RenameItem {
+    pub path: Vec<String>,
+    pub name: String
+}
+
+#[derive(Serialize)]
+pub struct FileChanged {
+    pub path: Vec<String>,
+    pub line: usize,
+    pub old: String,
+    pub new: String
+}
+
+/// Addresses a line by its `add_no`, used to lock, unlock and delete it. 
+#[derive(Serialize, Clone)]
+pub struct LockLine {
+    pub filepath: Vec<String>,
+    pub line_no: usize
+}
+
+#[derive(Serialize)]
+pub struct CreateLine {
+    pub filepath: Vec<String>,
+    pub at: usize
+}
+
+/// The activities the sync client acts on, deserialised from the server's 
+/// `ServerActivity`, any others are skipped. 
+#[derive(Deserialize)]
+pub enum ServerActivity {
+    CurrentProject(DirectoryDTO),
+    DirectoryErr(Value),
+    DirectoryUpdate(DirectoryUpdated),
+    LineLocked(FileLineLocked),
+    LineUnlocked(FileLineLocked),
+    LineAdded(FileLineAdded),
+    LineUpdated(FileLineUpdated),
+    LinesRemoved(FileLinesRemoved),
+    FileLines(FileLines),
+    FileRestored(FileRestored),
+    CrdtApplied(FileEdited),
+    OtApplied(FileEdited),
+    ResumeToken(ResumeToken)
+}
+
+#[derive(Deserialize, Default)]
+pub struct DirectoryDTO {
+    pub files: HashMap<String, Vec<String>>,
+    pub subdirs: HashMap<String, DirectoryDTO>
+}
+
+impl DirectoryDTO {
+    /// Collects the paths of every directory and file in the project. 
+    pub fn paths(&self, parent: &[String], dirs: &mut Vec<Vec<String>>, files: &mut Vec<Vec<String>>) {
+        for name in self.files.keys() {
+            files.push(child_path(parent, name));
+        }
+        for (name, subdir) in self.subdirs.iter() {
+            let path = child_path(parent, name);
+            subdir.paths(&path, dirs, files);
+            dirs.push(path);
+        }
+    }
+}
+
+fn child_path(parent: &[String], name: &str) -> Vec<String> {
+    let mut path = parent.to_vec();
+    path.push(name.to_owned());
+    path
+}
+
+#[derive(Deserialize)]
+pub struct FileLineLocked {
+    pub add_no: usize,
+    pub user_id: String
+}
+
+#[derive(Deserialize)]
+pub struct FileLineAdded {
+    pub filepath: Vec<String>,
+    pub add_no: usize,
+    pub at: usize,
+    pub user_id: String
+}
+
+#[derive(Deserialize)]
+pub struct FileLineUpdated {
+    pub filepath: Vec<String>,
+    pub add_no: usize,
+    pub user_id: String,
+    pub line: String
+}
+
+#[derive(Deserialize)]
+pub struct FileLinesRemoved {
+    pub filepath: Vec<String>,
+    pub add_nos: Vec<usize>,
+    pub user_id: String
+}
+
+#[derive(Deserialize, Clone)]
+pub struct NumberedLine {
+    pub add_no: usize,
+    pub line: String
+}
+
+#[derive(Deserialize)]
+pub struct FileLines {
+    pub filepath: Vec<String>,
+    pub lines: Vec<NumberedLine>
+}
+
+/// A file whose lines were all replaced, its new `add_no`s have to be 
+/// requested. 
+#[derive(Deserialize)]
+pub struct FileRestored {
+    pub path: Vec<String>
+}
+
+/// A file edited by a CRDT or text operation, its new `add_no`s have to 
+/// be requested. 
+#[derive(Deserialize)]
+pub struct FileEdited {
+    pub filepath: Vec<String>
+}
+
+#[derive(Deserialize)]
+pub struct ResumeToken {
+    pub user_id: String
+}
+
+/// Reads an activity sent by the server, `None` if it's one the sync 
+/// client doesn't act on. 
+pub fn parse(text: &str) -> Option<ServerActivity> {
+    let mut msg: Map<String, Value> = serde_json::from_str(text).ok()?;
+    let activity = msg.remove("ServerActivity")?;
+    serde_json::from_value(activity).ok()
+}
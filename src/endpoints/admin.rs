@@ -0,0 +1,51 @@
+use crate::{
+    logic::admin as admin_logic,
+    endpoints::auth as auth_endpoints,
+    models::auth::Identity,
+    models::lifecycle::{SessionEvents, AdminQuery},
+    utils::settings::AppSettings
+};
+
+use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::reply::Reply;
+use warp::reject;
+use warp::reject::Rejection;
+
+
+fn event_stream(
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path("events")
+        .and(warp::ws())
+        .and(warp::query::<AdminQuery>())
+        .and(settings.clone())
+        .and(events.clone())
+        .and_then(|
+            ws: warp::ws::Ws,
+            query: AdminQuery,
+            settings: AppSettings,
+            events: SessionEvents
+        | async move {
+            match admin_logic::stream_events(ws, query, settings, events) {
+                Ok(val) => Ok::<_, Rejection>(val),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+pub fn make_admin_filters(
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+
+    let events = event_stream(settings, events);
+
+    warp::path("admin")
+        .and(auth_endpoints::require_auth(auth))
+        .and(events)
+        .boxed()
+}
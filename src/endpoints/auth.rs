@@ -0,0 +1,92 @@
+use crate::{
+    logic::auth as auth_logic,
+    models::auth::{Identity, AuthQuery, TokenRequest},
+    models::session::{SessionCredentials, InviteQuery, PASSWORD_HEADER},
+    utils::settings::AppSettings
+};
+
+use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::reply::{self, Reply};
+use warp::reject;
+use warp::reject::Rejection;
+
+
+/// The largest body accepted when asking for a token. 
+const MAX_TOKEN_REQUEST_BYTES: u64 = 4 * 1024;
+
+/// Extracts who a request is from, rejecting it if authentication is 
+/// enabled and it holds no valid bearer token. 
+pub fn authenticated(
+    settings: &BoxedFilter<(AppSettings, )>
+) -> BoxedFilter<(Option<Identity>, )> {
+    warp::header::optional::<String>("authorization")
+        .and(warp::query::<AuthQuery>())
+        .and(settings.clone())
+        .and_then(|
+            header: Option<String>,
+            query: AuthQuery,
+            settings: AppSettings
+        | async move {
+            match auth_logic::authenticate(&settings, header, query.access_token) {
+                Ok(val) => Ok::<_, Rejection>(val),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+/// Extracts the credentials given for a protected session, the password 
+/// from `PASSWORD_HEADER` and any invite token from the query. 
+pub fn session_credentials() -> BoxedFilter<(SessionCredentials, )> {
+    warp::header::optional::<String>(PASSWORD_HEADER)
+        .and(warp::query::<InviteQuery>())
+        .map(|password: Option<String>, query: InviteQuery| SessionCredentials {
+            password,
+            invite: query.invite
+        })
+        .boxed()
+}
+
+/// Rejects a request if authentication is enabled and it holds no valid 
+/// bearer token, for routes that don't need to know who it's from. 
+pub fn require_auth(
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<()> {
+    auth.clone()
+        .map(|_| ())
+        .untuple_one()
+        .boxed()
+}
+
+fn issue_token(
+    settings: &BoxedFilter<(AppSettings, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path("token")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_TOKEN_REQUEST_BYTES))
+        .and(warp::body::json::<TokenRequest>())
+        .and(settings.clone())
+        .and_then(|
+            request: TokenRequest,
+            settings: AppSettings
+        | async move {
+            match auth_logic::issue_token(request, settings).await {
+                Ok(val) => Ok::<_, Rejection>(reply::json(&val)),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+pub fn make_auth_filters(
+    settings: &BoxedFilter<(AppSettings, )>
+) -> BoxedFilter<(impl Reply, )> {
+
+    let token = issue_token(settings);
+
+    warp::path("auth")
+        .and(token)
+        .boxed()
+}
@@ -0,0 +1,33 @@
+use crate::models::errors::CodealongError;
+
+use warp::http::{StatusCode, header};
+use warp::reply::{self, Reply};
+use warp::reject::Rejection;
+
+
+/// Turns the errors handlers reject with into replies with a matching 
+/// status, leaving warp's own rejections to its default handling. 
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    let err = match err.find::<CodealongError>() {
+        Some(v) => v,
+        None => return Err(err)
+    };
+    let status = match err {
+        CodealongError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+        CodealongError::NotFound => StatusCode::NOT_FOUND,
+        CodealongError::MaxCapacity => StatusCode::SERVICE_UNAVAILABLE,
+        CodealongError::InvalidArchive => StatusCode::BAD_REQUEST,
+        CodealongError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        CodealongError::Unauthorized => StatusCode::UNAUTHORIZED,
+        CodealongError::AlreadyJoined => StatusCode::CONFLICT,
+        CodealongError::Banned => StatusCode::FORBIDDEN,
+        CodealongError::BadRequest => StatusCode::BAD_REQUEST,
+        CodealongError::Forbidden => StatusCode::FORBIDDEN,
+        CodealongError::Conflict => StatusCode::CONFLICT
+    };
+    let res = reply::with_status(format!("{:?}", err), status);
+    if status == StatusCode::UNAUTHORIZED {
+        return Ok(reply::with_header(res, header::WWW_AUTHENTICATE, "Bearer").into_response());
+    }
+    Ok(res.into_response())
+}
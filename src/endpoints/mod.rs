@@ -1,2 +1,7 @@
 pub mod session;
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod templates;
+pub mod admin;
+pub mod auth;
+pub mod project;
+pub mod errors;
@@ -0,0 +1,196 @@
+use crate::{
+    endpoints::auth as auth_endpoints,
+    logic::project as project_logic,
+    models::session::{SessionStore, SessionCredentials},
+    models::auth::Identity,
+    utils::settings::AppSettings
+};
+
+use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::filters::path::Tail;
+use warp::http::StatusCode;
+use warp::reply::{self, Reply};
+use warp::reject;
+use warp::reject::Rejection;
+use warp::hyper::body::Bytes;
+
+
+/// The largest file body accepted, the project is then limited by 
+/// `max_proj_size_kb`. 
+const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+fn project_tree(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("tree"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore
+        | async move {
+            match project_logic::project_tree(session_id, creds, identity, settings, sessions_str).await {
+                Ok(val) => Ok::<_, Rejection>(reply::json(&val)),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn read_file(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("files"))
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            path: Tail,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore
+        | async move {
+            let path = path.as_str().to_owned();
+            match project_logic::read_file(session_id, path, creds, identity, settings, sessions_str).await {
+                Ok(val) => Ok::<_, Rejection>(val),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn create_file(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("files"))
+        .and(warp::path::tail())
+        .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_FILE_BYTES))
+        .and(warp::body::bytes())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            path: Tail,
+            body: Bytes,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore
+        | async move {
+            let path = path.as_str().to_owned();
+            match project_logic::create_file(session_id, path, body, creds, identity, settings, sessions_str).await {
+                Ok(_) => Ok::<_, Rejection>(StatusCode::CREATED),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn overwrite_file(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("files"))
+        .and(warp::path::tail())
+        .and(warp::put())
+        .and(warp::body::content_length_limit(MAX_FILE_BYTES))
+        .and(warp::body::bytes())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            path: Tail,
+            body: Bytes,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore
+        | async move {
+            let path = path.as_str().to_owned();
+            match project_logic::overwrite_file(session_id, path, body, creds, identity, settings, sessions_str).await {
+                Ok(_) => Ok::<_, Rejection>(StatusCode::NO_CONTENT),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn delete_file(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("files"))
+        .and(warp::path::tail())
+        .and(warp::delete())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            path: Tail,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore
+        | async move {
+            let path = path.as_str().to_owned();
+            match project_logic::delete_file(session_id, path, creds, identity, settings, sessions_str).await {
+                Ok(_) => Ok::<_, Rejection>(StatusCode::NO_CONTENT),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+/// Routes for reading and writing a session's project over HTTP, for tools 
+/// that can't use the websocket protocol. 
+pub fn make_project_filters(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+
+    let tree = project_tree(session, settings, auth);
+    let read = read_file(session, settings, auth);
+    let create = create_file(session, settings, auth);
+    let overwrite = overwrite_file(session, settings, auth);
+    let delete = delete_file(session, settings, auth);
+
+    tree.or(read)
+        .or(create)
+        .or(overwrite)
+        .or(delete)
+        .boxed()
+}
@@ -1,7 +1,16 @@
 use crate::{
     logic::session as session_logic,
-    models::session::SessionStore,
-    utils::settings::AppSettings
+    logic::recording as recording_logic,
+    logic::auth as auth_logic,
+    endpoints::auth as auth_endpoints,
+    endpoints::project as project_endpoints,
+    models::auth::Identity,
+    models::metadata::SessionListQuery,
+    models::session::{SessionStore, NewSessionQuery, ImportQuery, ExportQuery, SessionCredentials, PASSWORD_HEADER},
+    models::lifecycle::SessionEvents,
+    models::recording::PlaybackQuery,
+    utils::settings::AppSettings,
+    utils::storage::Storage
 };
 
 use warp::Filter;
@@ -9,8 +18,13 @@ use warp::filters::BoxedFilter;
 use warp::reply::{self, Reply};
 use warp::reject;
 use warp::reject::Rejection;
+use warp::hyper::body::Bytes;
 
 
+/// The largest archive body accepted when importing a project, the unpacked 
+/// project is then limited by `max_proj_size_kb`. 
+const MAX_UPLOAD_BYTES: u64 = 16 * 1024 * 1024;
+
 fn capacity_filters(
     session: &BoxedFilter<(SessionStore, )>, 
     settings: &BoxedFilter<(AppSettings, )>
@@ -32,31 +46,138 @@ fn available_filters(
 ) -> BoxedFilter<(impl Reply, )> {
     warp::path("available_active")
         .and(warp::get())
+        .and(auth_endpoints::session_credentials())
         .and(settings.clone())
         .and(session.clone())
-        .and_then(|settings, state| async {
-            let result = session_logic::available_active_sessions(settings, state).await;
+        .and_then(|creds: SessionCredentials, settings, state| async move {
+            let result = session_logic::available_active_sessions(&creds, settings, state).await;
             Ok::<_, Rejection>(reply::json(&result))
         })
         .boxed()
 }
 
-fn make_new_session(
+fn list_filters(
     session: &BoxedFilter<(SessionStore, )>, 
     settings: &BoxedFilter<(AppSettings, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path("list")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<SessionListQuery>())
+        .and(auth_endpoints::session_credentials())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            query: SessionListQuery,
+            creds: SessionCredentials,
+            settings: AppSettings,
+            state: SessionStore
+        | async move {
+            let result = session_logic::list_sessions(query, &creds, settings, state).await;
+            Ok::<_, Rejection>(reply::json(&result))
+        })
+        .boxed()
+}
+
+fn make_new_session(
+    session: &BoxedFilter<(SessionStore, )>, 
+    settings: &BoxedFilter<(AppSettings, )>,
+    storage: &BoxedFilter<(Storage, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
 ) -> BoxedFilter<(impl Reply, )> {
     warp::path("new")
         .and(warp::ws())
         .and(warp::path::param())
+        .and(warp::query::<NewSessionQuery>())
+        .and(warp::header::optional::<String>(PASSWORD_HEADER))
+        .and(auth.clone())
         .and(settings.clone())
         .and(session.clone())
+        .and(storage.clone())
+        .and(events.clone())
         .and_then(|
             ws: warp::ws::Ws,
             user_name: String,
+            mut query: NewSessionQuery,
+            password: Option<String>,
+            identity: Option<Identity>,
+            settings: AppSettings, 
+            sessions_str: SessionStore,
+            storage: Storage,
+            events: SessionEvents
+        | async move {
+            query.password = password;
+            let identity = auth_logic::identify(identity, user_name);
+            match session_logic::make_new_session(identity, query, ws, settings, sessions_str, storage, events).await {
+                Ok(val) => Ok::<_, Rejection>(val),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn import_session(
+    session: &BoxedFilter<(SessionStore, )>, 
+    settings: &BoxedFilter<(AppSettings, )>,
+    storage: &BoxedFilter<(Storage, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path("new")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(auth.clone())
+        .and(warp::query::<ImportQuery>())
+        .and(warp::header::optional::<String>(PASSWORD_HEADER))
+        .and(warp::body::content_length_limit(MAX_UPLOAD_BYTES))
+        .and(warp::body::bytes())
+        .and(settings.clone())
+        .and(session.clone())
+        .and(storage.clone())
+        .and(events.clone())
+        .and_then(|
+            creator: Option<Identity>,
+            mut query: ImportQuery,
+            password: Option<String>,
+            body: Bytes,
             settings: AppSettings, 
+            sessions_str: SessionStore,
+            storage: Storage,
+            events: SessionEvents
+        | async move {
+            query.password = password;
+            match session_logic::import_session(creator, query, body, settings, sessions_str, storage, events).await {
+                Ok(val) => Ok::<_, Rejection>(reply::json(&val)),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn export_session(
+    session: &BoxedFilter<(SessionStore, )>,
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<ExportQuery>())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and_then(|
+            session_id: String,
+            query: ExportQuery,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
             sessions_str: SessionStore
         | async move {
-            match session_logic::make_new_session(user_name, ws, settings, sessions_str).await {
+            match session_logic::export_session(session_id, query, creds, identity, settings, sessions_str).await {
                 Ok(val) => Ok::<_, Rejection>(val),
                 Err(err) => Err(reject::custom(err))
             }
@@ -64,18 +185,65 @@ fn make_new_session(
         .boxed()
 }
 
+fn playback_session(
+    session: &BoxedFilter<(SessionStore, )>, 
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path::param()
+        .and(warp::path("playback"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::query::<PlaybackQuery>())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and(events.clone())
+        .and_then(|
+            session_id: String,
+            query: PlaybackQuery,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
+            settings: AppSettings,
+            sessions_str: SessionStore,
+            events: SessionEvents
+        | async move {
+            match recording_logic::start_playback(session_id, query, creds, identity, settings, sessions_str, events).await {
+                Ok(val) => Ok::<_, Rejection>(reply::json(&val)),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
 pub fn make_session_filters(
     session: &BoxedFilter<(SessionStore, )>, 
-    settings: &BoxedFilter<(AppSettings, )>
+    settings: &BoxedFilter<(AppSettings, )>,
+    storage: &BoxedFilter<(Storage, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
 ) -> BoxedFilter<(impl Reply, )> {
 
     let available_sessions = available_filters(session, settings);
+    let list_sessions = list_filters(session, settings);
     let session_capacity = capacity_filters(session, settings);
-    let new_session = make_new_session(session, settings);
+    let new_session = make_new_session(session, settings, storage, events, auth);
+    let import = import_session(session, settings, storage, events, auth);
+    let export = export_session(session, settings, auth);
+    let playback = playback_session(session, settings, events, auth);
+    let project = project_endpoints::make_project_filters(session, settings, auth);
     
-    let sessions = available_sessions
-        .or(session_capacity)
-        .or(new_session);
+    let sessions = new_session
+        .or(import)
+        .or(project)
+        .or(export)
+        .or(playback)
+        .or(auth_endpoints::require_auth(auth)
+            .and(available_sessions
+                .or(list_sessions)
+                .or(session_capacity)));
 
     warp::path("session")
         .and(sessions)
@@ -0,0 +1,28 @@
+use crate::{
+    logic::session as session_logic,
+    endpoints::auth as auth_endpoints,
+    models::auth::Identity,
+    utils::settings::AppSettings
+};
+
+use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::reply::{self, Reply};
+use warp::reject::Rejection;
+
+
+pub fn make_templates_filters(
+    settings: &BoxedFilter<(AppSettings, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply, )> {
+    warp::path("templates")
+        .and(warp::path::end())
+        .and(auth_endpoints::require_auth(auth))
+        .and(warp::get())
+        .and(settings.clone())
+        .and_then(|settings| async {
+            let result = session_logic::available_templates(settings).await;
+            Ok::<_, Rejection>(reply::json(&result))
+        })
+        .boxed()
+}
@@ -1,6 +1,10 @@
 use crate::{
+    endpoints::auth as auth_endpoints,
     logic::user as user_logic,
-    models::session::SessionStore,
+    logic::auth as auth_logic,
+    models::auth::Identity,
+    models::session::{SessionStore, SessionCredentials},
+    models::lifecycle::SessionEvents,
     utils::settings::AppSettings,
 };
 
@@ -13,22 +17,62 @@ use warp::reject::Rejection;
 
 fn join_session(
     session: &BoxedFilter<(SessionStore, )>, 
-    settings: &BoxedFilter<(AppSettings, )>
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
 ) -> BoxedFilter<(impl Reply,)> {
     warp::path("join")
         .and(warp::ws())
         .and(warp::path::param())
         .and(warp::path::param())
+        .and(auth_endpoints::session_credentials())
+        .and(auth.clone())
         .and(settings.clone())
         .and(session.clone())
+        .and(events.clone())
         .and_then(|
             ws: warp::ws::Ws, 
             session_id: String, 
             user_name: String,
+            creds: SessionCredentials,
+            identity: Option<Identity>,
             settings: AppSettings, 
-            sessions_str: SessionStore
+            sessions_str: SessionStore,
+            events: SessionEvents
         | async move {
-            match user_logic::new_user(session_id, user_name, ws, settings, sessions_str).await {
+            let identity = auth_logic::identify(identity, user_name);
+            match user_logic::new_user(session_id, identity, creds, ws, settings, sessions_str, events).await {
+                Ok(val) => Ok::<_, Rejection>(val),
+                Err(err) => Err(reject::custom(err))
+            }
+        })
+        .boxed()
+}
+
+fn rejoin_session(
+    session: &BoxedFilter<(SessionStore, )>, 
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
+) -> BoxedFilter<(impl Reply,)> {
+    warp::path("rejoin")
+        .and(warp::ws())
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(auth.clone())
+        .and(settings.clone())
+        .and(session.clone())
+        .and(events.clone())
+        .and_then(|
+            ws: warp::ws::Ws, 
+            session_id: String, 
+            token: String,
+            identity: Option<Identity>,
+            settings: AppSettings, 
+            sessions_str: SessionStore,
+            events: SessionEvents
+        | async move {
+            match user_logic::resume_user(session_id, token, identity, ws, settings, sessions_str, events).await {
                 Ok(val) => Ok::<_, Rejection>(val),
                 Err(err) => Err(reject::custom(err))
             }
@@ -38,12 +82,16 @@ fn join_session(
 
 pub fn make_users_filters(
     session: &BoxedFilter<(SessionStore, )>, 
-    settings: &BoxedFilter<(AppSettings, )>
+    settings: &BoxedFilter<(AppSettings, )>,
+    events: &BoxedFilter<(SessionEvents, )>,
+    auth: &BoxedFilter<(Option<Identity>, )>
 ) -> BoxedFilter<(impl Reply, )> {
 
-    let join_session = join_session(session, settings);
+    let join_session = join_session(session, settings, events, auth);
+    let rejoin_session = rejoin_session(session, settings, events, auth);
 
-    let users = join_session;
+    let users = join_session
+        .or(rejoin_session);
 
     warp::path("users")
         .and(users)
@@ -0,0 +1,67 @@
+use crate::{
+    models::{
+        errors::CodealongError,
+        lifecycle::{SessionEvents, SessionEvent, AdminQuery}
+    },
+    utils::settings::AppSettings,
+    utils::tokens
+};
+
+use warp::reply::Reply;
+use warp::ws::{Message, WebSocket};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use futures::SinkExt;
+use futures_util::StreamExt;
+
+use serde_json::to_string as to_json_string;
+
+
+/// Streams session lifecycle events over a websocket, if the query holds 
+/// the configured `AppSettings::admin_token`. 
+pub fn stream_events(
+    ws: warp::ws::Ws,
+    query: AdminQuery,
+    settings: AppSettings,
+    events: SessionEvents
+) -> Result<impl Reply, CodealongError> {
+    match settings.admin_token {
+        Some(token) if tokens::secrets_match(&token, &query.token) => (),
+        Some(_) => return Err(CodealongError::Unauthorized),
+        None => return Err(CodealongError::NotFound)
+    }
+    let rx = events.subscribe();
+    Ok(ws.on_upgrade(move |socket| event_thread(socket, rx)))
+}
+
+async fn event_thread(ws: WebSocket, rx: broadcast::Receiver<SessionEvent>) {
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let mut rx = rx;
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let event = match event {
+                    Ok(v) => v,
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("admin event stream missed {} events", missed);
+                        continue;
+                    },
+                    Err(RecvError::Closed) => break
+                };
+                let text = match to_json_string(&event) {
+                    Ok(v) => v,
+                    Err(_) => continue
+                };
+                if ws_tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            },
+            msg = ws_rx.next() => match msg {
+                Some(Ok(_)) => (),
+                // The admin has disconnected.
+                _ => break
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    models::{
+        auth::{Claims, Identity, AuthUser, TokenRequest, IssuedToken},
+        errors::CodealongError
+    },
+    utils::settings::AppSettings,
+    utils::jwt,
+    utils::tokens,
+    utils::time::now_secs
+};
+
+use serde_json::from_slice;
+
+use uuid::Uuid;
+
+
+/// Works out who a request is from, given the bearer token from its 
+/// `Authorization` header or `access_token` query. 
+/// 
+/// # Returns 
+/// * `Ok(Some(identity))` - If the token is valid. 
+/// * `Ok(None)` - If authentication is disabled. 
+/// * `Err(Unauthorized)` - If authentication is enabled and the token is missing or invalid. 
+pub fn authenticate(
+    settings: &AppSettings,
+    header: Option<String>,
+    access_token: Option<String>
+) -> Result<Option<Identity>, CodealongError> {
+    let secret = match &settings.jwt_secret {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    let token = match (header, access_token) {
+        (Some(header), _) => header.strip_prefix("Bearer ").map(|t| t.trim().to_owned()),
+        (None, token) => token
+    };
+    let claims = token.and_then(|token| jwt::decode(secret, &token, now_secs()));
+    match claims {
+        Some(claims) => Ok(Some(Identity { id: claims.sub, name: claims.name })),
+        None => Err(CodealongError::Unauthorized)
+    }
+}
+
+/// Gives a user their identity in a session, anonymous users get a new id 
+/// and the name they asked for. 
+pub fn identify(identity: Option<Identity>, user_name: String) -> Identity {
+    match identity {
+        Some(v) => v,
+        None => Identity { id: Uuid::new_v4().to_string(), name: user_name }
+    }
+}
+
+/// Issues a bearer token to a user in the `auth_users_file`, meant for 
+/// development rather than as a real identity provider. 
+pub async fn issue_token(
+    request: TokenRequest,
+    settings: AppSettings
+) -> Result<IssuedToken, CodealongError> {
+    let (secret, path) = match (&settings.jwt_secret, &settings.auth_users_file) {
+        (Some(secret), Some(path)) => (secret, path),
+        _ => return Err(CodealongError::NotFound)
+    };
+    let users = match tokio::fs::read(path).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("failed to read auth users file {}: {}", path, e);
+            return Err(CodealongError::InternalServerError)
+        }
+    };
+    let users: Vec<AuthUser> = match from_slice(&users) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("failed to parse auth users file {}: {}", path, e);
+            return Err(CodealongError::InternalServerError)
+        }
+    };
+    let user = match users.into_iter().find(|u| u.id == request.id) {
+        Some(v) if tokens::secrets_match(&v.password, &request.password) => v,
+        _ => return Err(CodealongError::Unauthorized)
+    };
+
+    let iat = now_secs();
+    let exp = iat.saturating_add(settings.auth_token_ttl_secs);
+    let claims = Claims { sub: user.id, name: user.name, iat, exp };
+    Ok(IssuedToken { token: jwt::encode(secret, &claims), expires_at: exp })
+}
@@ -0,0 +1,81 @@
+use crate::{
+    models::{
+        user_activity::CrdtEdit,
+        session_activity::SendTo,
+        session::Session,
+        server_activity::ServerActivity,
+        directory::DirError,
+        crdt::{FileCrdtApplied, FileCrdtState}
+    }
+};
+
+use super::file::wrap_dir_err;
+use super::file as file_logic;
+
+use futures::FutureExt;
+
+
+/// Merges a user's character level edits into a file, rebroadcasting 
+/// any operations that changed the file to the other users. 
+/// 
+/// # Returns
+/// * `SendTo::ToOtherUsers(CrdtApplied)` - If the edits were merged.
+/// * `SendTo::ToNone` - If every operation had already been applied.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the file couldn't be found or an edit couldn't be merged.
+pub async fn apply_edit(
+    user_id: &str,
+    edit: CrdtEdit,
+    session: &Session
+) -> SendTo {
+    let user_id = user_id.to_owned();
+    let filepath = edit.path.clone();
+    let quota = session.quota.clone();
+    let res = session.rootdir.transverse_blocking(&filepath, 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let ops = file.apply_crdt_ops(&edit.ops, &quota).await?;
+            if !ops.is_empty() {
+                let lines = file.read().await;
+                file.record_revision(&lines, &user_id).await;
+            }
+            Ok(FileCrdtApplied { filepath: edit.path, user_id, ops })
+        }.boxed()
+    ).await;
+
+    if let Ok(Ok(_)) = res {
+        file_logic::store_file(&filepath, session).await;
+    }
+    match res {
+        Ok(Ok(v)) if v.ops.is_empty() => SendTo::ToNone,
+        Ok(Ok(v)) => SendTo::ToOtherUsers(ServerActivity::CrdtApplied(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
+
+/// Sends the full CRDT state of a file to the requesting user, so they can 
+/// reference existing characters in their edits. 
+pub async fn stream_out_state(
+    path: Vec<String>,
+    session: &Session
+) -> SendTo {
+    let res = session.rootdir.transverse_blocking(&path.clone(), 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let doc = file.crdt_state().await;
+            Ok(FileCrdtState { filepath: path, doc })
+        }.boxed()
+    ).await;
+
+    match res {
+        Ok(Ok(v)) => SendTo::ToSameUser(ServerActivity::CrdtState(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     models::{
         session::{
             Session, 
+            EditEngine
         },
         directory::{
             DirError, 
@@ -9,13 +10,29 @@ use crate::{
             Directory,
             RenameItem
         }, 
-        file::File,
+        file::{
+            File,
+            FileLine,
+            FileLineAdded,
+            FileLineUpdated,
+            FileLineUnlocked,
+            FileLinesRemoved
+        },
+        crdt::FileCrdtApplied,
+        ot::{OtOps, TextOperationApplied},
+        quota::{text_size, line_size},
         server_activity::ServerActivity,
-        session_activity::{SendTo, SessionActivity}
-    }
+        session_activity::{SendTo, SessionActivity},
+        user_activity::{LockLine, WriteFile, WriteMode},
+        undo::FileRestored
+    },
+    utils::storage::{StoreWrite, valid_name}
 };
 
-use futures::FutureExt;
+use std::sync::atomic::Ordering;
+
+use futures::{FutureExt, future::join_all};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
 
 
 pub async fn directory_changed(
@@ -45,8 +62,8 @@ pub async fn inner(
     dir: DirectoryUpdated
 ) -> Result<DirectoryUpdated, DirError> {
     match dir {
-        DirectoryUpdated::ErasedFile(v) => create_file(v, session).await,
-        DirectoryUpdated::CreatedFile(v) => deleted_file(v, session).await,
+        DirectoryUpdated::ErasedFile(v) => delete_file(v, None, session).await,
+        DirectoryUpdated::CreatedFile(v) => create_file(v, session).await,
         DirectoryUpdated::RenameFile(v) => rename_file(v, session).await,
 
         DirectoryUpdated::ErasedDir(v) => delete_dir(v, session).await,
@@ -59,34 +76,270 @@ async fn create_file(
     path: Vec<String>,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if path.len() == 0 {
+    if path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
-    let path_cpy = path.iter().map(|val| val.clone()).collect();
+    check_name(&path)?;
+    let path_cpy = path.clone();
+    let store = session.store.clone();
+    let quota = session.quota.clone();
     session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
         let mut files = dir.files.write().await;
         if files.contains_key(&filename) {
             return Err(DirError::NameClash)
         }
+        quota.grow(text_size(""))?;
         files.insert(filename.clone(), File::default_with(""));
+        store.write(StoreWrite::WriteFile(path_cpy.clone(), String::new()));
         Ok(DirectoryUpdated::CreatedFile(path_cpy))
     }.boxed()).await?
 }
 
-async fn deleted_file(
+/// Creates a file holding the given lines. 
+/// 
+/// # Returns 
+/// * `Ok(DirectoryUpdate(CreatedFile))` - If the file was created empty. 
+/// * `Ok(FileRestored)` - If the file was created with text. 
+pub async fn create_file_with(
+    path: Vec<String>,
+    lines: Vec<String>,
+    session: &Session
+) -> Result<SessionActivity, DirError> {
+    if path.is_empty() {
+        return Err(DirError::NotFound("".to_owned()));
+    }
+    check_name(&path)?;
+    let store = session.store.clone();
+    let quota = session.quota.clone();
+    let path_cpy = path.clone();
+    session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
+        let mut files = dir.files.write().await;
+        if files.contains_key(&filename) {
+            return Err(DirError::NameClash)
+        }
+        quota.grow(lines.iter().map(|l| line_size(l)).sum())?;
+        let text = lines.join("\n");
+        files.insert(filename, File::from_lines(lines.clone()));
+        store.write(StoreWrite::WriteFile(path_cpy.clone(), text.clone()));
+        let act = match lines.as_slice() {
+            [line] if line.is_empty() =>
+                ServerActivity::DirectoryUpdate(DirectoryUpdated::CreatedFile(path_cpy)),
+            _ => ServerActivity::FileRestored(FileRestored { path: path_cpy, lines })
+        };
+        Ok(act.wrap_to_session())
+    }.boxed()).await?
+}
+
+/// Writes the whole text of a file, creating it or replacing its lines. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsersEach(activities)` - The activities creating the file or changing its lines. 
+/// * `SendTo::ToNone` - If the file already had the text. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the file couldn't be written. 
+pub async fn write_file(
+    user_id: &str,
+    write: WriteFile,
+    session: &Session
+) -> SendTo {
+    let res = match write.mode {
+        WriteMode::Create => create_file_with(write.path, write.lines, session).await.map(|act| vec![act]),
+        WriteMode::Overwrite => replace_file(user_id, write.path, write.lines, None, session).await
+    };
+    match res {
+        Ok(acts) if acts.is_empty() => SendTo::ToNone,
+        Ok(acts) => SendTo::ToAllUsersEach(acts),
+        Err(e) => pack_errors(e)
+    }
+}
+
+/// Replaces every line of a file through the session's edit engine, so 
+/// connected users can apply it as they would another user's edits, 
+/// keeping its revision history. Fails if any line is locked, as its 
+/// holder may be editing it. 
+/// 
+/// # Arguments 
+/// * `expected` - The lines the file must have for it to be replaced, if any. 
+/// 
+/// # Returns 
+/// * `Ok(LinesRemoved, LineAdded, LineUpdated, LineUnlocked)` - The changed lines, for `EditEngine::LineLock`. 
+/// * `Ok(CrdtApplied)` - The character operations, for `EditEngine::Crdt`. 
+/// * `Ok(OtApplied)` - The text operation, for `EditEngine::Ot`. 
+/// * `Ok([])` - If the text didn't change. 
+/// * `Err(Superseded)` - If the file's lines aren't the ones expected. 
+pub async fn replace_file(
+    user_id: &str,
+    path: Vec<String>,
+    lines: Vec<String>,
+    expected: Option<Vec<String>>,
+    session: &Session
+) -> Result<Vec<SessionActivity>, DirError> {
+    if path.is_empty() {
+        return Err(DirError::NotFound("".to_owned()));
+    }
+    let user_id = user_id.to_owned();
+    let store = session.store.clone();
+    let quota = session.quota.clone();
+    let engine = session.engine;
+    let path_cpy = path.clone();
+    session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
+        let files = dir.files.read().await;
+        let file = match files.get(&filename) {
+            Some(v) => v,
+            None => return Err(DirError::NotFound(filename))
+        };
+        for line in file.read().await.iter() {
+            if line.line_data.read().await.locked.is_some() {
+                return Err(DirError::LineLocked(LockLine { filepath: path_cpy, line_no: line.add_no }))
+            }
+        }
+        let current = file.text().await;
+        if expected.is_some_and(|expected| expected.join("\n") != current) {
+            return Err(DirError::Superseded);
+        }
+        let text = lines.join("\n");
+        if current == text {
+            return Ok(vec![]);
+        }
+        let acts = match engine {
+            EditEngine::LineLock => {
+                quota.resize(file.byte_size().await, lines.iter().map(|l| line_size(l)).sum())?;
+                replace_lines(file, &path_cpy, &user_id, &lines).await
+            },
+            EditEngine::Crdt => {
+                let ops = file.crdt_state().await.diff_ops(&text, &user_id);
+                let ops = file.apply_crdt_ops(&ops, &quota).await?;
+                let applied = FileCrdtApplied { filepath: path_cpy.clone(), user_id: user_id.clone(), ops };
+                vec![ServerActivity::CrdtApplied(applied)]
+            },
+            EditEngine::Ot => {
+                let (revision, old) = file.ot_state().await;
+                let ops = OtOps::between(&old, &text);
+                let (revision, ops) = file.apply_text_operation(revision, ops, &quota).await?;
+                let applied = TextOperationApplied { filepath: path_cpy.clone(), user_id: user_id.clone(), revision, ops };
+                vec![ServerActivity::OtApplied(applied)]
+            }
+        };
+        let file_lines = file.read().await;
+        file.record_revision(&file_lines, &user_id).await;
+        store.write(StoreWrite::WriteFile(path_cpy, text));
+        Ok(acts.into_iter().map(ServerActivity::wrap_to_session).collect())
+    }.boxed()).await?
+}
+
+/// Changes the lines of a file that differ from `new`, giving the line 
+/// activities a user would see for the same edits. 
+async fn replace_lines(
+    file: &File,
+    filepath: &[String],
+    user_id: &str,
+    new: &[String]
+) -> Vec<ServerActivity> {
+    let mut lines = file._write().await;
+    let old = join_all(lines.iter().map(|l| l.get())).await;
+    let mut acts = vec![];
+    // Working back from the end keeps the indexes of earlier changes valid.
+    for diff in capture_diff_slices(Algorithm::Myers, &old, new).iter().rev() {
+        if let DiffOp::Equal { .. } = diff {
+            continue;
+        }
+        let (old_range, new_range) = (diff.old_range(), diff.new_range());
+        let updated = old_range.len().min(new_range.len());
+        for i in 0..updated {
+            let line = &lines[old_range.start + i];
+            let text = new[new_range.start + i].clone();
+            line.line_data.write().await.line = text.clone();
+            acts.push(ServerActivity::LineUpdated(FileLineUpdated {
+                filepath: filepath.to_vec(),
+                add_no: line.add_no,
+                user_id: user_id.to_owned(),
+                line: text
+            }));
+        }
+        if old_range.len() > updated {
+            let removed = lines.drain(old_range.start + updated..old_range.end);
+            acts.push(ServerActivity::LinesRemoved(FileLinesRemoved {
+                filepath: filepath.to_vec(),
+                add_nos: removed.map(|l| l.add_no).collect(),
+                user_id: user_id.to_owned()
+            }));
+        }
+        for i in updated..new_range.len() {
+            let at = old_range.start + i;
+            let text = new[new_range.start + i].clone();
+            let mut line = FileLine::new(&text);
+            line.add_no = file.line_count.fetch_add(1, Ordering::Relaxed);
+            let add_no = line.add_no;
+            lines.insert(at, line);
+            acts.push(ServerActivity::LineAdded(FileLineAdded {
+                filepath: filepath.to_vec(),
+                add_no,
+                at,
+                user_id: user_id.to_owned()
+            }));
+            acts.push(ServerActivity::LineUpdated(FileLineUpdated {
+                filepath: filepath.to_vec(),
+                add_no,
+                user_id: user_id.to_owned(),
+                line: text
+            }));
+            acts.push(ServerActivity::LineUnlocked(FileLineUnlocked {
+                filepath: filepath.to_vec(),
+                add_no,
+                user_id: user_id.to_owned()
+            }));
+        }
+    }
+    acts
+}
+
+/// Reads the whole text of a file, its lines joined by newlines. 
+pub async fn read_file(
+    path: Vec<String>,
+    session: &Session
+) -> Result<String, DirError> {
+    if path.is_empty() {
+        return Err(DirError::NotFound("".to_owned()));
+    }
+    session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
+        let files = dir.files.read().await;
+        match files.get(&filename) {
+            Some(file) => Ok(file.text().await),
+            None => Err(DirError::NotFound(filename))
+        }
+    }.boxed()).await?
+}
+
+/// Deletes a file, if given only when its lines still read `expected`. 
+/// 
+/// # Returns 
+/// * `Ok(ErasedFile)` - If the file was deleted. 
+/// * `Err(DirError::Superseded)` - If the file's lines don't read as expected. 
+/// * `Err(DirError::NotFound)` - If the file couldn't be found. 
+pub async fn delete_file(
     path: Vec<String>,
+    expected: Option<Vec<String>>,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if path.len() == 0 {
+    if path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
-    let path_cpy = path.iter().map(|val| val.clone()).collect();
+    let path_cpy = path.clone();
+    let store = session.store.clone();
+    let quota = session.quota.clone();
     session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
         let mut files = dir.files.write().await;
-        if !files.contains_key(&filename) {
-            return Err(DirError::NotFound(filename))
+        let file = match files.get(&filename) {
+            Some(v) => v,
+            None => return Err(DirError::NotFound(filename))
+        };
+        if let Some(expected) = expected {
+            if file.text().await != expected.join("\n") {
+                return Err(DirError::Superseded)
+            }
         }
+        quota.shrink(file.byte_size().await);
         files.remove(&filename);
+        store.write(StoreWrite::RemoveFile(path_cpy.clone()));
         Ok(DirectoryUpdated::ErasedFile(path_cpy))
     }.boxed()).await?
 }
@@ -95,17 +348,25 @@ async fn rename_file(
     rename: RenameItem,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if rename.path.len() == 0 {
+    if rename.path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
+    if !valid_name(&rename.name) {
+        return Err(DirError::InvalidName(rename.name));
+    }
+    let store = session.store.clone();
     session.rootdir.transverse_blocking(&rename.path.clone(), 0, |filename, dir| async move {
         let mut files = dir.files.write().await;
+        if files.contains_key(&rename.name) {
+            return Err(DirError::NameClash)
+        }
         let (_, target_file) = match files.get(&filename) {
             Some(v) => Directory::clone_file((&filename, v)).await,
             _ => return Err(DirError::NotFound(filename))
         };
         files.remove(&filename);
         files.insert(rename.name.clone(), target_file);
+        store.write(StoreWrite::Rename(rename.path.clone(), rename.name.clone()));
         Ok(DirectoryUpdated::RenameFile(rename))
     }.boxed()).await?
 }
@@ -116,10 +377,12 @@ async fn create_dir(
     path: Vec<String>,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if path.len() == 0 {
+    if path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
-    let path_cpy = path.iter().map(|val| val.clone()).collect();
+    check_name(&path)?;
+    let path_cpy = path.clone();
+    let store = session.store.clone();
     session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
         let mut dirs = dir.subdirs.write().await;
         if dirs.contains_key(&filename) {
@@ -127,6 +390,7 @@ async fn create_dir(
         }
         let new_dir = Directory::default();
         dirs.insert(filename.clone(), new_dir);
+        store.write(StoreWrite::CreateDir(path_cpy.clone()));
         Ok(DirectoryUpdated::CreatedDir(path_cpy))
     }.boxed()).await?
 }
@@ -135,16 +399,20 @@ async fn delete_dir(
     path: Vec<String>,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if path.len() == 0 {
+    if path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
-    let path_cpy = path.iter().map(|val| val.clone()).collect();
+    let path_cpy = path.clone();
+    let store = session.store.clone();
+    let quota = session.quota.clone();
     session.rootdir.transverse_blocking(&path, 0, |filename, dir| async move {
         let mut dirs = dir.subdirs.write().await;
-        if !dirs.contains_key(&filename) {
-            return Err(DirError::NotFound(filename))
-        }
-        dirs.remove(&filename);
+        let removed = match dirs.remove(&filename) {
+            Some(v) => v,
+            None => return Err(DirError::NotFound(filename))
+        };
+        quota.shrink(removed.byte_size().await);
+        store.write(StoreWrite::RemoveDir(path_cpy.clone()));
         Ok(DirectoryUpdated::ErasedDir(path_cpy))
     }.boxed()).await?
 }
@@ -153,17 +421,95 @@ async fn rename_dir(
     rename: RenameItem,
     session: &Session
 ) -> Result<DirectoryUpdated, DirError> {
-    if rename.path.len() == 0 {
+    if rename.path.is_empty() {
         return Err(DirError::NotFound("".to_owned()));
     }
+    if !valid_name(&rename.name) {
+        return Err(DirError::InvalidName(rename.name));
+    }
+    let store = session.store.clone();
     session.rootdir.transverse_blocking(&rename.path.clone(), 0, |filename, dir| async move {
         let mut dirs = dir.subdirs.write().await;
+        if dirs.contains_key(&rename.name) {
+            return Err(DirError::NameClash)
+        }
         let target_dir = match dirs.get(&filename) {
             Some(v) => v.clone_async().await,
             _ => return Err(DirError::NotFound(filename))
         };
         dirs.remove(&filename);
         dirs.insert(rename.name.clone(), target_dir);
+        store.write(StoreWrite::Rename(rename.path.clone(), rename.name.clone()));
         Ok(DirectoryUpdated::RenameDir(rename))
     }.boxed()).await?
 }
+
+fn check_name(path: &[String]) -> Result<(), DirError> {
+    match path.last() {
+        Some(name) if !valid_name(name) => Err(DirError::InvalidName(name.clone())),
+        _ => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::directory::DirectoryDTO;
+    use crate::utils::storage::{StoreWriter, memory::MemoryProject};
+
+    use std::sync::Arc;
+
+    fn session_with(lines: &[&str], max_bytes: usize) -> Session {
+        let mut project = DirectoryDTO::default();
+        project.files.insert("a.txt".to_owned(), lines.iter().map(|l| (*l).to_owned()).collect());
+        Session::restore(project, EditEngine::LineLock, StoreWriter::spawn(Arc::new(MemoryProject)), max_bytes)
+    }
+
+    async fn replace(session: &Session, lines: &[&str]) -> Result<Vec<String>, DirError> {
+        let lines = lines.iter().map(|l| (*l).to_owned()).collect();
+        let acts = replace_file("user", vec!["a.txt".to_owned()], lines, None, session).await?;
+        Ok(acts.into_iter().map(|act| match act {
+            SessionActivity::ServerActivity(ServerActivity::LineAdded(a)) => format!("added {} at {}", a.add_no, a.at),
+            SessionActivity::ServerActivity(ServerActivity::LineUpdated(u)) => format!("updated {} to {}", u.add_no, u.line),
+            SessionActivity::ServerActivity(ServerActivity::LineUnlocked(u)) => format!("unlocked {}", u.add_no),
+            SessionActivity::ServerActivity(ServerActivity::LinesRemoved(r)) => format!("removed {:?}", r.add_nos),
+            _ => "other".to_owned()
+        }).collect())
+    }
+
+    async fn text(session: &Session) -> String {
+        read_file(vec!["a.txt".to_owned()], session).await.ok().unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_changed_lines_are_replaced() {
+        let session = session_with(&["a", "b", "c"], usize::MAX);
+
+        let acts = replace(&session, &["a", "x", "b", "c"]).await.ok().unwrap();
+        assert_eq!(acts, vec!["added 3 at 1", "updated 3 to x", "unlocked 3"]);
+
+        let acts = replace(&session, &["a", "x", "c"]).await.ok().unwrap();
+        assert_eq!(acts, vec!["removed [1]"]);
+
+        let acts = replace(&session, &["a", "y", "c"]).await.ok().unwrap();
+        assert_eq!(acts, vec!["updated 3 to y"]);
+        assert_eq!(text(&session).await, "a\ny\nc");
+
+        assert!(replace(&session, &["a", "y", "c"]).await.ok().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replacing_is_limited_by_the_quota() {
+        // "a\nb\nc" takes 6 bytes, leaving room for 2 more.
+        let session = session_with(&["a", "b", "c"], 8);
+
+        assert!(replace(&session, &["a", "b", "c", "d"]).await.is_ok());
+        assert!(matches!(replace(&session, &["a", "bbbb", "c", "d"]).await, Err(DirError::QuotaExceeded { .. })));
+        assert_eq!(text(&session).await, "a\nb\nc\nd");
+
+        // Removing lines frees their space for the next change.
+        assert!(replace(&session, &["a", "d"]).await.is_ok());
+        assert!(replace(&session, &["a", "bbb", "d"]).await.is_ok());
+        assert!(matches!(replace(&session, &["a", "bbbb", "d"]).await, Err(DirError::QuotaExceeded { .. })));
+    }
+}
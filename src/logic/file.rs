@@ -1,22 +1,27 @@
 use crate::{
     models::{
-        user_activity::{LockLine, CreateLine},
+        user_activity::{LockLine, CreateLine, FileChanged, DeleteLine, DeleteRange},
         session_activity::{SendTo, SessionActivity},
-        session::Session, 
-        server_activity::ServerActivity, 
-        directory::{DirError, Directory}, file::{FileLine, FileLineLocked, FileLineAdded}
-    }, endpoints::user
+        session::Session,
+        server_activity::ServerActivity,
+        directory::{DirError, Directory},
+        file::{File, FileLineLocked, FileLineUnlocked, FileLineAdded, FileLineUpdated, FileLinesRemoved, FileLines},
+        quota::{ProjectQuota, line_size}
+    },
+    utils::storage::StoreWrite
 };
 
+use std::time::Duration;
+
 use futures::FutureExt;
 
 
 pub async fn lock_line(
-    user_id: &String,
-    line_lock: LockLine, 
+    user_id: &str,
+    line_lock: LockLine,
     session: &Session
 ) -> SendTo {
-    let user_id = user_id.clone();
+    let user_id = user_id.to_owned();
     let res = session.rootdir.transverse_blocking(&line_lock.filepath.clone(), 0,
         |f, d| async move { set_line_locked(f, user_id, d, line_lock).await }.boxed()).await;
 
@@ -37,16 +42,16 @@ fn handle_locked_response(res: Result<Result<FileLineLocked, DirError>, DirError
     }
 }
 
-fn wrap_dir_err(e: DirError) -> SendTo {
+pub(crate) fn wrap_dir_err(e: DirError) -> SendTo {
     let serv_act = ServerActivity::DirectoryErr(e);
     let sess_act = SessionActivity::ServerActivity(serv_act);
-    return SendTo::ToSameUser(sess_act);
+    SendTo::ToSameUser(sess_act)
 }
 
 async fn set_line_locked(
-    filename: String, 
+    filename: String,
     user_id: String,
-    dir: &Directory, 
+    dir: &Directory,
     line_lock: LockLine
 ) -> Result<FileLineLocked, DirError> {
     let files = dir.files.read().await;
@@ -55,50 +60,168 @@ async fn set_line_locked(
         None => return Err(DirError::NotFound(filename))
     };
     let lines = file.read().await;
-    let lines: Vec<&FileLine> = lines.iter().filter(|l| l.add_no == line_lock.line_no).collect();
-    let line = if lines.len() == 0 { return Err(DirError::DepthOutOfRange) }
-    else { lines[0] };
+    let line = match File::find_line(&lines, line_lock.line_no) {
+        Some(l) => l,
+        None => return Err(DirError::DepthOutOfRange)
+    };
     let mut line_data = line.line_data.write().await;
-    if let Some(_) = line_data.locked {
+    if line_data.locked.is_some() {
         return Err(DirError::LineLocked(line_lock.clone()))
     }
-    line_data.locked = Some(user_id.clone());
+    line_data.lock(&user_id);
     let res = FileLineLocked {
         add_no: line.add_no,
-        user_id: user_id
+        user_id
     };
     Ok(res)
 }
 
+/// Writes a file's current text through to the session's project store. 
+pub async fn store_file(path: &[String], session: &Session) {
+    let store = session.store.clone();
+    let filepath = path.to_vec();
+    // The file may have been removed since it was changed, leaving nothing to store.
+    let _ = session.rootdir.transverse_blocking(path, 0, |f, d| async move {
+        let files = d.files.read().await;
+        if let Some(file) = files.get(&f) {
+            let text = file.text().await;
+            store.write(StoreWrite::WriteFile(filepath.clone(), text));
+        }
+    }.boxed()).await;
+}
+
+/// Releases the lock a user holds on a line, broadcasting the unlock to the session. 
+pub async fn unlock_line(
+    user_id: &str,
+    line_lock: LockLine,
+    session: &Session
+) -> SendTo {
+    let user_id = user_id.to_owned();
+    let res = session.rootdir.transverse_blocking(&line_lock.filepath.clone(), 0,
+        |f, d| async move { set_line_unlocked(f, user_id, d, line_lock).await }.boxed()).await;
+
+    handle_unlocked_response(res)
+}
+
+fn handle_unlocked_response(res: Result<Result<FileLineUnlocked, DirError>, DirError>) -> SendTo {
+    let unlock_response = match res {
+        Ok(v) => v,
+        Err(e) => return wrap_dir_err(e)
+    };
+    match unlock_response {
+        Ok(v) => {
+            let server = ServerActivity::LineUnlocked(v).wrap_to_session();
+            SendTo::ToAllUsers(server)
+        },
+        Err(e) => wrap_dir_err(e)
+    }
+}
+
+async fn set_line_unlocked(
+    filename: String,
+    user_id: String,
+    dir: &Directory,
+    line_lock: LockLine
+) -> Result<FileLineUnlocked, DirError> {
+    let files = dir.files.read().await;
+    let file = match files.get(&filename) {
+        Some(f) => f,
+        None => return Err(DirError::NotFound(filename))
+    };
+    let lines = file.read().await;
+    let line = match File::find_line(&lines, line_lock.line_no) {
+        Some(l) => l,
+        None => return Err(DirError::DepthOutOfRange)
+    };
+    let mut line_data = line.line_data.write().await;
+    if line_data.locked.as_ref() != Some(&user_id) {
+        return Err(DirError::LineNotLocked(line_lock))
+    }
+    line_data.unlock();
+    Ok(FileLineUnlocked {
+        filepath: line_lock.filepath,
+        add_no: line.add_no,
+        user_id
+    })
+}
+
+/// Releases every lock held by a user, such as when they disconnect. 
+/// 
+/// # Returns 
+/// A `LineUnlocked` activity for each line that was released. 
+pub async fn release_user_locks(
+    user_id: &str,
+    session: &Session
+) -> Vec<SessionActivity> {
+    let released = session.rootdir.release_locks(vec![], 
+        &|line| line.locked.as_deref() == Some(user_id)).await;
+    pack_unlocked(released)
+}
+
+/// Releases every lock that has been idle for longer than `timeout`. 
+/// 
+/// # Returns 
+/// A `LineUnlocked` activity for each line that was released. 
+pub async fn expire_locks(
+    timeout: Duration,
+    session: &Session
+) -> Vec<SessionActivity> {
+    let released = session.rootdir.release_locks(vec![], 
+        &|line| line.lock_expired(timeout)).await;
+    pack_unlocked(released)
+}
+
+fn pack_unlocked(released: Vec<FileLineUnlocked>) -> Vec<SessionActivity> {
+    released.into_iter()
+        .map(|v| ServerActivity::LineUnlocked(v).wrap_to_session())
+        .collect()
+}
+
 
 pub async fn new_line(
-    user_id: &String,
-    line_create: CreateLine, 
+    user_id: &str,
+    line_create: CreateLine,
     session: &Session
 ) -> SendTo {
-    let user_id = user_id.clone();
-    let res = session.rootdir.transverse_blocking(&line_create.filepath.clone(), 0,
-        |f, d| async move { 
+    let res = add_line(user_id, line_create, session).await;
+    handle_created_response(res)
+}
+
+/// Adds an empty line to a file, locked by the user adding it. 
+/// 
+/// # Returns 
+/// * `Ok(FileLineAdded)` - The line added and the index it was inserted at. 
+/// * `Err(DirError)` - If the file couldn't be found or the quota is used up. 
+pub async fn add_line(
+    user_id: &str,
+    line_create: CreateLine,
+    session: &Session
+) -> Result<FileLineAdded, DirError> {
+    let user_id = user_id.to_owned();
+    let filepath = line_create.filepath.clone();
+    let quota = session.quota.clone();
+    let res = session.rootdir.transverse_blocking(&filepath, 0,
+        |f, d| async move {
             let files = d.files.read().await;
-            let file = match files.get(&user_id) {
+            let file = match files.get(&f) {
                 Some(v) => v,
                 None => return Err(DirError::NotFound(f))
             };
-            
-            let (new_line, _new_at) = file.insert_return_new_line(line_create.at, &user_id).await;
+            quota.grow(line_size(""))?;
+
+            let (new_line, _new_at) = file.insert_return_new_line(&line_create.filepath, line_create.at, &user_id).await;
+            let lines = file.read().await;
+            file.record_revision(&lines, &user_id).await;
             Ok(new_line)
         }.boxed()
-    ).await;
+    ).await??;
 
-    handle_created_response(res)
+    store_file(&filepath, session).await;
+    Ok(res)
 }
 
-fn handle_created_response(res: Result<Result<FileLineAdded, DirError>, DirError>) -> SendTo {
-    let lock_response = match res {
-        Ok(v) => v,
-        Err(e) => return wrap_dir_err(e)
-    };
-    match lock_response {
+fn handle_created_response(res: Result<FileLineAdded, DirError>) -> SendTo {
+    match res {
         Ok(v) => {
             let server = ServerActivity::LineAdded(v).wrap_to_session();
             SendTo::ToAllUsers(server)
@@ -107,26 +230,243 @@ fn handle_created_response(res: Result<Result<FileLineAdded, DirError>, DirError
     }
 }
 
+/// Which users may change the text of a line. 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineAccess {
+    /// Only the user holding the line's lock 
+    LockHolder,
+    /// Any user, unless another user holds the line's lock 
+    Unlocked
+}
+
+/// Replaces the text of a line the user holds the lock on, broadcasting
+/// the new text to the session.
+///
+/// # Returns
+/// * `SendTo::ToAllUsers(LineUpdated)` - If the line was updated.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the line couldn't be found or edited.
 pub async fn update_line(
-    user_id: &String,
-    line_create: CreateLine, 
+    user_id: &str,
+    change: FileChanged,
+    session: &Session
+) -> SendTo {
+    let res = edit_line(user_id, change, LineAccess::LockHolder, session).await;
+    handle_updated_response(res)
+}
+
+/// Replaces the text of a line if it still reads `change.old`. 
+/// 
+/// # Returns 
+/// * `Ok(FileLineUpdated)` - The line's new text. 
+/// * `Err(DirError::LineMismatch)` - If the line's text has changed since. 
+/// * `Err(DirError)` - If the line couldn't be found or `access` doesn't allow the edit. 
+pub async fn edit_line(
+    user_id: &str,
+    change: FileChanged,
+    access: LineAccess,
+    session: &Session
+) -> Result<FileLineUpdated, DirError> {
+    let user_id = user_id.to_owned();
+    let filepath = change.path.clone();
+    let quota = session.quota.clone();
+    let res = session.rootdir.transverse_blocking(&filepath, 0,
+        |f, d| async move { set_line_text(f, user_id, d, change, access, &quota).await }.boxed()
+    ).await??;
+
+    store_file(&filepath, session).await;
+    Ok(res)
+}
+
+fn handle_updated_response(res: Result<FileLineUpdated, DirError>) -> SendTo {
+    match res {
+        Ok(v) => {
+            let server = ServerActivity::LineUpdated(v).wrap_to_session();
+            SendTo::ToAllUsers(server)
+        },
+        Err(e) => wrap_dir_err(e)
+    }
+}
+
+async fn set_line_text(
+    filename: String,
+    user_id: String,
+    dir: &Directory,
+    change: FileChanged,
+    access: LineAccess,
+    quota: &ProjectQuota
+) -> Result<FileLineUpdated, DirError> {
+    let files = dir.files.read().await;
+    let file = match files.get(&filename) {
+        Some(f) => f,
+        None => return Err(DirError::NotFound(filename))
+    };
+    let lines = file.read().await;
+    let line = match File::find_line(&lines, change.line) {
+        Some(l) => l,
+        None => return Err(DirError::DepthOutOfRange)
+    };
+    let line_ref = LockLine {
+        filepath: change.path.clone(),
+        line_no: change.line
+    };
+    let mut line_data = line.line_data.write().await;
+    match (&line_data.locked, access) {
+        (Some(holder), _) if holder == &user_id => (),
+        (None, LineAccess::Unlocked) => (),
+        (Some(_), LineAccess::Unlocked) => return Err(DirError::LineLocked(line_ref)),
+        _ => return Err(DirError::LineNotLocked(line_ref))
+    }
+    if line_data.line != change.old {
+        return Err(DirError::LineMismatch(line_ref, line_data.line.clone()))
+    }
+    quota.resize(line_data.line.len(), change.new.len())?;
+    line_data.line = change.new.clone();
+    if line_data.locked.is_some() {
+        line_data.touch();
+    }
+    drop(line_data);
+    file.record_revision(&lines, &user_id).await;
+    Ok(FileLineUpdated {
+        filepath: change.path,
+        add_no: line.add_no,
+        user_id,
+        line: change.new
+    })
+}
+
+/// Deletes a single line, keyed by its `add_no`. 
+pub async fn delete_line(
+    user_id: &str,
+    delete: DeleteLine,
+    session: &Session
+) -> SendTo {
+    let range = DeleteRange {
+        filepath: delete.filepath,
+        from: delete.line_no,
+        to: delete.line_no
+    };
+    delete_range(user_id, range, session).await
+}
+
+/// Deletes every line between two lines inclusive, broadcasting the removed 
+/// lines to the session. 
+/// 
+/// # Returns
+/// * `SendTo::ToAllUsers(LinesRemoved)` - If the lines were removed.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If a line couldn't be found or is locked by another user.
+pub async fn delete_range(
+    user_id: &str,
+    delete: DeleteRange,
     session: &Session
 ) -> SendTo {
-    let user_id = user_id.clone();
-    let res = session.rootdir.transverse_blocking(&line_create.filepath, 0, 
+    let res = remove_range(user_id, delete, None, session).await;
+    handle_removed_response(res.map(|(removed, _)| removed))
+}
+
+/// Removes every line between two lines inclusive, if given only when they 
+/// still read `expected`. 
+/// 
+/// # Returns 
+/// * `Ok((FileLinesRemoved, index))` - The lines removed and the index of the first. 
+/// * `Err(DirError::LineMismatch)` - If a line doesn't read as expected. 
+/// * `Err(DirError)` - If a line couldn't be found or is locked by another user. 
+pub async fn remove_range(
+    user_id: &str,
+    delete: DeleteRange,
+    expected: Option<Vec<String>>,
+    session: &Session
+) -> Result<(FileLinesRemoved, usize), DirError> {
+    let user_id = user_id.to_owned();
+    let filepath = delete.filepath.clone();
+    let quota = session.quota.clone();
+    let res = session.rootdir.transverse_blocking(&filepath, 0,
+        |f, d| async move { remove_lines(f, user_id, d, delete, expected, &quota).await }.boxed()
+    ).await??;
+
+    store_file(&filepath, session).await;
+    Ok(res)
+}
+
+fn handle_removed_response(res: Result<FileLinesRemoved, DirError>) -> SendTo {
+    match res {
+        Ok(v) => {
+            let server = ServerActivity::LinesRemoved(v).wrap_to_session();
+            SendTo::ToAllUsers(server)
+        },
+        Err(e) => wrap_dir_err(e)
+    }
+}
+
+async fn remove_lines(
+    filename: String,
+    user_id: String,
+    dir: &Directory,
+    delete: DeleteRange,
+    expected: Option<Vec<String>>,
+    quota: &ProjectQuota
+) -> Result<(FileLinesRemoved, usize), DirError> {
+    let files = dir.files.read().await;
+    let file = match files.get(&filename) {
+        Some(f) => f,
+        None => return Err(DirError::NotFound(filename))
+    };
+    let mut lines = file._write().await;
+    let from = File::find_line_index(&lines, delete.from);
+    let to = File::find_line_index(&lines, delete.to);
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) if from <= to => (from, to),
+        _ => return Err(DirError::DepthOutOfRange)
+    };
+    let mut removed_size = 0;
+    for (i, line) in lines[from..=to].iter().enumerate() {
+        let line_data = line.line_data.read().await;
+        let line_ref = LockLine {
+            filepath: delete.filepath.clone(),
+            line_no: line.add_no
+        };
+        match &line_data.locked {
+            Some(holder) if holder != &user_id => return Err(DirError::LineLocked(line_ref)),
+            _ => ()
+        }
+        if let Some(expected) = &expected {
+            if expected.get(i) != Some(&line_data.line) || expected.len() != to - from + 1 {
+                return Err(DirError::LineMismatch(line_ref, line_data.line.clone()))
+            }
+        }
+        removed_size += line_size(&line_data.line);
+    }
+    quota.shrink(removed_size);
+    let add_nos: Vec<usize> = lines.drain(from..=to)
+        .map(|l| l.add_no)
+        .collect();
+    file.record_revision(&lines, &user_id).await;
+    let removed = FileLinesRemoved {
+        filepath: delete.filepath,
+        add_nos,
+        user_id
+    };
+    Ok((removed, from))
+}
+
+/// Sends every line of a file with its `add_no` to the requesting user, 
+/// so their client can address the lines. 
+pub async fn stream_out_lines(
+    path: Vec<String>,
+    session: &Session
+) -> SendTo {
+    let res = session.rootdir.transverse_blocking(&path.clone(), 0,
         |f, d| async move {
             let files = d.files.read().await;
-            let file = match files.get(&user_id) {
+            let file = match files.get(&f) {
                 Some(v) => v,
                 None => return Err(DirError::NotFound(f))
             };
-            let lines = file.read().await;
-            let lines: Vec<&FileLine> = lines.iter().filter(|l| l.add_no == line_create.at).collect();
-            
-            let (new_line, _new_at) = file.insert_return_new_line(line_create.at, &user_id).await;
-            Ok(new_line)
+            Ok(FileLines { filepath: path, lines: file.numbered_lines().await })
         }.boxed()
     ).await;
-    SendTo::ToNone
-}
 
+    match res {
+        Ok(Ok(v)) => SendTo::ToSameUser(ServerActivity::FileLines(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
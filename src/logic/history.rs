@@ -0,0 +1,76 @@
+use crate::{
+    models::{
+        user_activity::{FileHistory, FileDiff},
+        session_activity::SendTo,
+        session::Session,
+        server_activity::ServerActivity,
+        directory::DirError,
+        history::{FileRevisions, FileDiffed}
+    }
+};
+
+use super::file::wrap_dir_err;
+
+use futures::FutureExt;
+
+
+/// Sends the recent revisions of a file to the requesting user. 
+/// 
+/// # Returns
+/// * `SendTo::ToSameUser(FileHistory)` - The revisions still held, oldest first.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the file couldn't be found.
+pub async fn file_history(
+    request: FileHistory,
+    session: &Session
+) -> SendTo {
+    let res = session.rootdir.transverse_blocking(&request.path.clone(), 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let revisions = file.history.read().await.revisions();
+            Ok(FileRevisions { path: request.path, revisions })
+        }.boxed()
+    ).await;
+
+    match res {
+        Ok(Ok(v)) => SendTo::ToSameUser(ServerActivity::FileHistory(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
+
+/// Sends a unified diff of a file between two revisions to the requesting user. 
+/// 
+/// # Returns
+/// * `SendTo::ToSameUser(FileDiff)` - The diff, empty if the revisions match.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the file or either revision couldn't be found.
+pub async fn file_diff(
+    request: FileDiff,
+    session: &Session
+) -> SendTo {
+    let res = session.rootdir.transverse_blocking(&request.path.clone(), 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let diff = file.history.read().await
+                .diff(&f, request.from_rev, request.to_rev)
+                .map_err(DirError::RevisionNotFound)?;
+            Ok(FileDiffed { 
+                path: request.path, 
+                from_rev: request.from_rev, 
+                to_rev: request.to_rev, 
+                diff 
+            })
+        }.boxed()
+    ).await;
+
+    match res {
+        Ok(Ok(v)) => SendTo::ToSameUser(ServerActivity::FileDiff(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
@@ -1,4 +1,13 @@
 pub mod session;
 pub mod user;
 pub mod directory;
-pub mod file;
\ No newline at end of file
+pub mod file;
+pub mod crdt;
+pub mod ot;
+pub mod admin;
+pub mod recording;
+pub mod history;
+pub mod undo;
+pub mod auth;
+pub mod moderation;
+pub mod project;
@@ -0,0 +1,109 @@
+use crate::{
+    models::{
+        user_activity::{KickUser, BanUser, TransferOwnership},
+        session_activity::{SendTo, OutgoingActivity},
+        session::{Session, Role, Ban, UserKicked, UserBanned, OwnershipTransferred},
+        server_activity::ServerActivity,
+        directory::DirError
+    },
+    utils::storage::StoreWrite
+};
+
+use super::file::wrap_dir_err;
+use super::user as user_logic;
+
+
+/// Removes another user from the session, closing their connection. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsers(UserKicked)` - If the user was removed. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the user couldn't be found or is the sender. 
+pub async fn kick_user(
+    user_id: &str,
+    kick: KickUser,
+    session: &Session
+) -> SendTo {
+    let kicked = ServerActivity::UserKicked(UserKicked { id: kick.id.clone() });
+    if let Err(err) = remove_target(user_id, &kick.id, &kicked, session).await {
+        return wrap_dir_err(err);
+    }
+    SendTo::ToAllUsers(kicked.wrap_to_session())
+}
+
+/// Removes another user from the session, closing their connection, and 
+/// refuses them if they join again with the same id or name. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsers(UserBanned)` - If the user was banned. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the user couldn't be found or is the sender. 
+pub async fn ban_user(
+    user_id: &str,
+    ban: BanUser,
+    session: &Session
+) -> SendTo {
+    let name = match (ban.name, session.users.read().await.get(&ban.id)) {
+        (Some(name), _) => name,
+        (None, Some(user)) => user.name.clone(),
+        (None, None) => return wrap_dir_err(DirError::UserNotFound(ban.id))
+    };
+    let banned = ServerActivity::UserBanned(UserBanned { id: ban.id.clone(), name: name.clone() });
+    if let Err(err) = remove_target(user_id, &ban.id, &banned, session).await {
+        return wrap_dir_err(err);
+    }
+    session.bans.write().await.push(Ban { id: ban.id, name });
+    session.store.write(StoreWrite::WriteSettings(session.settings().await));
+    SendTo::ToAllUsers(banned.wrap_to_session())
+}
+
+/// Makes another user the owner of the session, the sender becomes an editor. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsers(OwnershipTransferred)` - If ownership was handed over. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the user couldn't be found or is the sender. 
+pub async fn transfer_ownership(
+    user_id: &str,
+    transfer: TransferOwnership,
+    session: &Session
+) -> SendTo {
+    if transfer.id == user_id {
+        return wrap_dir_err(DirError::NotPermitted);
+    }
+    let mut users = session.users.write().await;
+    match users.get_mut(&transfer.id) {
+        Some(user) => user.role = Role::Owner,
+        None => return wrap_dir_err(DirError::UserNotFound(transfer.id))
+    };
+    if let Some(user) = users.get_mut(user_id) {
+        user.role = Role::Editor;
+    }
+    drop(users);
+    let transferred = OwnershipTransferred { from: user_id.to_owned(), to: transfer.id };
+    SendTo::ToAllUsers(ServerActivity::OwnershipTransferred(transferred).wrap_to_session())
+}
+
+/// Sends a user the reason they're being removed, closes their connection 
+/// and removes them from the session, including if they'd dropped out. 
+async fn remove_target(
+    user_id: &str,
+    target_id: &str,
+    reason: &ServerActivity,
+    session: &Session
+) -> Result<(), DirError> {
+    // Removing themselves could leave the session without an owner.
+    if target_id == user_id {
+        return Err(DirError::NotPermitted);
+    }
+    let users = session.users.read().await;
+    let target = match users.get(target_id) {
+        Some(v) => v,
+        None => return Err(DirError::UserNotFound(target_id.to_owned()))
+    };
+    if target.sender.send(OutgoingActivity::closing(reason.clone().wrap_to_session())).is_err() {
+        // User has disconected, user logout code will run 
+    }
+    drop(users);
+    session.departed.write().await.remove(target_id);
+    user_logic::remove_user(target_id, session).await;
+    session.undo.write().await.remove(target_id);
+    Ok(())
+}
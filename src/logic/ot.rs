@@ -0,0 +1,84 @@
+use crate::{
+    models::{
+        user_activity::TextOperation,
+        session_activity::SendTo,
+        session::Session,
+        server_activity::ServerActivity,
+        directory::DirError,
+        ot::{TextOperationAck, TextOperationApplied, FileOtState}
+    }
+};
+
+use super::file::wrap_dir_err;
+use super::file as file_logic;
+
+use futures::FutureExt;
+
+
+/// Transforms and commits a user's text operation, acknowledging the new 
+/// revision to the author and broadcasting the transformed operation 
+/// to the other users. 
+/// 
+/// # Returns
+/// * `SendTo::ToSplit(OtAck, OtApplied)` - If the operation was committed.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the file couldn't be found or the operation couldn't be applied.
+pub async fn apply_operation(
+    user_id: &str,
+    operation: TextOperation,
+    session: &Session
+) -> SendTo {
+    let user_id = user_id.to_owned();
+    let filepath = operation.path.clone();
+    let quota = session.quota.clone();
+    let res = session.rootdir.transverse_blocking(&filepath, 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let (revision, ops) = file.apply_text_operation(operation.base_revision, operation.ops, &quota).await?;
+            let lines = file.read().await;
+            file.record_revision(&lines, &user_id).await;
+            drop(lines);
+            Ok(TextOperationApplied { filepath: operation.path, user_id, revision, ops })
+        }.boxed()
+    ).await;
+
+    if let Ok(Ok(_)) = res {
+        file_logic::store_file(&filepath, session).await;
+    }
+    match res {
+        Ok(Ok(v)) => {
+            let ack = TextOperationAck { filepath: v.filepath.clone(), revision: v.revision };
+            SendTo::ToSplit(
+                ServerActivity::OtAck(ack).wrap_to_session(),
+                ServerActivity::OtApplied(v).wrap_to_session()
+            )
+        },
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
+
+/// Sends the current revision and text of a file to the requesting user. 
+pub async fn stream_out_state(
+    path: Vec<String>,
+    session: &Session
+) -> SendTo {
+    let res = session.rootdir.transverse_blocking(&path.clone(), 0,
+        |f, d| async move {
+            let files = d.files.read().await;
+            let file = match files.get(&f) {
+                Some(v) => v,
+                None => return Err(DirError::NotFound(f))
+            };
+            let (revision, text) = file.ot_state().await;
+            Ok(FileOtState { filepath: path, revision, text })
+        }.boxed()
+    ).await;
+
+    match res {
+        Ok(Ok(v)) => SendTo::ToSameUser(ServerActivity::OtState(v).wrap_to_session()),
+        Ok(Err(e)) | Err(e) => wrap_dir_err(e)
+    }
+}
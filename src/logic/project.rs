@@ -0,0 +1,234 @@
+use crate::{
+    models::{
+        session::{SessionStore, Session, SessionCredentials},
+        server_activity::ServerActivity,
+        session_activity::{SendTo, SessionActivity},
+        user_activity::{UserActivity, WriteFile, WriteMode},
+        directory::{DirError, DirectoryUpdated, DirectoryTree},
+        auth::Identity,
+        errors::CodealongError
+    },
+    utils::settings::AppSettings
+};
+
+use super::user as user_logic;
+use super::directory as dir_logic;
+
+use percent_encoding::percent_decode_str;
+
+use warp::hyper::body::Bytes;
+
+
+/// The user id file changes made through the API are recorded against 
+/// when the request isn't authenticated. 
+const API_USER_ID: &str = "api";
+
+/// Lists the files and directories of a session's project. 
+pub async fn project_tree(
+    session_id: String,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<DirectoryTree, CodealongError> {
+    user_logic::check_access(&session_id, &sessions_str, &creds, identity.as_ref(), &settings, false).await?;
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(&session_id).ok_or(CodealongError::NotFound)?;
+    Ok(session.rootdir.spool_tree().await)
+}
+
+/// Reads the text of a file in a session's project. 
+pub async fn read_file(
+    session_id: String,
+    path: String,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<String, CodealongError> {
+    let path = parse_path(&path)?;
+    user_logic::check_access(&session_id, &sessions_str, &creds, identity.as_ref(), &settings, false).await?;
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(&session_id).ok_or(CodealongError::NotFound)?;
+    dir_logic::read_file(path, session).await.map_err(dir_error)
+}
+
+/// Creates a file in a session's project holding the body's text, 
+/// broadcasting it to the session's users. 
+pub async fn create_file(
+    session_id: String,
+    path: String,
+    body: Bytes,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<(), CodealongError> {
+    let access = FileAccess { session_id, path, creds, identity };
+    write_file(access, WriteMode::Create, body, settings, sessions_str).await
+}
+
+/// Replaces the text of a file in a session's project with the body's, 
+/// broadcasting it to the session's users. 
+pub async fn overwrite_file(
+    session_id: String,
+    path: String,
+    body: Bytes,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<(), CodealongError> {
+    let access = FileAccess { session_id, path, creds, identity };
+    write_file(access, WriteMode::Overwrite, body, settings, sessions_str).await
+}
+
+/// Deletes a file from a session's project, broadcasting the change to 
+/// the session's users. 
+pub async fn delete_file(
+    session_id: String,
+    path: String,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<(), CodealongError> {
+    let path = parse_path(&path)?;
+    user_logic::check_access(&session_id, &sessions_str, &creds, identity.as_ref(), &settings, true).await?;
+    let user_id = api_user_id(identity.as_ref());
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(&session_id).ok_or(CodealongError::NotFound)?;
+    let erase = UserActivity::DirUpdated(DirectoryUpdated::ErasedFile(path));
+    apply_edit(&user_id, &session_id, erase, &settings, session).await
+}
+
+/// Who is asking to write which file. 
+struct FileAccess {
+    session_id: String,
+    path: String,
+    creds: SessionCredentials,
+    identity: Option<Identity>
+}
+
+async fn write_file(
+    access: FileAccess,
+    mode: WriteMode,
+    body: Bytes,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<(), CodealongError> {
+    let path = parse_path(&access.path)?;
+    let text = match String::from_utf8(body.to_vec()) {
+        Ok(v) => v,
+        Err(_) => return Err(CodealongError::BadRequest)
+    };
+    let lines = text.split('\n').map(str::to_owned).collect();
+    user_logic::check_access(
+        &access.session_id, 
+        &sessions_str, 
+        &access.creds, 
+        access.identity.as_ref(), 
+        &settings, 
+        true
+    ).await?;
+    let user_id = api_user_id(access.identity.as_ref());
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(&access.session_id).ok_or(CodealongError::NotFound)?;
+    let write = WriteFile { path, lines, mode };
+    apply_edit(&user_id, &access.session_id, UserActivity::WriteFile(write), &settings, session).await
+}
+
+/// Applies a change made through the API as if the user had sent it, so 
+/// it's recorded and can be undone, then broadcasts it. 
+async fn apply_edit(
+    user_id: &str,
+    session_id: &str,
+    msg: UserActivity,
+    settings: &AppSettings,
+    session: &Session
+) -> Result<(), CodealongError> {
+    match user_logic::record_activity(user_id, session_id, msg, settings, session).await {
+        SendTo::ToSameUser(SessionActivity::ServerActivity(ServerActivity::DirectoryErr(e))) =>
+            return Err(dir_error(e)),
+        // The change wasn't sent over a connection, so every user is sent it.
+        SendTo::ToOtherUsers(act) => user_logic::send_all_users(&act, session).await,
+        res => user_logic::send_response(user_id, &res, session).await
+    }
+    Ok(())
+}
+
+/// The user id changes made through the API are recorded against. 
+fn api_user_id(identity: Option<&Identity>) -> String {
+    identity.map(|i| i.id.clone()).unwrap_or_else(|| API_USER_ID.to_owned())
+}
+
+/// Splits a file path from a URL into its percent decoded names. 
+fn parse_path(path: &str) -> Result<Vec<String>, CodealongError> {
+    path.split('/')
+        .map(|name| match percent_decode_str(name).decode_utf8() {
+            Ok(name) if !name.is_empty() => Ok(name.into_owned()),
+            _ => Err(CodealongError::BadRequest)
+        })
+        .collect()
+}
+
+fn dir_error(err: DirError) -> CodealongError {
+    match err {
+        DirError::NotFound(_) | DirError::DepthOutOfRange => CodealongError::NotFound,
+        DirError::NameClash | DirError::Locked(_) | DirError::LineLocked(_) => CodealongError::Conflict,
+        DirError::QuotaExceeded { .. } => CodealongError::TooLarge,
+        DirError::ReadOnly | DirError::NotPermitted => CodealongError::Forbidden,
+        _ => CodealongError::BadRequest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::undo as undo_logic;
+    use crate::models::{directory::DirectoryDTO, recording::RecordedEvent, session::EditEngine};
+    use crate::utils::recording::{Recorder, load_recording};
+    use crate::utils::storage::{StoreWriter, memory::MemoryProject};
+
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn api_writes_are_recorded_and_undoable() {
+        let dir = std::env::temp_dir().join(format!("codealong-rec-{}", uuid::Uuid::new_v4()));
+        let dir = dir.to_str().unwrap().to_owned();
+        let mut project = DirectoryDTO::default();
+        project.files.insert("a.txt".to_owned(), vec!["one".to_owned()]);
+        let mut session = Session::restore(project, EditEngine::LineLock, StoreWriter::spawn(Arc::new(MemoryProject)), usize::MAX);
+        session.recorder = Some(Recorder::open(&dir, "s1").unwrap());
+        let sessions: SessionStore = Arc::new(RwLock::new(HashMap::from([("s1".to_owned(), session)])));
+
+        let body = Bytes::from_static(b"one\ntwo");
+        overwrite_file("s1".to_owned(), "a.txt".to_owned(), body, SessionCredentials::default(), None, AppSettings::new(), sessions.clone())
+            .await.unwrap();
+
+        let mut recorded = None;
+        for _ in 0..50 {
+            let entries = load_recording(&dir, "s1").unwrap().unwrap_or_default();
+            recorded = entries.into_iter().find_map(|e| match e.event {
+                RecordedEvent::Activity { user_id, activity: UserActivity::WriteFile(write) } => Some((user_id, write)),
+                _ => None
+            });
+            if recorded.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (user_id, write) = recorded.expect("the write wasn't recorded");
+        assert_eq!(user_id, API_USER_ID);
+        assert_eq!(write.path, vec!["a.txt".to_owned()]);
+        assert_eq!(write.lines, vec!["one".to_owned(), "two".to_owned()]);
+
+        let sessions = sessions.read().await;
+        let session = &sessions["s1"];
+        undo_logic::undo(API_USER_ID, session).await;
+        assert_eq!(dir_logic::read_file(vec!["a.txt".to_owned()], session).await.ok(), Some("one".to_owned()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    models::{
+        session::{SessionStore, Session, SessionCredentials},
+        auth::Identity,
+        recording::{RecordEntry, RecordedEvent, PlaybackQuery, PlaybackControl, PlaybackState},
+        server_activity::ServerActivity,
+        user_activity::UserActivity,
+        directory::DirError,
+        errors::CodealongError,
+        response::NewSession,
+        session_activity::SendTo,
+        lifecycle::{SessionEvents, SessionEvent}
+    },
+    utils::settings::AppSettings,
+    utils::storage::{StoreWriter, memory::MemoryProject},
+    utils::recording::{self, Recorder}
+};
+use super::user as user_logic;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time::Instant;
+
+use uuid::Uuid;
+
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 16.0;
+
+/// Starts recording a session if `AppSettings::recordings_dir` is set, 
+/// beginning with its current project. A session already recorded, such as 
+/// one being restored, carries on from the end of its recording. 
+pub async fn start_recording(
+    settings: &AppSettings,
+    session_id: &str,
+    session: &mut Session
+) {
+    let dir = match &settings.recordings_dir {
+        Some(v) => v,
+        None => return
+    };
+    let recorder = match Recorder::open(dir, session_id) {
+        Ok(v) => v,
+        Err(e) => return log::error!("failed to start recording session {}: {}", session_id, e)
+    };
+    let project = session.rootdir.spool_to_dto().await;
+    let start = RecordedEvent::Start {
+        engine: session.engine,
+        project,
+        join_policy: Some(session.join_policy.clone())
+    };
+    session.recorder = Some(recorder);
+    record(session_id, session, start);
+}
+
+/// Records a user activity once it's been applied, activities which were 
+/// rejected or leave the project unchanged are skipped. 
+pub fn record_activity(
+    user_id: &str,
+    session_id: &str,
+    activity: UserActivity,
+    res: &SendTo,
+    session: &Session
+) {
+    if session.recorder.is_none() || activity.is_read_only() {
+        return;
+    }
+    if let SendTo::ToSameUser(_) = res {
+        return;
+    }
+    let event = RecordedEvent::Activity { user_id: user_id.to_owned(), activity };
+    record(session_id, session, event);
+}
+
+fn record(session_id: &str, session: &Session, event: RecordedEvent) {
+    let recorder = match &session.recorder {
+        Some(v) => v,
+        None => return
+    };
+    if let Err(e) = recorder.record(event) {
+        log::error!("failed to record session {}: {}", session_id, e);
+    }
+}
+
+/// Creates a read-only session which plays back the recording of another, 
+/// users join it by its id to watch and steer it with `PlaybackControl`. 
+/// The request must be able to read the recorded session, which the 
+/// playback session takes its join policy from. 
+pub async fn start_playback(
+    recorded_id: String,
+    query: PlaybackQuery,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore,
+    events: SessionEvents
+) -> Result<NewSession, CodealongError> {
+    // A session still running is checked as it is now rather than as recorded.
+    let live = sessions_str.read().await.get(&recorded_id)
+        .map(|session| (session.join_policy.clone(), session.metadata.title.clone()));
+    let (live_policy, title) = match live {
+        Some((policy, title)) => {
+            user_logic::check_access(&recorded_id, &sessions_str, &creds, identity.as_ref(), &settings, false).await?;
+            (Some(policy), title)
+        },
+        None => (None, None)
+    };
+    let dir = match settings.recordings_dir.clone() {
+        Some(v) => v,
+        None => return Err(CodealongError::NotFound)
+    };
+    if sessions_str.read().await.len() >= settings.max_sessions {
+        return Err(CodealongError::MaxCapacity)
+    }
+
+    let id = recorded_id.clone();
+    let entries = tokio::task::spawn_blocking(move || recording::load_recording(&dir, &id)).await;
+    let mut entries = match entries {
+        Ok(Ok(Some(v))) => v,
+        Ok(Ok(None)) => return Err(CodealongError::NotFound),
+        Ok(Err(e)) => {
+            log::error!("failed to load recording of session {}: {}", recorded_id, e);
+            return Err(CodealongError::InternalServerError)
+        },
+        Err(e) => {
+            log::error!("failed to load recording of session {}: {}", recorded_id, e);
+            return Err(CodealongError::InternalServerError)
+        }
+    };
+    let first = entries.iter().position(|e| matches!(e.event, RecordedEvent::Start { .. }));
+    match first {
+        Some(v) => drop(entries.drain(..v)),
+        None => return Err(CodealongError::NotFound)
+    };
+    let (engine, project, recorded_policy) = match &entries[0].event {
+        RecordedEvent::Start { engine, project, join_policy } => (*engine, project.clone(), join_policy.clone()),
+        RecordedEvent::Activity { .. } => return Err(CodealongError::NotFound)
+    };
+    let join_policy = match (live_policy, recorded_policy) {
+        (Some(policy), _) => policy,
+        (None, Some(policy)) => {
+            user_logic::check_credentials(&settings.token_secret, &recorded_id, &policy, &creds).await?;
+            policy
+        },
+        // Recorded without its policy, the session may have been protected.
+        (None, None) => return Err(CodealongError::Forbidden)
+    };
+
+    let (tx, rx) = mpsc::unbounded_channel::<PlaybackControl>();
+    // The recording already kept within its own quota.
+    let mut session = Session::restore(project, engine, StoreWriter::spawn(Arc::new(MemoryProject)), usize::MAX);
+    session.playback = Some(tx);
+    session.join_policy = join_policy;
+    // The recorded session's id would let anyone listing the playback reach it.
+    session.metadata.title = Some(match title {
+        Some(title) => format!("Playback of {}", title),
+        None => "Playback".to_owned()
+    });
+
+    let mut sessions = sessions_str.write().await;
+    if sessions.len() >= settings.max_sessions {
+        return Err(CodealongError::MaxCapacity)
+    }
+    let session_id = Uuid::new_v4().to_string();
+    sessions.insert(session_id.clone(), session);
+    drop(sessions);
+    events.emit(SessionEvent::Created { session_id: session_id.clone() });
+
+    let player = Player {
+        session_id: session_id.clone(),
+        start_ms: entries[0].at_ms,
+        entries,
+        next: 1,
+        position_ms: 0,
+        speed: clamp_speed(query.speed),
+        paused: query.paused,
+        settings
+    };
+    tokio::task::spawn(run_playback(player, sessions_str, rx));
+    Ok(NewSession { session_id })
+}
+
+/// Passes a viewer's control on to the task playing back their session. 
+pub fn control_playback(
+    control: PlaybackControl,
+    session: &Session
+) -> SendTo {
+    match &session.playback {
+        Some(tx) => {
+            if tx.send(control).is_err() {
+                // The playback task has stopped, the session is closing
+            }
+            SendTo::ToNone
+        },
+        None => {
+            let err = ServerActivity::DirectoryErr(DirError::NotPlayback);
+            SendTo::ToSameUser(err.wrap_to_session())
+        }
+    }
+}
+
+fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_finite() {
+        speed.clamp(MIN_SPEED, MAX_SPEED)
+    } else {
+        1.0
+    }
+}
+
+/// Plays the entries of a recording into a session in time, stopping once 
+/// the session is closed. 
+async fn run_playback(
+    mut player: Player,
+    sessions_str: SessionStore,
+    mut controls: UnboundedReceiver<PlaybackControl>
+) {
+    loop {
+        let wait = player.until_next();
+        let started = Instant::now();
+        let playing = tokio::select! {
+            control = controls.recv() => match control {
+                Some(control) => {
+                    player.advance(started.elapsed());
+                    player.control(control, &sessions_str).await
+                },
+                // The session was closed, dropping its sender
+                None => false
+            },
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() =>
+                player.play_next(&sessions_str).await
+        };
+        if !playing {
+            break;
+        }
+    }
+}
+
+struct Player {
+    session_id: String,
+    entries: Vec<RecordEntry>,
+    /// When the first entry was recorded, positions are relative to it 
+    start_ms: u64,
+    /// The index of the next entry to be played 
+    next: usize,
+    position_ms: u64,
+    speed: f64,
+    paused: bool,
+    settings: AppSettings
+}
+
+impl Player {
+    fn offset(&self, index: usize) -> u64 {
+        self.entries[index].at_ms.saturating_sub(self.start_ms)
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.offset(self.entries.len() - 1)
+    }
+
+    fn finished(&self) -> bool {
+        self.next >= self.entries.len()
+    }
+
+    fn state(&self) -> PlaybackState {
+        PlaybackState {
+            position_ms: self.position_ms,
+            duration_ms: self.duration_ms(),
+            speed: self.speed,
+            paused: self.paused
+        }
+    }
+
+    /// How long until the next entry is due, `None` if nothing is due. 
+    fn until_next(&self) -> Option<Duration> {
+        if self.paused || self.finished() {
+            return None;
+        }
+        let gap = self.offset(self.next).saturating_sub(self.position_ms);
+        Some(Duration::from_millis((gap as f64 / self.speed) as u64))
+    }
+
+    /// Moves the position on by time spent playing. 
+    fn advance(&mut self, elapsed: Duration) {
+        if self.paused || self.finished() {
+            return;
+        }
+        let played = (elapsed.as_millis() as f64 * self.speed) as u64;
+        self.position_ms = (self.position_ms + played).min(self.offset(self.next));
+    }
+
+    /// Plays the next entry to the session's users, returning false if the 
+    /// session has closed. 
+    async fn play_next(&mut self, sessions_str: &SessionStore) -> bool {
+        let sessions = sessions_str.read().await;
+        let session = match sessions.get(&self.session_id) {
+            Some(v) => v,
+            None => return false
+        };
+        self.position_ms = self.offset(self.next);
+        apply_entry(&self.entries[self.next], &self.session_id, &self.settings, session, true).await;
+        self.next += 1;
+        if self.finished() {
+            send_state(self.state(), session).await;
+        }
+        true
+    }
+
+    /// Applies a viewer's control and tells every viewer the new state, 
+    /// returning false if the session has closed. 
+    async fn control(&mut self, control: PlaybackControl, sessions_str: &SessionStore) -> bool {
+        let sessions = sessions_str.read().await;
+        let session = match sessions.get(&self.session_id) {
+            Some(v) => v,
+            None => return false
+        };
+        if let Some(speed) = control.speed {
+            self.speed = clamp_speed(speed);
+        }
+        if let Some(paused) = control.paused {
+            self.paused = paused;
+        }
+        if let Some(seek_ms) = control.seek_ms {
+            self.seek(seek_ms, session).await;
+        }
+        send_state(self.state(), session).await;
+        true
+    }
+
+    /// Rebuilds the project as it was at `target_ms`, from the last time the 
+    /// recording started before it, and sends it to every viewer. 
+    async fn seek(&mut self, target_ms: u64, session: &Session) {
+        let target_ms = target_ms.min(self.duration_ms());
+        let next = (0..self.entries.len())
+            .find(|i| self.offset(*i) > target_ms)
+            .unwrap_or(self.entries.len())
+            .max(1);
+        let base = (0..next).rev()
+            .find(|i| matches!(self.entries[*i].event, RecordedEvent::Start { .. }))
+            .unwrap_or(0);
+        for entry in self.entries[base..next].iter() {
+            apply_entry(entry, &self.session_id, &self.settings, session, false).await;
+        }
+        self.next = next;
+        self.position_ms = target_ms;
+
+        let project = session.rootdir.spool_to_dto().await;
+        let current = ServerActivity::CurrentProject(project);
+        user_logic::send_all_users(&current.wrap_to_session(), session).await;
+    }
+}
+
+/// Applies an entry of a recording to a session as its recorded user, 
+/// sending the result on to the viewers if `broadcast` is set. 
+async fn apply_entry(
+    entry: &RecordEntry,
+    session_id: &str,
+    settings: &AppSettings,
+    session: &Session,
+    broadcast: bool
+) {
+    match &entry.event {
+        RecordedEvent::Start { project, .. } => {
+            session.rootdir.replace_with_dto(project.clone()).await;
+            // Recorded undos after this only apply to changes made since.
+            session.undo.write().await.clear();
+            if broadcast {
+                let current = ServerActivity::CurrentProject(project.clone());
+                user_logic::send_all_users(&current.wrap_to_session(), session).await;
+            }
+        },
+        RecordedEvent::Activity { user_id, activity } => {
+            let res = user_logic::dispatch_activity(user_id, session_id, activity.clone(), settings, session).await;
+            if broadcast {
+                // The recorded user isn't in the session, so only what the
+                // other users were sent reaches the viewers.
+                user_logic::send_response(user_id, &res, session).await;
+            }
+        }
+    }
+}
+
+async fn send_state(state: PlaybackState, session: &Session) {
+    let state = ServerActivity::PlaybackState(state);
+    user_logic::send_all_users(&state.wrap_to_session(), session).await;
+}
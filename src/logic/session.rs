@@ -1,14 +1,30 @@
 use crate::{
     utils::settings::AppSettings,
+    utils::persistence::SessionPersistence,
+    utils::storage::{Storage, StoreWriter, StoreWrite},
+    utils::archive::{self, ImportLimits, ImportError},
+    utils::templates,
+    utils::tokens,
     models::errors::CodealongError,
+    models::auth::Identity,
+    models::metadata::{SessionMetadata, SessionListQuery, SessionListing, SessionPage, MAX_PAGE_SIZE},
     models::{
-        session::{SessionStore, Session},
-        session_activity::SessionActivity,
+        session::{SessionStore, Session, NewSessionQuery, ImportQuery, ExportQuery, JoinPolicy, SessionCredentials},
+        directory::{Directory, DirectoryDTO},
+        quota::ProjectQuota,
+        lifecycle::{SessionEvents, SessionEvent},
+        session_activity::{SessionActivity, OutgoingActivity},
+        user_activity::SyncSince,
         server_activity::ServerActivity
     },
-    models::{response::Count, session_activity::SendTo}
+    models::{response::{Count, NewSession}, session_activity::SendTo}
 };
 use super::user as user_logic;
+use super::file as file_logic;
+use super::recording as recording_logic;
+
+use std::sync::Arc;
+use std::time::Duration;
 
 use futures::future::join_all;
 
@@ -16,6 +32,8 @@ use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::mpsc;
 
 use warp::Reply;
+use warp::reply;
+use warp::hyper::body::Bytes;
 
 use uuid::Uuid;
 
@@ -26,16 +44,78 @@ pub async fn sessions_capacity(
 ) -> Count {
     let max_sessions = settings.max_sessions;
 
-    let active_sessions = available_active_sessions(settings, state).await;
+    let active_sessions = sessions_with_room(None, settings, state).await;
     let sessions: usize = active_sessions.len();
 
     if max_sessions > sessions {
         return Count::new(max_sessions - sessions);
     }
-    return Count::new(0);
+    Count::new(0)
 }
 
+/// Lists the sessions with room for another user, protected sessions are 
+/// only listed for an invite to them. 
 pub async fn available_active_sessions(
+    creds: &SessionCredentials,
+    settings: AppSettings, 
+    state: SessionStore
+) -> Vec<String> {
+    sessions_with_room(Some(creds), settings, state).await
+}
+
+/// Lists the sessions matching a query with their metadata, newest first, 
+/// protected sessions are only listed for an invite to them. Passwords 
+/// aren't checked, hashing one for every session would stall the listing. 
+pub async fn list_sessions(
+    query: SessionListQuery,
+    creds: &SessionCredentials,
+    settings: AppSettings,
+    state: SessionStore
+) -> SessionPage {
+    let max_sess_users = settings.max_sess_users;
+    let per_page = query.per_page.clamp(1, MAX_PAGE_SIZE);
+    let tag = query.tag.map(|t| t.trim().to_lowercase());
+    let sessions = state.read().await;
+
+    let listings_fut = sessions.iter().map(|(key, value)| async {
+        if value.join_policy.is_protected() && 
+            user_logic::invite_role(&settings.token_secret, key, creds).is_none() {
+            return None;
+        }
+        if tag.as_ref().is_some_and(|t| !value.metadata.tags.contains(t)) {
+            return None;
+        }
+        let participants = value.users.read().await.len();
+        if max_sess_users.saturating_sub(participants) < query.free_seats {
+            return None;
+        }
+        Some(SessionListing {
+            session_id: key.clone(),
+            metadata: value.metadata.clone(),
+            participants,
+            max_participants: max_sess_users,
+            protected: value.join_policy.is_protected()
+        })
+    });
+    let mut listings: Vec<SessionListing> = join_all(listings_fut).await
+        .into_iter()
+        .flatten()
+        .collect();
+    listings.sort_by(|a, b| b.metadata.created_at.cmp(&a.metadata.created_at)
+        .then_with(|| a.session_id.cmp(&b.session_id)));
+
+    let total = listings.len();
+    let sessions = listings.into_iter()
+        .skip(query.page.saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+    SessionPage { sessions, page: query.page, per_page, total }
+}
+
+/// Lists the sessions with room for another user, hiding the protected 
+/// sessions the credentials hold no invite to if any are given. 
+async fn sessions_with_room(
+    creds: Option<&SessionCredentials>,
     settings: AppSettings, 
     state: SessionStore
 ) -> Vec<String> {
@@ -43,6 +123,12 @@ pub async fn available_active_sessions(
     let session = state.read().await;
 
     let mapped_sessions_fut = session.iter().map(|(key, value)| async {
+        if let Some(creds) = creds {
+            if value.join_policy.is_protected() && 
+                user_logic::invite_role(&settings.token_secret, key, creds).is_none() {
+                return None;
+            }
+        }
         if value.users.read().await.len() < max_sess_users {
             return Some(key.clone());
         }
@@ -54,53 +140,537 @@ pub async fn available_active_sessions(
 }
 
 pub async fn make_new_session(
-    user_name: String,
+    identity: Identity,
+    query: NewSessionQuery,
     ws: warp::ws::Ws, 
     settings: AppSettings, 
-    sessions_str: SessionStore
+    sessions_str: SessionStore,
+    storage: Storage,
+    events: SessionEvents
 ) -> Result<impl Reply, CodealongError> {
-    let (tx, rx) = mpsc::unbounded_channel::<SessionActivity>();
+    let rootdir = match &query.template {
+        Some(name) => Directory::from_dto(read_template(&settings, name).await?),
+        None => Directory::new_with_file()
+    };
+    let (tx, rx) = mpsc::unbounded_channel::<OutgoingActivity>();
 
-    let (session_id, user_id) = match check_add_session(settings.max_sessions, 
-        user_name, 
+    let (session_id, user_id) = match check_add_session(&settings, 
+        identity, 
+        query,
+        rootdir,
         &sessions_str, 
+        &storage,
         tx
     ).await {
         Ok(v) => v,
         Err(e) => return Err(e)
     };
+    events.emit(SessionEvent::Created { session_id: session_id.clone() });
 
     let res_future = ws.on_upgrade(move |socket| 
-        user_logic::user_thread(user_id, session_id, socket, sessions_str, rx)
+        user_logic::user_thread(user_id, session_id, socket, sessions_str, rx, settings, events)
     );
 
     Ok(res_future)
 }
 
 async fn check_add_session(
-    max_sessions: usize,
-    user_name: String,
+    settings: &AppSettings,
+    identity: Identity,
+    query: NewSessionQuery,
+    rootdir: Directory,
     sessions_str: &SessionStore,
-    tx: UnboundedSender<SessionActivity>
+    storage: &Storage,
+    tx: UnboundedSender<OutgoingActivity>
 ) -> Result<(String, String), CodealongError> {
+    // Templates over the project limit are refused, the same as imports.
+    let used = rootdir.byte_size().await;
+    if used > settings.max_proj_bytes() {
+        return Err(CodealongError::TooLarge)
+    }
+    // Hashing the password is slow, so it's done before taking the lock.
+    let policy = join_policy(query.password.as_deref(), query.invite_only);
     let mut sessions = sessions_str.write().await;
 
-    if sessions.len() >= max_sessions {
+    if sessions.len() >= settings.max_sessions {
         return Err(CodealongError::MaxCapacity)
     }
 
     let session_id = Uuid::new_v4().to_string();
-    let user_id = Uuid::new_v4().to_string();
-    let session = Session::new(user_name, user_id.clone(), tx);
+    let user_id = identity.id;
+    let store = open_session_store(storage, &session_id)?;
+    let quota = ProjectQuota::new(used, settings.max_proj_bytes());
+    let metadata = SessionMetadata::new(Some(identity.name.clone()), query.title, query.description, query.tags.as_deref());
+    let mut session = Session::new(identity.name, user_id.clone(), tx, rootdir, query.engine, store, quota);
+    session.join_policy = policy;
+    session.metadata = metadata;
+    // Stored first, a project found without its settings isn't restored.
+    session.store.write(StoreWrite::WriteSettings(session.settings().await));
+    let project = session.rootdir.spool_to_dto().await;
+    session.store.write(StoreWrite::WriteProject(project));
+    recording_logic::start_recording(settings, &session_id, &mut session).await;
     sessions.insert(session_id.clone(), session);
     Ok((session_id, user_id))
 }
 
+/// Builds the join policy a session was asked to be created with. 
+fn join_policy(password: Option<&str>, invite_only: bool) -> JoinPolicy {
+    match password {
+        _ if invite_only => JoinPolicy::InviteOnly,
+        Some(password) => JoinPolicy::Password(tokens::password_hash(password)),
+        None => JoinPolicy::Open
+    }
+}
+
+/// Lists the names of the templates new sessions can be started from. 
+pub async fn available_templates(settings: AppSettings) -> Vec<String> {
+    let dir = settings.templates_dir;
+    match tokio::task::spawn_blocking(move || templates::list_templates(&dir)).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => {
+            log::error!("failed to list templates: {}", e);
+            vec![]
+        },
+        Err(e) => {
+            log::error!("failed to list templates: {}", e);
+            vec![]
+        }
+    }
+}
+
+async fn read_template(settings: &AppSettings, name: &str) -> Result<DirectoryDTO, CodealongError> {
+    let dir = settings.templates_dir.clone();
+    let template = name.to_owned();
+    let project = tokio::task::spawn_blocking(move || templates::load_template(&dir, &template)).await;
+    match project {
+        Ok(Ok(Some(v))) => Ok(v),
+        Ok(Ok(None)) => Err(CodealongError::NotFound),
+        Ok(Err(e)) => {
+            log::error!("failed to read template {}: {}", name, e);
+            Err(CodealongError::InternalServerError)
+        },
+        Err(e) => {
+            log::error!("failed to read template {}: {}", name, e);
+            Err(CodealongError::InternalServerError)
+        }
+    }
+}
+
+fn open_session_store(
+    storage: &Storage,
+    session_id: &str
+) -> Result<StoreWriter, CodealongError> {
+    storage.open_project(session_id).map(StoreWriter::spawn).map_err(|e| {
+        log::error!("failed to open project store for session {}: {}", session_id, e);
+        CodealongError::InternalServerError
+    })
+}
+
+/// Creates a new session from an uploaded archive, unpacking it into the 
+/// session's project. Users then join the session by its id. 
+pub async fn import_session(
+    creator: Option<Identity>,
+    query: ImportQuery,
+    body: Bytes,
+    settings: AppSettings,
+    sessions_str: SessionStore,
+    storage: Storage,
+    events: SessionEvents
+) -> Result<NewSession, CodealongError> {
+    if sessions_str.read().await.len() >= settings.max_sessions {
+        return Err(CodealongError::MaxCapacity)
+    }
+
+    let limits = ImportLimits {
+        max_bytes: settings.max_proj_bytes(),
+        max_files: settings.max_import_files
+    };
+    let format = query.format;
+    let project = tokio::task::spawn_blocking(move || archive::read_archive(&body, format, &limits)).await;
+    let project = match project {
+        Ok(Ok(v)) => v,
+        Ok(Err(ImportError::TooLarge | ImportError::TooManyFiles)) => return Err(CodealongError::TooLarge),
+        Ok(Err(e)) => {
+            log::info!("rejected imported archive: {}", e);
+            return Err(CodealongError::InvalidArchive)
+        },
+        Err(e) => {
+            log::error!("failed to unpack imported archive: {}", e);
+            return Err(CodealongError::InternalServerError)
+        }
+    };
+
+    let policy = join_policy(query.password.as_deref(), query.invite_only);
+    let mut sessions = sessions_str.write().await;
+    if sessions.len() >= settings.max_sessions {
+        return Err(CodealongError::MaxCapacity)
+    }
+    let session_id = Uuid::new_v4().to_string();
+    let store = open_session_store(&storage, &session_id)?;
+    let mut session = Session::restore(project, query.engine, store, settings.max_proj_bytes());
+    session.join_policy = policy;
+    session.metadata = SessionMetadata::new(creator.map(|i| i.name), query.title, query.description, query.tags.as_deref());
+    session.store.write(StoreWrite::WriteSettings(session.settings().await));
+    session.store.write(StoreWrite::WriteProject(session.rootdir.spool_to_dto().await));
+    recording_logic::start_recording(&settings, &session_id, &mut session).await;
+    sessions.insert(session_id.clone(), session);
+    events.emit(SessionEvent::Created { session_id: session_id.clone() });
+    Ok(NewSession { session_id })
+}
+
+/// Packs a session's project into an archive, keeping its directory 
+/// structure, so it can be downloaded by anyone who can read it. 
+pub async fn export_session(
+    session_id: String,
+    query: ExportQuery,
+    creds: SessionCredentials,
+    identity: Option<Identity>,
+    settings: AppSettings,
+    sessions_str: SessionStore
+) -> Result<impl Reply, CodealongError> {
+    user_logic::check_access(&session_id, &sessions_str, &creds, identity.as_ref(), &settings, false).await?;
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(&session_id).ok_or(CodealongError::NotFound)?;
+    let project = session.rootdir.spool_to_dto().await;
+    drop(sessions);
+
+    let format = query.format;
+    let archive = tokio::task::spawn_blocking(move || archive::write_archive(&project, format)).await;
+    let archive = match archive {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => {
+            log::error!("failed to export session {}: {}", session_id, e);
+            return Err(CodealongError::InternalServerError)
+        },
+        Err(e) => {
+            log::error!("failed to export session {}: {}", session_id, e);
+            return Err(CodealongError::InternalServerError)
+        }
+    };
+
+    let disposition = format!("attachment; filename=\"{}.{}\"", session_id, format.extension());
+    let reply = reply::with_header(archive, "content-type", format.content_type());
+    Ok(reply::with_header(reply, "content-disposition", disposition))
+}
+
+/// Sends the whole project to the requesting user, stamped with the 
+/// sequence number of the last activity it includes. 
 pub async fn stream_out_session(
     session: &Session
 ) -> SendTo {
+    // Holding the log stops activities being broadcast while the project is read.
+    let log = session.log.read().await;
     let project_dir = session.rootdir.spool_to_dto().await;
     let server_act = ServerActivity::CurrentProject(project_dir);
     let sess_act = SessionActivity::ServerActivity(server_act);
-    SendTo::ToSameUser(sess_act)
+    SendTo::ToSameUserReplay(vec![OutgoingActivity::stamped(log.last_seq(), sess_act)])
+}
+
+/// Replays every activity broadcast since the last one a user saw, or 
+/// sends the whole project if the log no longer holds them all. 
+pub async fn sync_since(
+    user_id: &str,
+    since: SyncSince,
+    session: &Session
+) -> SendTo {
+    let missed = session.log.read().await.since(since.seq, user_id);
+    match missed {
+        Some(v) if v.is_empty() => SendTo::ToNone,
+        Some(v) => SendTo::ToSameUserReplay(v),
+        None => stream_out_session(session).await
+    }
+}
+
+/// Spawns a background task that periodically releases line locks which 
+/// have sat idle for longer than `AppSettings::lock_timeout_secs`. 
+pub fn spawn_lock_expiry(
+    settings: AppSettings, 
+    state: SessionStore
+) {
+    if settings.lock_timeout_secs == 0 {
+        return;
+    }
+    let timeout = Duration::from_secs(settings.lock_timeout_secs);
+    let period = Duration::from_secs((settings.lock_timeout_secs / 2).max(1));
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            expire_session_locks(timeout, &state).await;
+        }
+    });
+}
+
+async fn expire_session_locks(timeout: Duration, state: &SessionStore) {
+    let sessions = state.read().await;
+    for (_, session) in sessions.iter() {
+        for act in file_logic::expire_locks(timeout, session).await {
+            user_logic::send_all_users(&act, session).await;
+        }
+    }
+}
+
+/// Spawns a background task that removes users who disconnected and didn't 
+/// resume within `AppSettings::resume_grace_secs`. 
+pub fn spawn_resume_expiry(
+    settings: AppSettings,
+    state: SessionStore,
+    events: SessionEvents
+) {
+    if settings.resume_grace_secs == 0 {
+        return;
+    }
+    let grace = Duration::from_secs(settings.resume_grace_secs);
+    let period = Duration::from_secs((settings.resume_grace_secs / 2).max(1));
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let sessions = state.read().await;
+            for (session_id, session) in sessions.iter() {
+                user_logic::expire_departed(grace, session_id, session, &events).await;
+            }
+        }
+    });
+}
+
+/// Loads every persisted session into the store, so users can rejoin 
+/// them with the same session id. 
+pub async fn restore_sessions(
+    settings: &AppSettings,
+    persistence: &Arc<dyn SessionPersistence>,
+    storage: &Storage,
+    state: &SessionStore
+) {
+    let loader = persistence.clone();
+    let snapshots = match tokio::task::spawn_blocking(move || loader.load_all()).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => return log::error!("failed to load session snapshots: {}", e),
+        Err(e) => return log::error!("failed to load session snapshots: {}", e)
+    };
+    let mut sessions = state.write().await;
+    for snapshot in snapshots {
+        let store = match storage.open_project(&snapshot.id) {
+            Ok(v) => StoreWriter::spawn(v),
+            Err(e) => {
+                log::error!("failed to open project store for session {}: {}", snapshot.id, e);
+                continue;
+            }
+        };
+        log::info!("restored session {} from snapshot", snapshot.id);
+        let mut session = Session::restore(snapshot.project, snapshot.engine, store, settings.max_proj_bytes());
+        session.join_policy = snapshot.join_policy;
+        *session.bans.get_mut() = snapshot.bans;
+        session.metadata = snapshot.metadata;
+        recording_logic::start_recording(settings, &snapshot.id, &mut session).await;
+        sessions.insert(snapshot.id, session);
+    }
+}
+
+/// Loads the project of every session found in the storage backend that 
+/// isn't already in the store, such as after a crash. A project stored 
+/// without its settings isn't restored, it may have been protected. 
+pub async fn restore_stored_sessions(
+    settings: &AppSettings,
+    storage: &Storage,
+    state: &SessionStore
+) {
+    let loader = storage.clone();
+    let session_ids = match tokio::task::spawn_blocking(move || loader.stored_projects()).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => return log::error!("failed to list stored projects: {}", e),
+        Err(e) => return log::error!("failed to list stored projects: {}", e)
+    };
+    let mut sessions = state.write().await;
+    for session_id in session_ids {
+        if sessions.contains_key(&session_id) {
+            continue;
+        }
+        let project = storage.open_project(&session_id)
+            .and_then(|store| Ok((store.load()?, store.load_settings()?, store)));
+        match project {
+            Ok((Some(project), Some(stored), store)) => {
+                log::info!("restored session {} from storage", session_id);
+                let store = StoreWriter::spawn(store);
+                let mut session = Session::restore(project, stored.engine, store, settings.max_proj_bytes());
+                session.join_policy = stored.join_policy;
+                *session.bans.get_mut() = stored.bans;
+                session.metadata = stored.metadata;
+                recording_logic::start_recording(settings, &session_id, &mut session).await;
+                sessions.insert(session_id, session);
+            },
+            Ok((Some(_), None, _)) => log::warn!("skipped stored project {}: its settings weren't stored", session_id),
+            Ok((None, _, _)) => (),
+            Err(e) => log::error!("failed to load stored project {}: {}", session_id, e)
+        }
+    }
+}
+
+/// Saves a snapshot of every session in the store. 
+pub async fn snapshot_sessions(
+    persistence: &Arc<dyn SessionPersistence>,
+    state: &SessionStore
+) {
+    let sessions = state.read().await;
+    let snapshot_futures = sessions.iter()
+        .filter(|(_, session)| !session.is_playback())
+        .map(|(id, session)| session.to_snapshot(id));
+    let snapshots = join_all(snapshot_futures).await;
+    drop(sessions);
+
+    let saver = persistence.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        for snapshot in snapshots.iter() {
+            if let Err(e) = saver.save(snapshot) {
+                log::error!("failed to save session {}: {}", snapshot.id, e);
+            }
+        }
+    }).await;
+    if let Err(e) = res {
+        log::error!("failed to save session snapshots: {}", e);
+    }
+}
+
+/// Waits for the writes queued to every session's project store, so none 
+/// are lost when the server stops. 
+pub async fn flush_stores(state: &SessionStore) {
+    let sessions = state.read().await;
+    join_all(sessions.values().map(|session| session.store.flush())).await;
+}
+
+/// Spawns a background task that snapshots every session each 
+/// `AppSettings::snapshot_interval_secs`. 
+pub fn spawn_snapshots(
+    settings: AppSettings,
+    state: SessionStore,
+    persistence: Arc<dyn SessionPersistence>
+) {
+    let period = Duration::from_secs(settings.snapshot_interval_secs.max(1));
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        // The first tick completes immediately, there's nothing new to save yet.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            snapshot_sessions(&persistence, &state).await;
+        }
+    });
+}
+
+/// Spawns a background task that closes sessions which have had no users 
+/// for longer than `AppSettings::idle_session_secs`, freeing their slot. 
+/// 
+/// If `AppSettings::persist_idle_sessions` is set a closed session is 
+/// snapshotted and left in storage so it's restored on the next start, 
+/// otherwise it's removed from both. 
+pub fn spawn_session_reaper(
+    settings: AppSettings,
+    state: SessionStore,
+    storage: Storage,
+    persistence: Option<Arc<dyn SessionPersistence>>,
+    events: SessionEvents
+) {
+    if settings.idle_session_secs == 0 {
+        return;
+    }
+    let grace = Duration::from_secs(settings.idle_session_secs);
+    let period = Duration::from_secs((settings.idle_session_secs / 2).max(1));
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            for (session_id, session) in take_idle_sessions(grace, &state).await {
+                if session.is_playback() {
+                    // Playback sessions are only held in memory.
+                    events.emit(SessionEvent::Reaped { session_id, persisted: false });
+                    continue;
+                }
+                let persist = settings.persist_idle_sessions;
+                let persisted = close_session(&session_id, session, persist, &storage, &persistence).await;
+                events.emit(SessionEvent::Reaped { session_id, persisted });
+            }
+        }
+    });
+}
+
+async fn take_idle_sessions(
+    grace: Duration, 
+    state: &SessionStore
+) -> Vec<(String, Session)> {
+    let mut sessions = state.write().await;
+    let mut idle = vec![];
+    for (id, session) in sessions.iter() {
+        if session.idle_for(grace).await {
+            idle.push(id.clone());
+        }
+    }
+    idle.into_iter()
+        .filter_map(|id| sessions.remove(&id).map(|session| (id, session)))
+        .collect()
+}
+
+/// Removes or keeps what's stored of a closed session. 
+/// 
+/// # Returns 
+/// * `true` - If the session was kept, by its snapshot or its project store, so it can be restored. 
+/// * `false` - If it was removed, or keeping it failed. 
+async fn close_session(
+    session_id: &str,
+    session: Session,
+    persist: bool,
+    storage: &Storage,
+    persistence: &Option<Arc<dyn SessionPersistence>>
+) -> bool {
+    let snapshot = session.to_snapshot(session_id).await;
+    let written = if persist {
+        session.store.write(StoreWrite::WriteProject(snapshot.project.clone()));
+        session.store.flush().await
+    }
+    else {
+        // Writes still queued would recreate the project once it's removed.
+        session.store.flush().await;
+        false
+    };
+    // The session's store queue closes with it.
+    drop(session);
+    let storage = storage.clone();
+    let persistence = persistence.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        let id = &snapshot.id;
+        if !persist {
+            if let Some(persistence) = persistence {
+                if let Err(e) = persistence.remove(id) {
+                    log::error!("failed to remove snapshot of closed session {}: {}", id, e);
+                }
+            }
+            if let Err(e) = storage.remove_project(id) {
+                log::error!("failed to remove stored project of session {}: {}", id, e);
+            }
+            return false;
+        }
+        let snapshotted = match persistence.map(|p| p.save(&snapshot)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                log::error!("failed to snapshot closed session {}: {}", id, e);
+                false
+            },
+            None => false
+        };
+        // Backends that don't keep projects, such as memory, list none.
+        let stored = match storage.stored_projects() {
+            Ok(ids) => written && ids.contains(id),
+            Err(e) => {
+                log::error!("failed to list stored projects: {}", e);
+                false
+            }
+        };
+        snapshotted || stored
+    }).await;
+    match res {
+        Ok(persisted) => persisted,
+        Err(e) => {
+            log::error!("failed to close session {}: {}", session_id, e);
+            false
+        }
+    }
 }
@@ -0,0 +1,309 @@
+use crate::{
+    models::{
+        user_activity::{UserActivity, CreateLine, FileChanged, DeleteRange, WriteMode},
+        session_activity::{SendTo, SessionActivity},
+        session::Session,
+        server_activity::ServerActivity,
+        directory::{DirError, DirectoryUpdated, RenameItem},
+        undo::Change
+    }
+};
+
+use super::file::wrap_dir_err;
+use super::directory as dir_logic;
+use super::file::{self as file_logic, LineAccess};
+
+use futures::FutureExt;
+
+
+/// Works out how an activity could be undone, before it's applied so 
+/// anything it removes can be kept. 
+pub async fn track(msg: &UserActivity, session: &Session) -> Option<Change> {
+    match msg {
+        UserActivity::CreateLine(create) => Some(Change::LineInserted {
+            filepath: create.filepath.clone(),
+            at: create.at,
+            // Filled in once the line has been added
+            add_no: 0
+        }),
+        UserActivity::FileChanged(change) => Some(Change::LineEdited {
+            filepath: change.path.clone(),
+            add_no: change.line,
+            old: change.old.clone(),
+            new: change.new.clone()
+        }),
+        UserActivity::DirUpdated(DirectoryUpdated::CreatedFile(path)) => Some(Change::FileCreated {
+            path: path.clone(),
+            lines: vec![String::new()]
+        }),
+        UserActivity::DirUpdated(DirectoryUpdated::RenameFile(rename)) => Some(Change::FileRenamed {
+            path: rename.path.clone(),
+            name: rename.name.clone()
+        }),
+        UserActivity::WriteFile(write) => match write.mode {
+            WriteMode::Create => Some(Change::FileCreated { path: write.path.clone(), lines: write.lines.clone() }),
+            WriteMode::Overwrite => {
+                let old = file_lines(&write.path, session).await?;
+                Some(Change::FileReplaced { path: write.path.clone(), old, new: write.lines.clone() })
+            }
+        },
+        UserActivity::DirUpdated(DirectoryUpdated::ErasedFile(path)) => {
+            let lines = file_lines(path, session).await?;
+            Some(Change::FileDeleted { path: path.clone(), lines })
+        },
+        _ => None
+    }
+}
+
+/// Adds a tracked change to the user's undo history once the activity 
+/// it came from has been applied. 
+pub async fn commit(
+    user_id: &str,
+    change: Option<Change>,
+    res: &SendTo,
+    session: &Session
+) {
+    let change = match (change, res) {
+        (None, _) | (_, SendTo::ToSameUser(_) | SendTo::ToNone) => return,
+        (Some(Change::LineInserted { filepath, at, .. }), SendTo::ToAllUsers(act)) => match act {
+            SessionActivity::ServerActivity(ServerActivity::LineAdded(added)) =>
+                Change::LineInserted { filepath, at, add_no: added.add_no },
+            _ => return
+        },
+        (Some(change), _) => change
+    };
+    session.undo.write().await
+        .entry(user_id.to_owned())
+        .or_default()
+        .push(change);
+}
+
+/// Reverts the user's most recent change, broadcasting the result to 
+/// every user as it wasn't made by their client. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsers(activity)` - The activity the revert would've been sent as if made directly. 
+/// * `SendTo::ToAllUsersEach(activities)` - The activities reverting a file's lines being written. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If there's nothing to undo or the change couldn't be reverted. 
+pub async fn undo(user_id: &str, session: &Session) -> SendTo {
+    step(user_id, session, true).await
+}
+
+/// Reapplies the user's most recently undone change. 
+/// 
+/// # Returns 
+/// * `SendTo::ToAllUsers(activity)` - The activity the change would've been sent as if made directly. 
+/// * `SendTo::ToAllUsersEach(activities)` - The activities writing a file's lines again. 
+/// * `SendTo::ToSameUser(DirectoryErr)` - If there's nothing to redo or the change couldn't be reapplied. 
+pub async fn redo(user_id: &str, session: &Session) -> SendTo {
+    step(user_id, session, false).await
+}
+
+async fn step(user_id: &str, session: &Session, undoing: bool) -> SendTo {
+    // Held throughout so a user's undos and redos apply in order.
+    let mut histories = session.undo.write().await;
+    let history = histories.entry(user_id.to_owned()).or_default();
+    let popped = match if undoing { history.pop_undo() } else { history.pop_redo() } {
+        Some(change) => change,
+        None if undoing => return wrap_dir_err(DirError::NothingToUndo),
+        None => return wrap_dir_err(DirError::NothingToRedo)
+    };
+    let change = if undoing { popped.clone().inverse() } else { popped.clone() };
+    let requested = change.clone();
+    let (applied, res) = match apply(user_id, change, session).await {
+        Ok(v) => v,
+        // A superseded change is dropped as it won't apply later either, 
+        // others such as a locked line may once the lock is released.
+        Err(DirError::Superseded) => return wrap_dir_err(DirError::Superseded),
+        Err(e) => {
+            if undoing {
+                history.push_undo(popped);
+            }
+            else {
+                history.push_redo(popped);
+            }
+            return wrap_dir_err(e);
+        }
+    };
+    if let (
+        Change::LineInserted { add_no: from, .. },
+        Change::LineInserted { filepath, add_no: to, .. }
+    ) = (&requested, &applied) {
+        history.renumber(filepath, *from, *to);
+    }
+    if undoing {
+        history.push_redo(applied.inverse());
+    } else {
+        history.push_undo(applied);
+    }
+    res
+}
+
+/// Applies a change as the given user, checking what it changes hasn't 
+/// been changed since. 
+/// 
+/// # Returns 
+/// The change as applied, such as with the `add_no` of a re-added line, and 
+/// the activities to broadcast. 
+async fn apply(
+    user_id: &str,
+    change: Change,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let res = match change {
+        Change::LineInserted { filepath, at, .. } => insert_line(user_id, filepath, at, session).await,
+        Change::LineRemoved { filepath, add_no, .. } => remove_line(user_id, filepath, add_no, session).await,
+        Change::LineEdited { filepath, add_no, old, new } =>
+            edit_line(user_id, filepath, add_no, old, new, session).await,
+        Change::FileCreated { path, lines } => create_file(path, lines, session).await,
+        Change::FileDeleted { path, lines } => delete_file(path, lines, session).await,
+        Change::FileRenamed { path, name } => rename_file(path, name, session).await,
+        Change::FileReplaced { path, old, new } => replace_file(user_id, path, old, new, session).await
+    };
+    match res {
+        Err(
+            DirError::NotFound(_) | DirError::NameClash | DirError::DepthOutOfRange | DirError::LineMismatch(..)
+        ) => Err(DirError::Superseded),
+        res => res
+    }
+}
+
+async fn insert_line(
+    user_id: &str,
+    filepath: Vec<String>,
+    at: usize,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let added = file_logic::add_line(user_id, CreateLine { filepath: filepath.clone(), at }, session).await?;
+    let change = Change::LineInserted { filepath, at: added.at, add_no: added.add_no };
+    Ok((change, SendTo::ToAllUsers(ServerActivity::LineAdded(added).wrap_to_session())))
+}
+
+async fn remove_line(
+    user_id: &str,
+    filepath: Vec<String>,
+    add_no: usize,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let delete = DeleteRange { filepath: filepath.clone(), from: add_no, to: add_no };
+    // Only a line left as it was inserted is removed, any text is someone's edit.
+    let (removed, at) = file_logic::remove_range(user_id, delete, Some(vec![String::new()]), session).await?;
+    let change = Change::LineRemoved { filepath, at, add_no };
+    Ok((change, SendTo::ToAllUsers(ServerActivity::LinesRemoved(removed).wrap_to_session())))
+}
+
+async fn edit_line(
+    user_id: &str,
+    filepath: Vec<String>,
+    add_no: usize,
+    old: String,
+    new: String,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let edit = FileChanged { path: filepath.clone(), line: add_no, old: old.clone(), new: new.clone() };
+    let updated = file_logic::edit_line(user_id, edit, LineAccess::Unlocked, session).await?;
+    let change = Change::LineEdited { filepath, add_no, old, new };
+    Ok((change, SendTo::ToAllUsers(ServerActivity::LineUpdated(updated).wrap_to_session())))
+}
+
+async fn create_file(
+    path: Vec<String>,
+    lines: Vec<String>,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let act = dir_logic::create_file_with(path.clone(), lines.clone(), session).await?;
+    Ok((Change::FileCreated { path, lines }, SendTo::ToAllUsers(act)))
+}
+
+async fn delete_file(
+    path: Vec<String>,
+    lines: Vec<String>,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let erased = dir_logic::delete_file(path.clone(), Some(lines.clone()), session).await?;
+    let act = ServerActivity::DirectoryUpdate(erased);
+    Ok((Change::FileDeleted { path, lines }, SendTo::ToAllUsers(act.wrap_to_session())))
+}
+
+async fn rename_file(
+    path: Vec<String>,
+    name: String,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let rename = RenameItem { path: path.clone(), name: name.clone() };
+    let updated = dir_logic::inner(session, DirectoryUpdated::RenameFile(rename)).await?;
+    let act = ServerActivity::DirectoryUpdate(updated);
+    Ok((Change::FileRenamed { path, name }, SendTo::ToAllUsers(act.wrap_to_session())))
+}
+
+async fn replace_file(
+    user_id: &str,
+    path: Vec<String>,
+    old: Vec<String>,
+    new: Vec<String>,
+    session: &Session
+) -> Result<(Change, SendTo), DirError> {
+    let acts = dir_logic::replace_file(user_id, path.clone(), new.clone(), Some(old.clone()), session).await?;
+    Ok((Change::FileReplaced { path, old, new }, SendTo::ToAllUsersEach(acts)))
+}
+
+/// Reads the lines of a file, `None` if it can't be found. 
+async fn file_lines(path: &[String], session: &Session) -> Option<Vec<String>> {
+    session.rootdir.transverse_blocking(path, 0, |f, d| async move {
+        let files = d.files.read().await;
+        let text = files.get(&f)?.text().await;
+        Some(text.split('\n').map(str::to_owned).collect())
+    }.boxed()).await.ok()?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::directory::DirectoryDTO;
+    use crate::models::session::EditEngine;
+    use crate::utils::storage::{StoreWriter, memory::MemoryProject};
+
+    use std::sync::Arc;
+
+    fn session_with(lines: &[&str]) -> Session {
+        let mut project = DirectoryDTO::default();
+        project.files.insert("a.txt".to_owned(), lines.iter().map(|l| (*l).to_owned()).collect());
+        Session::restore(project, EditEngine::LineLock, StoreWriter::spawn(Arc::new(MemoryProject)), usize::MAX)
+    }
+
+    async fn set_lock(session: &Session, add_no: usize, user_id: Option<&str>) {
+        let user_id = user_id.map(str::to_owned);
+        session.rootdir.transverse_blocking(&["a.txt".to_owned()], 0, |f, d| async move {
+            let files = d.files.read().await;
+            let lines = files[&f].read().await;
+            let mut line_data = lines[add_no].line_data.write().await;
+            match user_id {
+                Some(id) => line_data.lock(&id),
+                None => { line_data.unlock(); }
+            }
+        }.boxed()).await.ok();
+    }
+
+    #[tokio::test]
+    async fn undo_waits_for_a_locked_line() {
+        let session = session_with(&["edited"]);
+        let edit = Change::LineEdited {
+            filepath: vec!["a.txt".to_owned()],
+            add_no: 0,
+            old: "original".to_owned(),
+            new: "edited".to_owned()
+        };
+        session.undo.write().await.entry("alice".to_owned()).or_default().push(edit);
+
+        set_lock(&session, 0, Some("bob")).await;
+        let refused = undo("alice", &session).await;
+        assert!(matches!(refused, SendTo::ToSameUser(SessionActivity::ServerActivity(
+            ServerActivity::DirectoryErr(DirError::LineLocked(_))))));
+
+        set_lock(&session, 0, None).await;
+        let undone = undo("alice", &session).await;
+        assert!(matches!(undone, SendTo::ToAllUsers(SessionActivity::ServerActivity(
+            ServerActivity::LineUpdated(ref updated))) if updated.line == "original"));
+        assert!(matches!(redo("alice", &session).await, SendTo::ToAllUsers(_)));
+    }
+}
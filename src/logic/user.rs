@@ -3,19 +3,40 @@ use crate::{
         session::{
             SessionStore,
             Session,
-            UserState
+            UserState,
+            UserJoined,
+            UserLeft,
+            DepartedUser,
+            ResumeToken,
+            Role,
+            RoleChanged,
+            JoinPolicy,
+            SessionCredentials,
+            Invite
         },
-        session_activity::SessionActivity,
-        user_activity::UserActivity,
+        session_activity::{SessionActivity, OutgoingActivity},
+        server_activity::ServerActivity,
+        directory::DirError,
+        user_activity::{UserActivity, SetRole, CreateInvite},
         errors::CodealongError, 
-        session_activity::SendTo
+        auth::Identity,
+        session_activity::SendTo,
+        lifecycle::{SessionEvents, SessionEvent}
     },
-    utils::settings::AppSettings
+    utils::settings::AppSettings,
+    utils::tokens,
+    utils::time::now_secs
 };
 
 use super::session as session_logic;
 use super::directory as dir_logic;
 use super::file as file_logic;
+use super::crdt as crdt_logic;
+use super::ot as ot_logic;
+use super::recording as recording_logic;
+use super::history as history_logic;
+use super::undo as undo_logic;
+use super::moderation as moderation_logic;
 
 use warp::{filters::ws, ws::WebSocket};
 use warp::reply::Reply;
@@ -29,81 +50,376 @@ use futures_util::{StreamExt, stream::SplitSink};
 
 use serde_json::{to_string as to_json_string, from_str};
 
-use uuid::Uuid;
+
+use std::time::{Duration, Instant};
 
 
 pub async fn new_user(
     session_id: String, 
-    user_name: String,
+    identity: Identity,
+    creds: SessionCredentials,
     ws: warp::ws::Ws, 
     settings: AppSettings, 
-    sessions_str: SessionStore
+    sessions_str: SessionStore,
+    events: SessionEvents
 ) -> Result<impl Reply, CodealongError> {
-    let (tx, rx) = mpsc::unbounded_channel::<SessionActivity>();
+    let (tx, rx) = mpsc::unbounded_channel::<OutgoingActivity>();
 
-    let new_user = UserState::new(user_name, tx);
+    let new_user = UserState::new(identity.name, tx);
 
-    let user_id = match check_add_users(settings.max_sess_users, 
+    let user_id = match check_add_users(&settings, 
         &session_id, 
         &sessions_str, 
-        new_user
+        identity.id,
+        new_user,
+        &creds
     ).await {
         Ok(v) => v,
         Err(e) => return Err(e)
     };
 
     let res_future = ws.on_upgrade(move |socket| 
-        user_thread(user_id, session_id, socket, sessions_str, rx)
+        user_thread(user_id, session_id, socket, sessions_str, rx, settings, events)
     );
 
     Ok(res_future)
 }
 
+/// Reconnects a user who dropped out of a session, using the resume token 
+/// they were given when joining. They keep their id, name and locks, and 
+/// are sent the activity they missed while away. An authenticated user may 
+/// only resume their own identity. 
+pub async fn resume_user(
+    session_id: String,
+    token: String,
+    identity: Option<Identity>,
+    ws: warp::ws::Ws,
+    settings: AppSettings,
+    sessions_str: SessionStore,
+    events: SessionEvents
+) -> Result<impl Reply, CodealongError> {
+    let user_id = match tokens::verify_resume_token(&settings.token_secret, &session_id, &token) {
+        Some(v) => v,
+        None => return Err(CodealongError::Unauthorized)
+    };
+    if identity.is_some_and(|i| i.id != user_id) {
+        return Err(CodealongError::Unauthorized)
+    }
+    let sessions = sessions_str.read().await;
+    let session = match sessions.get(&session_id) {
+        Some(val) => val,
+        _ => return Err(CodealongError::NotFound)
+    };
+    if !session.departed.read().await.contains_key(&user_id) {
+        return Err(CodealongError::NotFound)
+    }
+    drop(sessions);
+
+    let res_future = ws.on_upgrade(move |socket| async move {
+        // The grace window may have passed while the connection was upgraded.
+        let missed = match take_departed(&user_id, &session_id, &sessions_str).await {
+            Some(v) => v,
+            None => return
+        };
+        user_thread(user_id, session_id, socket, sessions_str, missed, settings, events).await
+    });
+
+    Ok(res_future)
+}
+
+async fn take_departed(
+    user_id: &str,
+    session_id: &String,
+    sessions_str: &SessionStore
+) -> Option<UnboundedReceiver<OutgoingActivity>> {
+    let sessions = sessions_str.read().await;
+    let session = sessions.get(session_id)?;
+    let departed = session.departed.write().await.remove(user_id)?;
+    Some(departed.missed)
+}
+
+/// Adds a user to a session if their credentials satisfy its join policy, 
+/// they're given the role of their invite, or without one become its owner 
+/// if it has none, such as an imported session or one whose owner has left. 
 async fn check_add_users(
-    max_sess_users: usize, 
+    settings: &AppSettings, 
     session_id: &String, 
     sessions_str: &SessionStore,
-    new_user: UserState
+    user_id: String,
+    new_user: UserState,
+    creds: &SessionCredentials
 ) -> Result<String, CodealongError> {
+    let mut new_user = new_user;
+    let policy = match sessions_str.read().await.get(session_id) {
+        Some(val) => val.join_policy.clone(),
+        _ => return Err(CodealongError::NotFound)
+    };
+    let invited = check_credentials(&settings.token_secret, session_id, &policy, creds).await?;
+    if let Some(role) = invited {
+        new_user.role = role;
+    }
     let sessions = sessions_str.read().await;
     let session = match sessions.get(session_id) {
         Some(val) => val,
         _ => return Err(CodealongError::NotFound)
     };
+    if session.bans.read().await.iter().any(|b| b.refuses(&user_id, &new_user.name)) {
+        return Err(CodealongError::Banned)
+    }
     let mut users = session.users.write().await;
 
-    if users.len() <= max_sess_users {
+    if users.len() >= settings.max_sess_users {
         return Err(CodealongError::MaxCapacity)
     }
 
-    let user_id = Uuid::new_v4().to_string();
+    // A user who dropped out must resume rather than join again.
+    if users.contains_key(&user_id) {
+        return Err(CodealongError::AlreadyJoined)
+    }
+    // An invite's role is kept, so it can't be used to take over the session.
+    if invited.is_none() && !users.values().any(|u| u.role == Role::Owner) {
+        new_user.role = Role::Owner;
+    }
 
     users.insert(
         user_id.clone(), 
         new_user
     );
+    *session.emptied_at.write().await = None;
     Ok(user_id)
 }
 
+/// Checks a request may read, or write, a session's project. Banned users 
+/// are refused, users in the session keep their role, anyone else must 
+/// satisfy its join policy. The credentials are checked without holding 
+/// the session store's lock, so callers shouldn't hold it either. 
+pub async fn check_access(
+    session_id: &str,
+    sessions_str: &SessionStore,
+    creds: &SessionCredentials,
+    identity: Option<&Identity>,
+    settings: &AppSettings,
+    write: bool
+) -> Result<(), CodealongError> {
+    let (member, policy, playback) = {
+        let sessions = sessions_str.read().await;
+        let session = sessions.get(session_id).ok_or(CodealongError::NotFound)?;
+        if let Some(identity) = identity {
+            if session.bans.read().await.iter().any(|b| b.refuses(&identity.id, &identity.name)) {
+                return Err(CodealongError::Banned)
+            }
+        }
+        let member = match identity {
+            Some(identity) => session.users.read().await.get(&identity.id).map(|u| u.role),
+            None => None
+        };
+        (member, session.join_policy.clone(), session.is_playback())
+    };
+    let role = match member {
+        Some(role) => role,
+        None => check_credentials(&settings.token_secret, session_id, &policy, creds).await?
+            .unwrap_or_default()
+    };
+    if write && (playback || role == Role::Viewer) {
+        return Err(CodealongError::Forbidden)
+    }
+    Ok(())
+}
+
+/// Checks credentials against a session's join policy, a valid invite token 
+/// admits a user whatever the policy. 
+/// 
+/// # Returns 
+/// * `Ok(Some(role))` - If an invite token was given, with the role it grants. 
+/// * `Ok(None)` - If the credentials are otherwise enough. 
+/// * `Err(Unauthorized)` - If the credentials don't satisfy the policy. 
+/// 
+/// Checking a password is slow, so it's run on the blocking pool and 
+/// callers shouldn't hold the session store's lock while awaiting it. 
+pub async fn check_credentials(
+    secret: &str,
+    session_id: &str,
+    policy: &JoinPolicy,
+    creds: &SessionCredentials
+) -> Result<Option<Role>, CodealongError> {
+    if creds.invite.is_some() {
+        return match invite_role(secret, session_id, creds) {
+            Some(role) => Ok(Some(role)),
+            None => Err(CodealongError::Unauthorized)
+        };
+    }
+    let (hash, password) = match (policy, &creds.password) {
+        (JoinPolicy::Open, _) => return Ok(None),
+        (JoinPolicy::Password(hash), Some(password)) => (hash.clone(), password.clone()),
+        _ => return Err(CodealongError::Unauthorized)
+    };
+    match tokio::task::spawn_blocking(move || tokens::verify_password(&hash, &password)).await {
+        Ok(true) => Ok(None),
+        _ => Err(CodealongError::Unauthorized)
+    }
+}
+
+/// The role granted by the credentials' invite token for a session, `None` 
+/// if there's no valid one. Cheap enough to check against every session. 
+pub fn invite_role(
+    secret: &str,
+    session_id: &str,
+    creds: &SessionCredentials
+) -> Option<Role> {
+    let invite = creds.invite.as_ref()?;
+    tokens::verify_invite_token(secret, session_id, invite, now_secs())
+}
+
 pub async fn user_thread(
     user_id: String,
     session_id: String,
     ws: ws::WebSocket,
     sessions: SessionStore,
-    user_rx: UnboundedReceiver<SessionActivity>
+    user_rx: UnboundedReceiver<OutgoingActivity>,
+    settings: AppSettings,
+    events: SessionEvents
 ) {
     let (user_ws_tx, mut user_ws_rx) = ws.split();
     let rx = UnboundedReceiverStream::new(user_rx);
 
     user_send_task(rx, user_ws_tx);
 
-    await_user_activity(user_id, session_id, sessions, &mut user_ws_rx).await;
+    user_connected(&user_id, &session_id, &sessions, &settings).await;
+
+    await_user_activity(user_id.clone(), session_id.clone(), sessions.clone(), &settings, &mut user_ws_rx).await;
+
+    if settings.resume_grace_secs > 0 {
+        user_departed(&user_id, &session_id, &sessions).await;
+        return;
+    }
+    let sessions = sessions.read().await;
+    if let Some(session) = sessions.get(&session_id) {
+        if remove_user(&user_id, session).await {
+            events.emit(SessionEvent::Emptied { session_id });
+        }
+    }
+}
+
+/// Sends a newly connected user their resume token and the list of users 
+/// in their session, and announces them to the other users. 
+async fn user_connected(
+    user_id: &str,
+    session_id: &String,
+    sessions: &SessionStore,
+    settings: &AppSettings
+) {
+    let sessions = sessions.read().await;
+    let session = match sessions.get(session_id) {
+        Some(val) => val,
+        _ => return
+    };
+    let users = session.users.read().await;
+    let user_list: Vec<UserJoined> = users.iter()
+        .map(|(id, user)| UserJoined { id: id.clone(), name: user.name.clone(), role: user.role })
+        .collect();
+    let joined = match user_list.iter().find(|u| u.id == user_id) {
+        Some(v) => v.clone(),
+        None => return
+    };
+    drop(users);
+
+    let token = ResumeToken {
+        user_id: user_id.to_owned(),
+        token: tokens::resume_token(&settings.token_secret, session_id, user_id)
+    };
+    let token = SendTo::ToSameUser(ServerActivity::ResumeToken(token).wrap_to_session());
+    send_response(user_id, &token, session).await;
+
+    let res = SendTo::ToSplit(
+        ServerActivity::UserList(user_list).wrap_to_session(),
+        ServerActivity::UserJoined(joined).wrap_to_session()
+    );
+    send_response(user_id, &res, session).await;
+}
+
+/// Holds a disconnected user's place in their session so they can resume, 
+/// activity sent to them is queued until they do. 
+async fn user_departed(
+    user_id: &str,
+    session_id: &String,
+    sessions: &SessionStore
+) {
+    let sessions = sessions.read().await;
+    let session = match sessions.get(session_id) {
+        Some(val) => val,
+        _ => return
+    };
+    let mut users = session.users.write().await;
+    let user = match users.get_mut(user_id) {
+        Some(v) => v,
+        None => return
+    };
+    let (tx, rx) = mpsc::unbounded_channel::<OutgoingActivity>();
+    // Swapping the sender ends the old connection's send task.
+    user.sender = tx;
+    let departed = DepartedUser { missed: rx, left_at: Instant::now() };
+    session.departed.write().await.insert(user_id.to_owned(), departed);
+}
+
+/// Removes every departed user whose grace window has passed. 
+pub async fn expire_departed(
+    grace: Duration,
+    session_id: &str,
+    session: &Session,
+    events: &SessionEvents
+) {
+    let mut departed = session.departed.write().await;
+    let expired: Vec<String> = departed.iter()
+        .filter(|(_, user)| user.left_at.elapsed() >= grace)
+        .map(|(id, _)| id.clone())
+        .collect();
+    for user_id in expired.iter() {
+        departed.remove(user_id);
+    }
+    drop(departed);
+    for user_id in expired {
+        if remove_user(&user_id, session).await {
+            events.emit(SessionEvent::Emptied { session_id: session_id.to_owned() });
+        }
+    }
+}
+
+/// Removes a user from their session, releases their locks and announces 
+/// they've left, marking the session as emptied if they were the last user. 
+/// Their undo history is kept for if they come back with the same id, until 
+/// the session empties. 
+/// 
+/// # Returns 
+/// If the session was emptied. 
+pub async fn remove_user(
+    user_id: &str,
+    session: &Session
+) -> bool {
+    let mut users = session.users.write().await;
+    if users.remove(user_id).is_none() {
+        return false;
+    }
+    let emptied = users.is_empty();
+    if emptied {
+        *session.emptied_at.write().await = Some(Instant::now());
+    }
+    drop(users);
+    if emptied {
+        session.undo.write().await.clear();
+    }
+    for act in file_logic::release_user_locks(user_id, session).await {
+        send_all_users(&act, session).await;
+    }
+    let left = ServerActivity::UserLeft(UserLeft { id: user_id.to_owned() });
+    send_all_users(&left.wrap_to_session(), session).await;
+    emptied
 }
 
 async fn await_user_activity(
     user_id: String,
     session_id: String,
     sessions: SessionStore,
+    settings: &AppSettings,
     user_ws_rx: &mut SplitStream<WebSocket>
 ) {
     loop {
@@ -115,23 +431,23 @@ async fn await_user_activity(
             user_id.clone(), 
             session_id.clone(), 
             msg, 
-            &sessions
+            &sessions,
+            settings
         ).await
     }
 }
 
 async fn next_response(user_ws_rx: &mut SplitStream<WebSocket>) -> Option<Message> {
-    let msg = if let Some(m) = user_ws_rx.next().await { m }
-    else { return None; };
-    if let Ok(m) = msg { Some(m) }
-    else { None }
+    let msg = user_ws_rx.next().await?;
+    msg.ok()
 }
 
 async fn process_user_resquest(
     user_id: String, 
     sess_id: String, 
     msg: Message, 
-    sessions: &SessionStore
+    sessions: &SessionStore,
+    settings: &AppSettings
 ) {
     let msg = match extract_message(&msg) {
         Some(val) => val,
@@ -143,18 +459,193 @@ async fn process_user_resquest(
         _ => return
     };
 
-    let res = match msg {
+    let err = match check_engine(&msg, session) {
+        Some(err) => Some(err),
+        None => check_read_only(&msg, session)
+    };
+    let err = match err {
+        Some(err) => Some(err),
+        None => check_role(&user_id, &msg, session).await
+    };
+    if let Some(err) = err {
+        return send_response(&user_id, &err, session).await;
+    }
+    let res = record_activity(&user_id, &sess_id, msg, settings, session).await;
+    send_response(&user_id, &res, session).await;
+}
+
+fn check_engine(msg: &UserActivity, session: &Session) -> Option<SendTo> {
+    match msg.required_engine() {
+        Some(engine) if engine != session.engine => {
+            let err = ServerActivity::DirectoryErr(DirError::WrongEngine(session.engine));
+            Some(SendTo::ToSameUser(err.wrap_to_session()))
+        },
+        _ => None
+    }
+}
+
+fn check_read_only(msg: &UserActivity, session: &Session) -> Option<SendTo> {
+    if !session.is_playback() || msg.is_read_only() {
+        return None;
+    }
+    let err = ServerActivity::DirectoryErr(DirError::ReadOnly);
+    Some(SendTo::ToSameUser(err.wrap_to_session()))
+}
+
+async fn check_role(user_id: &str, msg: &UserActivity, session: &Session) -> Option<SendTo> {
+    // A removed user may still send activity until their connection closes.
+    let role = session.users.read().await.get(user_id).map(|u| u.role);
+    if role.is_some_and(|role| msg.permitted_for(role)) {
+        return None;
+    }
+    let err = ServerActivity::DirectoryErr(DirError::NotPermitted);
+    Some(SendTo::ToSameUser(err.wrap_to_session()))
+}
+
+/// Applies an activity a user made to a session, recording it if the 
+/// session is recorded, without checking it's allowed by the session. 
+pub async fn record_activity(
+    user_id: &str,
+    session_id: &str,
+    msg: UserActivity,
+    settings: &AppSettings,
+    session: &Session
+) -> SendTo {
+    let recorded = session.recorder.as_ref().map(|_| msg.clone());
+    let res = dispatch_activity(user_id, session_id, msg, settings, session).await;
+    if let Some(msg) = recorded {
+        recording_logic::record_activity(user_id, session_id, msg, &res, session);
+    }
+    res
+}
+
+/// Applies an activity to a session as the given user, without checking 
+/// it's allowed by the session. 
+pub async fn dispatch_activity(
+    user_id: &str,
+    session_id: &str,
+    msg: UserActivity,
+    settings: &AppSettings,
+    session: &Session
+) -> SendTo {
+    let change = undo_logic::track(&msg, session).await;
+    let res = apply_activity(user_id, session_id, msg, settings, session).await;
+    undo_logic::commit(user_id, change, &res, session).await;
+    res
+}
+
+async fn apply_activity(
+    user_id: &str,
+    session_id: &str,
+    msg: UserActivity,
+    settings: &AppSettings,
+    session: &Session
+) -> SendTo {
+    match msg {
         UserActivity::RequestSync => 
             session_logic::stream_out_session(session).await,
+        UserActivity::SyncSince(since) => 
+            session_logic::sync_since(user_id, since, session).await,
         UserActivity::DirUpdated(update) => 
             dir_logic::directory_changed(update, session).await,
         UserActivity::LockLine(lock) =>
-            file_logic::lock_line(&user_id, lock, session).await,
+            file_logic::lock_line(user_id, lock, session).await,
+        UserActivity::UnlockLine(lock) =>
+            file_logic::unlock_line(user_id, lock, session).await,
         UserActivity::CreateLine(create) =>
-            file_logic::new_line(&user_id, create, session).await,
-        _ => SendTo::ToNone
+            file_logic::new_line(user_id, create, session).await,
+        UserActivity::FileChanged(change) =>
+            file_logic::update_line(user_id, change, session).await,
+        UserActivity::DeleteLine(delete) =>
+            file_logic::delete_line(user_id, delete, session).await,
+        UserActivity::DeleteRange(delete) =>
+            file_logic::delete_range(user_id, delete, session).await,
+        UserActivity::WriteFile(write) =>
+            dir_logic::write_file(user_id, write, session).await,
+        UserActivity::CrdtEdit(edit) =>
+            crdt_logic::apply_edit(user_id, edit, session).await,
+        UserActivity::RequestCrdtState(path) =>
+            crdt_logic::stream_out_state(path, session).await,
+        UserActivity::TextOperation(operation) =>
+            ot_logic::apply_operation(user_id, operation, session).await,
+        UserActivity::RequestOtState(path) =>
+            ot_logic::stream_out_state(path, session).await,
+        UserActivity::RequestFileLines(path) =>
+            file_logic::stream_out_lines(path, session).await,
+        UserActivity::PlaybackControl(control) =>
+            recording_logic::control_playback(control, session),
+        UserActivity::FileHistory(request) =>
+            history_logic::file_history(request, session).await,
+        UserActivity::FileDiff(request) =>
+            history_logic::file_diff(request, session).await,
+        UserActivity::Undo =>
+            undo_logic::undo(user_id, session).await,
+        UserActivity::Redo =>
+            undo_logic::redo(user_id, session).await,
+        UserActivity::SetRole(set) =>
+            set_role(user_id, set, session).await,
+        UserActivity::CreateInvite(create) =>
+            create_invite(session_id, create, settings),
+        UserActivity::KickUser(kick) =>
+            moderation_logic::kick_user(user_id, kick, session).await,
+        UserActivity::BanUser(ban) =>
+            moderation_logic::ban_user(user_id, ban, session).await,
+        UserActivity::TransferOwnership(transfer) =>
+            moderation_logic::transfer_ownership(user_id, transfer, session).await,
+    }
+}
+
+/// Mints an invite token to the session, for the owner to share. 
+/// 
+/// # Returns
+/// * `SendTo::ToSameUser(Invite)` - The token, with the role it grants and when it expires.
+fn create_invite(
+    session_id: &str,
+    create: CreateInvite,
+    settings: &AppSettings
+) -> SendTo {
+    let expires_in = create.expires_in_secs.unwrap_or(settings.invite_ttl_secs);
+    let expires_at = now_secs().saturating_add(expires_in);
+    let invite = Invite {
+        token: tokens::invite_token(&settings.token_secret, session_id, create.role, expires_at),
+        role: create.role,
+        expires_at
     };
-    send_response(&user_id, &res, session).await;
+    SendTo::ToSameUser(ServerActivity::Invite(invite).wrap_to_session())
+}
+
+/// Changes the role of another user, announcing it to every user. A user 
+/// made a viewer has their line locks released. 
+/// 
+/// # Returns
+/// * `SendTo::ToAllUsers(RoleChanged)` - If the role was changed.
+/// * `SendTo::ToSameUser(DirectoryErr)` - If the user couldn't be found or is the sender.
+async fn set_role(
+    user_id: &str,
+    set: SetRole,
+    session: &Session
+) -> SendTo {
+    // Changing their own role could leave the session without an owner.
+    if set.id == user_id {
+        let err = ServerActivity::DirectoryErr(DirError::NotPermitted);
+        return SendTo::ToSameUser(err.wrap_to_session());
+    }
+    let mut users = session.users.write().await;
+    match users.get_mut(&set.id) {
+        Some(user) => user.role = set.role,
+        None => {
+            let err = ServerActivity::DirectoryErr(DirError::UserNotFound(set.id));
+            return SendTo::ToSameUser(err.wrap_to_session());
+        }
+    };
+    drop(users);
+    if set.role == Role::Viewer {
+        for act in file_logic::release_user_locks(&set.id, session).await {
+            send_all_users(&act, session).await;
+        }
+    }
+    let changed = RoleChanged { id: set.id, role: set.role };
+    SendTo::ToAllUsers(ServerActivity::RoleChanged(changed).wrap_to_session())
 }
 
 fn extract_message(msg: &Message) -> Option<UserActivity> {
@@ -162,68 +653,127 @@ fn extract_message(msg: &Message) -> Option<UserActivity> {
         Ok(v) => v,
         Err(_) => return None
     };
-    match from_str::<UserActivity>(msg_text) {
-        Ok(v) => Some(v),
-        Err(_) => return None
-    }
+    from_str::<UserActivity>(msg_text).ok()
 }
 
-async fn send_response(user_id: &String, res: &SendTo, session: &Session) {
+pub async fn send_response(user_id: &str, res: &SendTo, session: &Session) {
     match res {
         SendTo::ToNone => (),
         SendTo::ToAllUsers(v) => send_all_users(v, session).await,
+        SendTo::ToAllUsersEach(v) => for act in v {
+            send_all_users(act, session).await;
+        },
         SendTo::ToOtherUsers(v) => send_other_users(user_id, v, session).await,
         SendTo::ToSameUser(v) => send_same_users(user_id, v, session).await,
         SendTo::ToSplit(u, o) => {
             join!(send_same_users(user_id, u, session),
                 send_other_users(user_id, o, session));
-        }
+        },
+        SendTo::ToSameUserReplay(v) => send_same_user_replay(user_id, v.clone(), session).await
     };
 }
 
-async fn send_all_users(act: &SessionActivity, session: &Session) {
+/// Sends an activity to every user, recording it in the session's event log. 
+pub async fn send_all_users(act: &SessionActivity, session: &Session) {
+    // Holding the log while sending keeps each user's activities in sequence order.
+    let mut log = session.log.write().await;
+    let seq = log.push(act.clone(), None);
     let users = session.users.read().await;
     for (_, user) in users.iter() {
-        if let Err(_) = user.sender.send(act.clone()) {
+        if user.sender.send(OutgoingActivity::stamped(seq, act.clone())).is_err() {
             // User has disconected, user logout code will run 
         }
     }
 }
 
-async fn send_other_users(user_id: &String, act: &SessionActivity, session: &Session) {
+async fn send_other_users(user_id: &str, act: &SessionActivity, session: &Session) {
+    let mut log = session.log.write().await;
+    let seq = log.push(act.clone(), Some(user_id));
     let users = session.users.read().await;
     for (id, user) in users.iter() {
         if id == user_id { continue; }
-        if let Err(_) = user.sender.send(act.clone()) {
+        if user.sender.send(OutgoingActivity::stamped(seq, act.clone())).is_err() {
             // User has disconected, user logout code will run 
         }
     }
 }
 
-async fn send_same_users(user_id: &String, act: &SessionActivity, session: &Session) {
+async fn send_same_users(user_id: &str, act: &SessionActivity, session: &Session) {
+    send_same_user_replay(user_id, vec![OutgoingActivity::unstamped(act.clone())], session).await
+}
+
+async fn send_same_user_replay(user_id: &str, acts: Vec<OutgoingActivity>, session: &Session) {
     let users = session.users.read().await;
     if let Some(user) = users.get(user_id) {
-        if let Err(_) = user.sender.send(act.clone()) {
-            // User has disconected, user logout code will run 
+        for act in acts {
+            if user.sender.send(act).is_err() {
+                // User has disconected, user logout code will run 
+                break;
+            }
         }
     }
 }
 
 fn user_send_task(
-    rx: UnboundedReceiverStream<SessionActivity>,
+    rx: UnboundedReceiverStream<OutgoingActivity>,
     user_ws_tx: SplitSink<ws::WebSocket, Message>
 ) {
     let mut rx = rx;
     let mut user_ws_tx = user_ws_tx;
     tokio::task::spawn(async move {
-        while let Some(_message) = rx.next().await { 
-            match to_json_string(&_message) {
-                Ok(string) => user_ws_tx
+        while let Some(message) = rx.next().await { 
+            if let Ok(string) = to_json_string(&message) {
+                user_ws_tx
                     .send(Message::text(string))
                     .unwrap_or_else(|_e| { })
-                    .await,
-                _ => ()
+                    .await
+            }
+            if message.close {
+                user_ws_tx
+                    .send(Message::close())
+                    .unwrap_or_else(|_e| { })
+                    .await;
+                break;
             }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::directory::DirectoryDTO;
+    use crate::models::session::{Session, EditEngine};
+    use crate::utils::storage::{StoreWriter, memory::MemoryProject};
+
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::sync::RwLock;
+
+    fn store_with_empty_session() -> SessionStore {
+        let session = Session::restore(
+            DirectoryDTO::default(), 
+            EditEngine::LineLock, 
+            StoreWriter::spawn(Arc::new(MemoryProject)), 
+            usize::MAX
+        );
+        Arc::new(RwLock::new(HashMap::from([("s1".to_owned(), session)])))
+    }
+
+    async fn join(settings: &AppSettings, sessions: &SessionStore, user_id: &str, creds: &SessionCredentials) -> Role {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let user = UserState::new(user_id.to_owned(), tx);
+        check_add_users(settings, &"s1".to_owned(), sessions, user_id.to_owned(), user, creds).await.ok().unwrap();
+        sessions.read().await["s1"].users.read().await[user_id].role
+    }
+
+    #[tokio::test]
+    async fn invites_keep_their_role_in_a_session_without_an_owner() {
+        let settings = AppSettings::new();
+        let sessions = store_with_empty_session();
+        let invite = tokens::invite_token(&settings.token_secret, "s1", Role::Viewer, now_secs() + 60);
+        let creds = SessionCredentials { invite: Some(invite), ..Default::default() };
+        assert!(join(&settings, &sessions, "viewer", &creds).await == Role::Viewer);
+        assert!(join(&settings, &sessions, "first", &SessionCredentials::default()).await == Role::Owner);
+        assert!(join(&settings, &sessions, "second", &SessionCredentials::default()).await != Role::Owner);
+    }
+}
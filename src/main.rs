@@ -7,9 +7,17 @@ extern crate serde;
 extern crate futures;
 
 use utils::settings::AppSettings;
+use utils::persistence;
+use utils::storage::{self, Storage};
 use models::session::SessionStore;
+use models::lifecycle::SessionEvents;
 use endpoints::session as session_endpoints;
 use endpoints::user as user_endpoints;
+use endpoints::templates as templates_endpoints;
+use endpoints::admin as admin_endpoints;
+use endpoints::auth as auth_endpoints;
+use endpoints::errors as error_endpoints;
+use logic::session as session_logic;
 
 use std::collections::HashMap;
 use std::sync::{
@@ -52,9 +60,35 @@ async fn main() {
     pretty_env_logger::init();
 
     let app_settings = AppSettings::new();
+    if app_settings.jwt_secret.is_none() {
+        log::warn!("jwt_secret is unset, requests aren't authenticated and anyone may create or join sessions");
+    }
 
     let session_state = SessionStore::default();
 
+    session_logic::spawn_lock_expiry(app_settings.clone(), session_state.clone());
+
+    let storage = storage::from_settings(&app_settings)
+        .expect("failed to open project storage");
+
+    let persistence = persistence::from_settings(&app_settings);
+    if let Some(persistence) = &persistence {
+        session_logic::restore_sessions(&app_settings, persistence, &storage, &session_state).await;
+        session_logic::spawn_snapshots(app_settings.clone(), session_state.clone(), persistence.clone());
+    }
+    session_logic::restore_stored_sessions(&app_settings, &storage, &session_state).await;
+
+    let session_events = SessionEvents::default();
+    session_logic::spawn_resume_expiry(app_settings.clone(), session_state.clone(), session_events.clone());
+    session_logic::spawn_session_reaper(
+        app_settings.clone(), 
+        session_state.clone(), 
+        storage.clone(), 
+        persistence.clone(), 
+        session_events.clone()
+    );
+    let shutdown_state = session_state.clone();
+
     // Keep track of all connected users, key is usize, value
     // is a websocket sender.
     let users = Users::default();
@@ -69,8 +103,21 @@ async fn main() {
         .map(move || session_state.clone())
         .boxed();
 
-    let sessions = session_endpoints::make_session_filters(&session_filter, &settings_filter);
-    let users = user_endpoints::make_users_filters(&session_filter, &settings_filter);
+    let storage_filter: BoxedFilter<(Storage, )> = warp::any()
+        .map(move || storage.clone())
+        .boxed();
+
+    let events_filter: BoxedFilter<(SessionEvents, )> = warp::any()
+        .map(move || session_events.clone())
+        .boxed();
+
+    let auth_filter = auth_endpoints::authenticated(&settings_filter);
+
+    let sessions = session_endpoints::make_session_filters(&session_filter, &settings_filter, &storage_filter, &events_filter, &auth_filter);
+    let users = user_endpoints::make_users_filters(&session_filter, &settings_filter, &events_filter, &auth_filter);
+    let auth = auth_endpoints::make_auth_filters(&settings_filter);
+    let templates = templates_endpoints::make_templates_filters(&settings_filter, &auth_filter);
+    let admin = admin_endpoints::make_admin_filters(&settings_filter, &events_filter, &auth_filter);
 
     // GET /chat -> websocket upgrade
     let chat = warp::path("chat")
@@ -94,9 +141,22 @@ async fn main() {
 
     let routes = index.or(chat)
         .or(sessions)
-        .or(users);
-
-    warp::serve(routes).run(([127, 0, 0, 1], 8080)).await;
+        .or(users)
+        .or(templates)
+        .or(admin)
+        .or(auth)
+        .recover(error_endpoints::handle_rejection);
+
+    let (_, server) = warp::serve(routes)
+        .bind_with_graceful_shutdown(([127, 0, 0, 1], 8080), async {
+            tokio::signal::ctrl_c().await.ok();
+        });
+    server.await;
+
+    session_logic::flush_stores(&shutdown_state).await;
+    if let Some(persistence) = &persistence {
+        session_logic::snapshot_sessions(persistence, &shutdown_state).await;
+    }
 }
 
 
@@ -0,0 +1,51 @@
+use serde::{Serialize, Deserialize};
+
+
+/// The claims of a bearer token. 
+#[derive(Serialize, Deserialize)]
+pub struct Claims {
+    /// The stable id of the user, used as their id in sessions 
+    pub sub: String,
+    /// The name shown to other users 
+    pub name: String,
+    /// Seconds since the unix epoch when the token was issued 
+    pub iat: u64,
+    /// Seconds since the unix epoch when the token stops working 
+    pub exp: u64
+}
+
+/// Who a request is from. 
+#[derive(Clone)]
+pub struct Identity {
+    pub id: String,
+    pub name: String
+}
+
+/// Lets websocket clients, which can't set headers, give a bearer token. 
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct AuthQuery {
+    pub access_token: Option<String>
+}
+
+/// A user in the `auth_users_file`, tokens are only issued to these. 
+#[derive(Deserialize)]
+pub struct AuthUser {
+    pub id: String,
+    pub name: String,
+    pub password: String
+}
+
+/// Asks for a token as a user in the `auth_users_file`. 
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub id: String,
+    pub password: String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub token: String,
+    /// Seconds since the unix epoch when the token stops working 
+    pub expires_at: u64
+}
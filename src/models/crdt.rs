@@ -0,0 +1,255 @@
+use std::cmp::Ordering;
+
+use serde::{Serialize, Deserialize};
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+
+/// The client id used for characters seeded from a file's existing text.
+pub const SEED_CLIENT: &str = "";
+
+/// A causal id for a single character, unique across all clients.
+///
+/// Ids are ordered by their Lamport `clock` and then by `client`,
+/// so concurrent inserts at the same position resolve identically on
+/// every replica.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct CharId {
+    pub client: String,
+    pub clock: u64
+}
+
+impl Ord for CharId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.clock.cmp(&other.clock)
+            .then_with(|| self.client.cmp(&other.client))
+    }
+}
+
+impl PartialOrd for CharId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A single character in the sequence, deleted characters are kept
+/// as tombstones so later inserts can still reference them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrdtChar {
+    pub id: CharId,
+    /// The character this was inserted directly after, `None` for the start of the file
+    pub origin: Option<CharId>,
+    pub value: char,
+    pub deleted: bool
+}
+
+/// A character level operation sent by a client.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum CrdtOp {
+    Insert {
+        id: CharId,
+        origin: Option<CharId>,
+        value: char
+    },
+    Delete {
+        id: CharId
+    }
+}
+
+/// Possible errors when merging an operation.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CrdtError {
+    /// An operation references a character that hasn't been seen yet,
+    /// the client should request the file's state.
+    MissingCausalId(CharId)
+}
+
+/// A replicated growable array (RGA) holding the text of a file.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CrdtDoc {
+    pub chars: Vec<CrdtChar>
+}
+
+impl CrdtDoc {
+    /// Seeds a document from existing text, with ids owned by `SEED_CLIENT`.
+    pub fn from_text(text: &str) -> Self {
+        let mut origin = None;
+        let chars = text.chars()
+            .enumerate()
+            .map(|(i, value)| {
+                let id = CharId { client: SEED_CLIENT.to_owned(), clock: i as u64 + 1 };
+                let c = CrdtChar { id: id.clone(), origin: origin.take(), value, deleted: false };
+                origin = Some(id);
+                c
+            })
+            .collect();
+        CrdtDoc { chars }
+    }
+
+    /// The visible text of the document.
+    pub fn text(&self) -> String {
+        self.chars.iter()
+            .filter(|c| !c.deleted)
+            .map(|c| c.value)
+            .collect()
+    }
+
+    fn index_of(&self, id: &CharId) -> Option<usize> {
+        self.chars.iter().position(|c| &c.id == id)
+    }
+
+    /// Merges an operation into the document.
+    ///
+    /// # Returns
+    /// * `Ok(true)` - If the operation changed the document.
+    /// * `Ok(false)` - If the operation had already been applied.
+    /// * `Err(CrdtError::MissingCausalId(id))` - If a referenced character is missing.
+    pub fn apply(&mut self, op: &CrdtOp) -> Result<bool, CrdtError> {
+        match op {
+            CrdtOp::Insert { id, origin, value } => self.integrate_insert(id, origin, *value),
+            CrdtOp::Delete { id } => self.integrate_delete(id)
+        }
+    }
+
+    /// Builds the operations turning the visible text into `text`, new
+    /// characters are given ids owned by `client` that are newer than
+    /// any already in the document.
+    pub fn diff_ops(&self, text: &str, client: &str) -> Vec<CrdtOp> {
+        let visible: Vec<&CrdtChar> = self.chars.iter().filter(|c| !c.deleted).collect();
+        let old: Vec<char> = visible.iter().map(|c| c.value).collect();
+        let new: Vec<char> = text.chars().collect();
+        let mut clock = self.chars.iter().map(|c| c.id.clock).max().unwrap_or(0);
+        let mut ops = vec![];
+        let mut origin: Option<CharId> = None;
+        for diff in capture_diff_slices(Algorithm::Myers, &old, &new) {
+            let (old_range, new_range) = (diff.old_range(), diff.new_range());
+            if let DiffOp::Equal { .. } = diff {
+                origin = old_range.last().map(|i| visible[i].id.clone());
+                continue;
+            }
+            for i in old_range {
+                ops.push(CrdtOp::Delete { id: visible[i].id.clone() });
+            }
+            for i in new_range {
+                clock += 1;
+                let id = CharId { client: client.to_owned(), clock };
+                ops.push(CrdtOp::Insert { id: id.clone(), origin: origin.take(), value: new[i] });
+                origin = Some(id);
+            }
+        }
+        ops
+    }
+
+    fn integrate_insert(
+        &mut self,
+        id: &CharId,
+        origin: &Option<CharId>,
+        value: char
+    ) -> Result<bool, CrdtError> {
+        if self.index_of(id).is_some() {
+            return Ok(false);
+        }
+        let mut at = match origin {
+            Some(o) => match self.index_of(o) {
+                Some(i) => i + 1,
+                None => return Err(CrdtError::MissingCausalId(o.clone()))
+            },
+            None => 0
+        };
+        // Concurrent inserts after the same origin are ordered by descending id,
+        // skipping over any greater siblings along with everything inserted after them.
+        while at < self.chars.len() && &self.chars[at].id > id {
+            at += 1;
+        }
+        let c = CrdtChar { id: id.clone(), origin: origin.clone(), value, deleted: false };
+        self.chars.insert(at, c);
+        Ok(true)
+    }
+
+    fn integrate_delete(&mut self, id: &CharId) -> Result<bool, CrdtError> {
+        let at = match self.index_of(id) {
+            Some(i) => i,
+            None => return Err(CrdtError::MissingCausalId(id.clone()))
+        };
+        let c = &mut self.chars[at];
+        if c.deleted {
+            return Ok(false);
+        }
+        c.deleted = true;
+        Ok(true)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileCrdtApplied {
+    pub filepath: Vec<String>,
+    pub user_id: String,
+    pub ops: Vec<CrdtOp>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileCrdtState {
+    pub filepath: Vec<String>,
+    pub doc: CrdtDoc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(client: &str, clock: u64) -> CharId {
+        CharId { client: client.to_owned(), clock }
+    }
+
+    fn insert(client: &str, clock: u64, origin: Option<CharId>, value: char) -> CrdtOp {
+        CrdtOp::Insert { id: id(client, clock), origin, value }
+    }
+
+    #[test]
+    fn concurrent_inserts_converge() {
+        let seed = CrdtDoc::from_text("ac");
+        let a = insert("a", 3, Some(id(SEED_CLIENT, 1)), 'x');
+        let b = insert("b", 3, Some(id(SEED_CLIENT, 1)), 'y');
+        let c = CrdtOp::Delete { id: id(SEED_CLIENT, 2) };
+
+        let mut first = seed.clone();
+        let mut second = seed;
+        for op in [&a, &b, &c] {
+            assert_eq!(first.apply(op).ok(), Some(true));
+        }
+        for op in [&c, &b, &a] {
+            assert_eq!(second.apply(op).ok(), Some(true));
+        }
+        assert_eq!(first.text(), second.text());
+        assert_eq!(first.text(), "ayx");
+    }
+
+    #[test]
+    fn repeated_and_unknown_ops() {
+        let mut doc = CrdtDoc::from_text("a");
+        let op = insert("a", 2, Some(id(SEED_CLIENT, 1)), 'b');
+        assert_eq!(doc.apply(&op).ok(), Some(true));
+        assert_eq!(doc.apply(&op).ok(), Some(false));
+        let delete = CrdtOp::Delete { id: id("a", 2) };
+        assert_eq!(doc.apply(&delete).ok(), Some(true));
+        assert_eq!(doc.apply(&delete).ok(), Some(false));
+        assert_eq!(doc.text(), "a");
+
+        let orphan = insert("b", 5, Some(id("c", 4)), 'z');
+        assert!(matches!(doc.apply(&orphan), Err(CrdtError::MissingCausalId(missing)) if missing == id("c", 4)));
+    }
+
+    #[test]
+    fn diff_ops_reach_the_text() {
+        let mut doc = CrdtDoc::from_text("hello world");
+        doc.apply(&CrdtOp::Delete { id: id(SEED_CLIENT, 6) }).ok();
+        for text in ["hello, there world", "", "world"] {
+            let ops = doc.diff_ops(text, "api");
+            for op in ops.iter() {
+                assert_eq!(doc.apply(op).ok(), Some(true));
+            }
+            assert_eq!(doc.text(), text);
+        }
+        assert!(doc.diff_ops("world", "api").is_empty());
+    }
+}
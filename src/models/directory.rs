@@ -1,9 +1,13 @@
 use super::{
-    file::File,
+    file::{File, FileLineData, FileLineUnlocked},
+    quota::line_size,
+    crdt::CrdtError,
+    ot::OtError,
+    session::EditEngine,
     user_activity
 };
 
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 
 use tokio::sync::RwLock;
 
@@ -26,7 +30,43 @@ pub enum DirError {
     DepthOutOfRange,
     /// A file or directory of a name already exists. 
     NameClash,
-    LineLocked(user_activity::LockLine)
+    /// A name can't be used for a file or directory. 
+    InvalidName(String),
+    /// A line is locked by another user. 
+    LineLocked(user_activity::LockLine),
+    /// A line cannot be edited as the user doesn't hold its lock. 
+    LineNotLocked(user_activity::LockLine),
+    /// The text a user expected to replace doesn't match the line, 
+    /// contains the line and its current text. 
+    LineMismatch(user_activity::LockLine, String),
+    /// The activity isn't supported by the session's edit engine, 
+    /// contains the engine the session uses. 
+    WrongEngine(EditEngine),
+    /// A CRDT operation couldn't be merged. 
+    Crdt(CrdtError),
+    /// A text operation couldn't be applied. 
+    Ot(OtError),
+    /// The change would grow the project past `max_proj_size_kb`, contains 
+    /// the bytes the project currently uses and the limit. 
+    QuotaExceeded { used: usize, limit: usize },
+    /// The session is playing back a recording and can't be edited. 
+    ReadOnly,
+    /// The activity only applies to a session playing back a recording. 
+    NotPlayback,
+    /// A revision of a file isn't in its history, either dropped or not 
+    /// made yet. 
+    RevisionNotFound(usize),
+    /// The user has no changes left to undo. 
+    NothingToUndo,
+    /// The user has no undone changes left to redo. 
+    NothingToRedo,
+    /// The change being undone or redone has since been changed by 
+    /// someone else, so it's been dropped. 
+    Superseded,
+    /// The user's role doesn't allow the activity. 
+    NotPermitted,
+    /// No user in the session has the given id. 
+    UserNotFound(String)
 }
 
 /// Serialisable responses to directory operations. 
@@ -166,6 +206,31 @@ impl Directory {
         }
     }
 
+    /// Builds a directory from a `DirectoryDTO`, such as one restored from 
+    /// a snapshot. 
+    pub fn from_dto(dto: DirectoryDTO) -> Self {
+        let files = dto.files.into_iter()
+            .map(|(name, lines)| (name, File::from_lines(lines)))
+            .collect();
+        let subdirs = dto.subdirs.into_iter()
+            .map(|(name, dir)| (name, Directory::from_dto(dir)))
+            .collect();
+
+        Directory {
+            files: RwLock::new(files),
+            subdirs: RwLock::new(subdirs)
+        }
+    }
+
+    /// Replaces every file and subdirectory with those of a `DirectoryDTO`. 
+    pub async fn replace_with_dto(&self, dto: DirectoryDTO) {
+        let Directory { files, subdirs } = Directory::from_dto(dto);
+        let mut own_files = self.files.write().await;
+        let mut own_subdirs = self.subdirs.write().await;
+        *own_files = files.into_inner();
+        *own_subdirs = subdirs.into_inner();
+    }
+
     /// Asnchronously transverses through the subdirs, reading and 
     /// copying each line of each file into a `DirectoryDTO`.
     #[async_recursion]
@@ -180,6 +245,19 @@ impl Directory {
         }
     }
 
+    /// Asnchronously transverses through the subdirs, listing the names of 
+    /// each file and subdirectory in a `DirectoryTree`. 
+    #[async_recursion]
+    pub async fn spool_tree(&self) -> DirectoryTree {
+        let mut files: Vec<String> = self.files.read().await.keys().cloned().collect();
+        files.sort();
+        let subdirs = self.subdirs.read().await;
+        let subdir_futures = subdirs.iter()
+            .map(|(name, dir)| async { (name.clone(), dir.spool_tree().await) });
+        let subdirs = join_all(subdir_futures).await.into_iter().collect();
+        DirectoryTree { files, subdirs }
+    }
+
     async fn spool_subdirs(&self) -> HashMap<String, DirectoryDTO> {
         let subdirs = self.subdirs.read().await;
         let subdir_futures = subdirs.iter()
@@ -210,6 +288,20 @@ impl Directory {
         (file_name.clone(), lines)
     }
 
+    /// Asynchronously totals the size of every file in this directory and 
+    /// its subdirs. 
+    #[async_recursion]
+    pub async fn byte_size(&self) -> usize {
+        let mut size = 0;
+        for file in self.files.read().await.values() {
+            size += file.byte_size().await;
+        }
+        for dir in self.subdirs.read().await.values() {
+            size += dir.byte_size().await;
+        }
+        size
+    }
+
     /// Asnchronously transverses through the subdirs, reading and 
     /// copying each line of each file into a `DirectoryDTO`.
     #[async_recursion]
@@ -248,12 +340,79 @@ impl Directory {
         let (file_name, file) = key_vals;
         (file_name.clone(), file.clone().await)
     }
+
+    /// Asynchronously transverses through the subdirs, clearing the lock of 
+    /// every line where `should_release` returns true. 
+    /// 
+    /// # Arguments
+    /// * `path` - The path of this directory from the root. 
+    /// * `should_release` - Decides if a given line's lock should be cleared. 
+    /// 
+    /// # Returns 
+    /// Each line that was unlocked. 
+    #[async_recursion]
+    pub async fn release_locks(
+        &self, 
+        path: Vec<String>, 
+        should_release: &(dyn Fn(&FileLineData) -> bool + Sync)
+    ) -> Vec<FileLineUnlocked> {
+        let mut released = vec![];
+        let files = self.files.read().await;
+        for (name, file) in files.iter() {
+            let mut filepath = path.clone();
+            filepath.push(name.clone());
+            let unlocked = file.release_locks(should_release).await
+                .into_iter()
+                .map(|(add_no, user_id)| FileLineUnlocked { 
+                    filepath: filepath.clone(), 
+                    add_no, 
+                    user_id 
+                });
+            released.extend(unlocked);
+        }
+        let subdirs = self.subdirs.read().await;
+        for (name, dir) in subdirs.iter() {
+            let mut dirpath = path.clone();
+            dirpath.push(name.clone());
+            released.extend(dir.release_locks(dirpath, should_release).await);
+        }
+        released
+    }
+}
+
+/// The names of the files and subdirectories in a directory, without 
+/// their contents. 
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryTree {
+    pub files: Vec<String>,
+    pub subdirs: BTreeMap<String, DirectoryTree>
 }
 
 /// A data transfer object allowing copies of whole 
 /// directories to be serialised and transmitted. 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct DirectoryDTO {
     pub files: HashMap<String, Vec<String>>,
     pub subdirs: HashMap<String, DirectoryDTO>
 }
+
+impl DirectoryDTO {
+    /// Gets a nested subdirectory, creating any missing directories on the way. 
+    pub fn subdir_mut<S: AsRef<str>>(&mut self, names: &[S]) -> &mut DirectoryDTO {
+        names.iter().fold(self, |dir, name| {
+            dir.subdirs.entry(name.as_ref().to_owned()).or_default()
+        })
+    }
+
+    /// Totals the size of every file in the project. 
+    pub fn byte_size(&self) -> usize {
+        let files: usize = self.files.values()
+            .flat_map(|lines| lines.iter())
+            .map(|line| line_size(line))
+            .sum();
+        let subdirs: usize = self.subdirs.values()
+            .map(DirectoryDTO::byte_size)
+            .sum();
+        files + subdirs
+    }
+}
@@ -1,17 +1,21 @@
 use warp::reject::Reject;
 
+#[allow(dead_code)]
 pub trait LocalReject : Reject {}
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct InternalServerError;
 impl Reject for InternalServerError {}
 impl LocalReject for InternalServerError {}
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct NotFound;
 impl Reject for NotFound {}
 impl LocalReject for NotFound {}
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct MaxCapacity;
 impl Reject for MaxCapacity {}
@@ -23,6 +27,23 @@ impl LocalReject for MaxCapacity {}
 pub enum CodealongError {
     InternalServerError,
     NotFound,
-    MaxCapacity
+    MaxCapacity,
+    /// An uploaded archive couldn't be unpacked. 
+    InvalidArchive,
+    /// An uploaded project is larger than `max_proj_size_kb` or has too many files. 
+    TooLarge,
+    /// A request was missing valid credentials. 
+    Unauthorized,
+    /// A user tried to join a session they're already in. 
+    AlreadyJoined,
+    /// A user tried to join a session they're banned from. 
+    Banned,
+    /// A request was malformed, such as a file path that isn't valid. 
+    BadRequest,
+    /// A request isn't allowed, such as editing a read-only session. 
+    Forbidden,
+    /// A request clashes with the state of the project, such as a file 
+    /// that already exists or a line locked by a user. 
+    Conflict
 }
 impl Reject for CodealongError {}
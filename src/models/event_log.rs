@@ -0,0 +1,102 @@
+use super::session_activity::{SessionActivity, OutgoingActivity};
+
+use std::collections::VecDeque;
+
+
+/// The most broadcast activities held for replay per session.
+pub const MAX_EVENT_LOG: usize = 1024;
+
+struct LogEntry {
+    seq: u64,
+    activity: SessionActivity,
+    /// The user the activity wasn't sent to, such as the author of an edit
+    excluded: Option<String>
+}
+
+/// The activities broadcast in a session, numbered in the order they were
+/// sent so a user can catch up on what they missed. The oldest entries are
+/// dropped once `MAX_EVENT_LOG` is reached.
+#[derive(Default)]
+pub struct EventLog {
+    entries: VecDeque<LogEntry>,
+    last_seq: u64
+}
+
+impl EventLog {
+    /// The sequence number of the latest activity, `0` if there's none.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Appends a broadcast activity, returning its sequence number.
+    pub fn push(&mut self, activity: SessionActivity, excluded: Option<&str>) -> u64 {
+        self.last_seq += 1;
+        self.entries.push_back(LogEntry {
+            seq: self.last_seq,
+            activity,
+            excluded: excluded.map(str::to_owned)
+        });
+        if self.entries.len() > MAX_EVENT_LOG {
+            self.entries.pop_front();
+        }
+        self.last_seq
+    }
+
+    /// Gets every activity a user was sent after `seq`, `None` if `seq` is
+    /// newer than the log or some of those activities are no longer held.
+    pub fn since(&self, seq: u64, user_id: &str) -> Option<Vec<OutgoingActivity>> {
+        let oldest = self.entries.front().map(|e| e.seq).unwrap_or(self.last_seq + 1);
+        if seq > self.last_seq || seq + 1 < oldest {
+            return None;
+        }
+        let missed = self.entries.iter()
+            .filter(|e| e.seq > seq && e.excluded.as_deref() != Some(user_id))
+            .map(|e| OutgoingActivity::stamped(e.seq, e.activity.clone()))
+            .collect();
+        Some(missed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{directory::DirectoryDTO, server_activity::ServerActivity};
+
+    fn activity() -> SessionActivity {
+        SessionActivity::ServerActivity(ServerActivity::CurrentProject(DirectoryDTO::default()))
+    }
+
+    fn seqs(missed: Option<Vec<OutgoingActivity>>) -> Option<Vec<u64>> {
+        missed.map(|m| m.into_iter().filter_map(|a| a.seq).collect())
+    }
+
+    #[test]
+    fn activities_are_numbered_in_order() {
+        let mut log = EventLog::default();
+        assert_eq!(log.last_seq(), 0);
+        assert_eq!(log.push(activity(), None), 1);
+        assert_eq!(log.push(activity(), Some("author")), 2);
+        assert_eq!(log.push(activity(), None), 3);
+        assert_eq!(log.last_seq(), 3);
+
+        assert_eq!(seqs(log.since(0, "user")), Some(vec![1, 2, 3]));
+        assert_eq!(seqs(log.since(1, "author")), Some(vec![3]));
+        assert_eq!(seqs(log.since(3, "user")), Some(vec![]));
+        assert_eq!(seqs(log.since(4, "user")), None);
+    }
+
+    #[test]
+    fn trimmed_activities_cant_be_caught_up_on() {
+        let mut log = EventLog::default();
+        for _ in 0..MAX_EVENT_LOG + 10 {
+            log.push(activity(), None);
+        }
+        let last = log.last_seq();
+        assert_eq!(last, (MAX_EVENT_LOG + 10) as u64);
+        // The oldest held activity is 11, so a user who saw 10 missed nothing dropped.
+        assert_eq!(seqs(log.since(10, "user")).map(|s| s.len()), Some(MAX_EVENT_LOG));
+        assert_eq!(seqs(log.since(9, "user")), None);
+        assert_eq!(seqs(log.since(0, "user")), None);
+        assert_eq!(seqs(log.since(last - 1, "user")), Some(vec![last]));
+    }
+}
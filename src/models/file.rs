@@ -1,10 +1,18 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use tokio::sync::RwLock;
 
 use serde::{Serialize, Deserialize};
 
+use super::crdt::{CrdtDoc, CrdtOp};
+use super::ot::{OtHistory, OtOps, OtError};
+use super::directory::DirError;
+use super::quota::{ProjectQuota, line_size, text_size};
+use super::history::RevisionHistory;
+
 use futures::future::join_all;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
 use tokio::sync::RwLockReadGuard;
 use tokio::sync::RwLockWriteGuard;
 
@@ -14,7 +22,37 @@ pub struct FileLineData {
     /// The data on the line 
     pub line: String,
     /// If a line is locked to a user, the unique lock id will be stored 
-    pub locked: Option<String>
+    pub locked: Option<String>,
+    /// When the lock holder last locked or edited the line 
+    #[serde(skip)]
+    pub locked_at: Option<Instant>
+}
+
+impl FileLineData {
+    /// Locks the line to a user, starting the lock's idle timer. 
+    pub fn lock(&mut self, user_id: &str) {
+        self.locked = Some(user_id.to_owned());
+        self.locked_at = Some(Instant::now());
+    }
+
+    /// Restarts the lock's idle timer. 
+    pub fn touch(&mut self) {
+        self.locked_at = Some(Instant::now());
+    }
+
+    /// Clears the lock, returning the user id that held it. 
+    pub fn unlock(&mut self) -> Option<String> {
+        self.locked_at = None;
+        self.locked.take()
+    }
+
+    /// Checks if the line has been locked without activity for longer than `timeout`. 
+    pub fn lock_expired(&self, timeout: Duration) -> bool {
+        match self.locked_at {
+            Some(at) => self.locked.is_some() && at.elapsed() >= timeout,
+            None => false
+        }
+    }
 }
 
 pub struct FileLine {
@@ -29,6 +67,7 @@ impl FileLine {
             line_data: RwLock::new(FileLineData {
                 line: s.to_owned(),
                 locked: None,
+                locked_at: None
             }),
             add_no: 0,
         }
@@ -38,15 +77,17 @@ impl FileLine {
             line_data: RwLock::new(FileLineData {
                 line: "".to_owned(),
                 locked: None,
+                locked_at: None
             }),
             add_no
         }
     }
-    pub fn _new_locked_at(add_no: usize, lock_id: &String) -> Self {
+    pub fn _new_locked_at(add_no: usize, lock_id: &str) -> Self {
         FileLine {
             line_data: RwLock::new(FileLineData {
                 line: "".to_owned(),
-                locked: Some(lock_id.clone())
+                locked: Some(lock_id.to_owned()),
+                locked_at: Some(Instant::now())
             }),
             add_no,
         }
@@ -65,7 +106,8 @@ impl Default for FileLine {
         FileLine { 
             line_data: RwLock::new(FileLineData {
                 line: "".to_owned(), 
-                locked: None 
+                locked: None,
+                locked_at: None
             }),
             add_no: 0, 
         }
@@ -80,28 +122,103 @@ pub struct FileLineLocked {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FileLineAdded {
+    pub filepath: Vec<String>,
+    pub add_no: usize,
+    /// The index the line was inserted at 
+    pub at: usize,
+    pub user_id: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileLineUnlocked {
+    pub filepath: Vec<String>,
     pub add_no: usize,
     pub user_id: String
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileLinesRemoved {
+    pub filepath: Vec<String>,
+    /// The `add_no` of each removed line, in file order 
+    pub add_nos: Vec<usize>,
+    pub user_id: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileLineUpdated {
+    pub filepath: Vec<String>,
+    pub add_no: usize,
+    pub user_id: String,
+    pub line: String
+}
+
+/// A line of a file with the number it was given when added. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NumberedLine {
+    pub add_no: usize,
+    pub line: String
+}
+
+/// Every line of a file in order, with their `add_no`s so a client can 
+/// address the lines in later activities. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileLines {
+    pub filepath: Vec<String>,
+    pub lines: Vec<NumberedLine>
+}
+
 pub struct File {
     pub line_count: AtomicUsize,
-    pub lines: RwLock<Vec<FileLine>>
+    pub lines: RwLock<Vec<FileLine>>,
+    /// Character level state for sessions using `EditEngine::Crdt`, 
+    /// seeded from the lines on first use 
+    pub crdt: RwLock<Option<CrdtDoc>>,
+    /// Committed text operations for sessions using `EditEngine::Ot` 
+    pub ot: RwLock<OtHistory>,
+    /// Recent changes to the file, for reviewing what was changed 
+    pub history: RwLock<RevisionHistory>
 }
 
 impl File {
-    pub async fn read(&self) -> RwLockReadGuard<Vec<FileLine>> {
+    pub async fn read(&self) -> RwLockReadGuard<'_, Vec<FileLine>> {
         self.lines.read().await
     }
 
-    pub async fn _write(&self) -> RwLockWriteGuard<Vec<FileLine>> {
+    pub async fn _write(&self) -> RwLockWriteGuard<'_, Vec<FileLine>> {
         self.lines.write().await
     }
     pub fn default_with(val: &str) -> Self {
         let line = vec![FileLine::new(val)];
         File {
             lines: RwLock::new(line),
-            line_count: AtomicUsize::new(0)
+            line_count: AtomicUsize::new(1),
+            crdt: RwLock::new(None),
+            ot: RwLock::new(OtHistory::default()),
+            history: RwLock::new(RevisionHistory::new(vec![NumberedLine { add_no: 0, line: val.to_owned() }]))
+        }
+    }
+
+    /// Creates a file from its lines, such as when restoring a `DirectoryDTO`. 
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        let line_count = lines.len();
+        let history = RevisionHistory::new(lines.iter()
+            .enumerate()
+            .map(|(add_no, line)| NumberedLine { add_no, line: line.clone() })
+            .collect());
+        let lines = lines.iter()
+            .enumerate()
+            .map(|(add_no, s)| {
+                let mut line = FileLine::new(s);
+                line.add_no = add_no;
+                line
+            })
+            .collect();
+        File {
+            lines: RwLock::new(lines),
+            line_count: AtomicUsize::new(line_count),
+            crdt: RwLock::new(None),
+            ot: RwLock::new(OtHistory::default()),
+            history: RwLock::new(history)
         }
     }
 
@@ -113,23 +230,125 @@ impl File {
             FileLine { line_data, add_no: line.add_no }
         });
         let lines = join_all(line_futures).await;
+        let crdt = self.crdt.read().await.clone();
+        let ot = self.ot.read().await.clone();
+        let history = self.history.read().await.clone();
         File {
             lines: RwLock::new(lines),
-            line_count: AtomicUsize::new(self.line_count.load(Ordering::Acquire))
+            line_count: AtomicUsize::new(self.line_count.load(Ordering::Acquire)),
+            crdt: RwLock::new(crdt),
+            ot: RwLock::new(ot),
+            history: RwLock::new(history)
+        }
+    }
+
+    /// Totals the size of the file's lines. 
+    pub async fn byte_size(&self) -> usize {
+        let lines = self.lines.read().await;
+        let mut size = 0;
+        for line in lines.iter() {
+            size += line_size(&line.line_data.read().await.line);
+        }
+        size
+    }
+
+    /// Reads all the lines of the file, joined by newlines. 
+    pub async fn text(&self) -> String {
+        let lines = self.lines.read().await;
+        File::lines_text(&lines).await
+    }
+
+    /// Reads the given lines, joined by newlines, for when the file's 
+    /// lines are already held. 
+    pub async fn lines_text(lines: &[FileLine]) -> String {
+        let line_futures = lines.iter().map(|line| line.get());
+        join_all(line_futures).await.join("\n")
+    }
+
+    /// Adds a change to the file's revision history, the lines it changed 
+    /// are found by comparing them with the previous revision. 
+    /// 
+    /// # Arguments
+    /// * `lines` - The file's lines after the change, as held by the caller. 
+    pub async fn record_revision(&self, lines: &[FileLine], user_id: &str) -> usize {
+        let line_futures = lines.iter().map(|line| async {
+            NumberedLine { add_no: line.add_no, line: line.get().await }
+        });
+        let lines = join_all(line_futures).await;
+        self.history.write().await.push(user_id, lines)
+    }
+
+    /// Replaces the whole content of the file, keeping the lines which are 
+    /// unchanged, each changed line is given a fresh `add_no`. 
+    pub async fn set_text(&self, text: &str) {
+        let mut lines = self.lines.write().await;
+        let old = join_all(lines.iter().map(|l| l.get())).await;
+        let new: Vec<String> = text.split('\n').map(str::to_owned).collect();
+        let mut old_lines = std::mem::take(&mut *lines).into_iter();
+        for diff in capture_diff_slices(Algorithm::Myers, &old, &new) {
+            if let DiffOp::Equal { len, .. } = diff {
+                lines.extend(old_lines.by_ref().take(len));
+                continue;
+            }
+            old_lines.by_ref().take(diff.old_range().len()).for_each(drop);
+            lines.extend(new[diff.new_range()].iter().map(|s| {
+                let mut line = FileLine::new(s);
+                line.add_no = self.line_count.fetch_add(1, Ordering::Relaxed);
+                line
+            }));
+        }
+    }
+
+    /// Gets a copy of the file's CRDT, seeding it from the lines if needed. 
+    pub async fn crdt_state(&self) -> CrdtDoc {
+        let mut crdt = self.crdt.write().await;
+        if crdt.is_none() {
+            *crdt = Some(CrdtDoc::from_text(&self.text().await));
         }
+        crdt.clone().unwrap_or_default()
     }
 
-    pub async fn insert_return_new_line(&self, at: usize, user_id: &String) -> (FileLineAdded, usize) {
+    /// Merges character level operations into the file's CRDT, either all 
+    /// of the operations are applied or none are, the lines are then 
+    /// rebuilt from the merged text. 
+    /// 
+    /// # Returns 
+    /// The operations that changed the file, skipping any already applied. 
+    pub async fn apply_crdt_ops(
+        &self, 
+        ops: &[CrdtOp], 
+        quota: &ProjectQuota
+    ) -> Result<Vec<CrdtOp>, DirError> {
+        let mut crdt = self.crdt.write().await;
+        let mut doc = match crdt.as_ref() {
+            Some(doc) => doc.clone(),
+            None => CrdtDoc::from_text(&self.text().await)
+        };
+        let mut applied = vec![];
+        for op in ops {
+            if doc.apply(op).map_err(DirError::Crdt)? {
+                applied.push(op.clone());
+            }
+        }
+        let text = doc.text();
+        quota.resize(self.byte_size().await, text_size(&text))?;
+        self.set_text(&text).await;
+        *crdt = Some(doc);
+        Ok(applied)
+    }
+
+    pub async fn insert_return_new_line(
+        &self, 
+        filepath: &[String], 
+        at: usize, 
+        user_id: &str
+    ) -> (FileLineAdded, usize) {
         let mut lines = self._write().await;
         let len = lines.len();
         let add_no = self.line_count.fetch_add(1, Ordering::Relaxed);
         
         let line = FileLine::_new_locked_at(add_no, user_id);
-        let line_copy = FileLineAdded {
-            add_no: line.add_no,
-            user_id: user_id.clone()
-        };
-        let inserted_at = if at <= len {
+        let inserted_at = if at >= len {
             lines.push(line);
             len
         }
@@ -137,7 +356,109 @@ impl File {
             lines.insert(at, line);
             at
         };
+        let line_copy = FileLineAdded {
+            filepath: filepath.to_vec(),
+            add_no,
+            at: inserted_at,
+            user_id: user_id.to_owned()
+        };
         (line_copy, inserted_at)
     }
 
+    /// Copies every line of the file with its `add_no`. 
+    pub async fn numbered_lines(&self) -> Vec<NumberedLine> {
+        let lines = self.lines.read().await;
+        let line_futures = lines.iter().map(|line| async {
+            NumberedLine { add_no: line.add_no, line: line.get().await }
+        });
+        join_all(line_futures).await
+    }
+
+    /// Gets the file's current revision and text. 
+    pub async fn ot_state(&self) -> (usize, String) {
+        let ot = self.ot.read().await;
+        (ot.revision(), self.text().await)
+    }
+
+    /// Transforms a text operation against every operation committed since 
+    /// `base_revision`, then applies and commits it. 
+    /// 
+    /// # Returns 
+    /// The file's new revision and the transformed operation. 
+    pub async fn apply_text_operation(
+        &self, 
+        base_revision: usize, 
+        ops: OtOps,
+        quota: &ProjectQuota
+    ) -> Result<(usize, OtOps), DirError> {
+        let mut ot = self.ot.write().await;
+        let concurrent = match ot.since(base_revision) {
+            Some(v) => v,
+            None => return Err(DirError::Ot(OtError::RevisionOutOfRange(ot.revision())))
+        };
+        let mut ops = ops;
+        for committed in concurrent {
+            let (transformed, _) = ops.transform(committed).map_err(DirError::Ot)?;
+            ops = transformed;
+        }
+        let text = ops.apply(&self.text().await).map_err(DirError::Ot)?;
+        quota.resize(self.byte_size().await, text_size(&text))?;
+        self.set_text(&text).await;
+        let revision = ot.push(ops.clone());
+        Ok((revision, ops))
+    }
+
+    /// Clears the lock on every line where `should_release` returns true, 
+    /// returning the `add_no` and previous lock holder of each. 
+    pub async fn release_locks<F>(&self, should_release: F) -> Vec<(usize, String)> 
+    where 
+        F: Fn(&FileLineData) -> bool
+    {
+        let lines = self.lines.read().await;
+        let mut released = vec![];
+        for line in lines.iter() {
+            let mut line_data = line.line_data.write().await;
+            if !should_release(&line_data) {
+                continue;
+            }
+            if let Some(user_id) = line_data.unlock() {
+                released.push((line.add_no, user_id));
+            }
+        }
+        released
+    }
+
+    /// Finds the index of a line by the number it was given when added to the file. 
+    pub fn find_line_index(lines: &[FileLine], add_no: usize) -> Option<usize> {
+        lines.iter().position(|l| l.add_no == add_no)
+    }
+
+    /// Finds a line by the number it was given when added to the file. 
+    pub fn find_line(lines: &[FileLine], add_no: usize) -> Option<&FileLine> {
+        lines.iter().find(|l| l.add_no == add_no)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn add_nos(file: &File) -> Vec<usize> {
+        file.read().await.iter().map(|l| l.add_no).collect()
+    }
+
+    #[tokio::test]
+    async fn set_text_keeps_unchanged_lines() {
+        let file = File::default_with("a");
+        file.set_text("a\nb\nc").await;
+        assert_eq!(add_nos(&file).await, vec![0, 1, 2]);
+
+        file.set_text("a\nB\nc\nd").await;
+        assert_eq!(file.text().await, "a\nB\nc\nd");
+        assert_eq!(add_nos(&file).await, vec![0, 3, 2, 4]);
+
+        file.set_text("c\nd").await;
+        assert_eq!(file.text().await, "c\nd");
+        assert_eq!(add_nos(&file).await, vec![2, 4]);
+    }
 }
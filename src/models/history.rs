@@ -0,0 +1,262 @@
+use super::file::NumberedLine;
+use crate::utils::time::now_secs;
+
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize};
+
+use similar::TextDiff;
+
+
+/// The most revisions kept per file, older ones are dropped. 
+pub const MAX_FILE_REVISIONS: usize = 128;
+
+/// The most bytes of changed text kept per file, older revisions are 
+/// dropped once their changes add up to more. 
+pub const MAX_HISTORY_BYTES: usize = 256 * 1024;
+
+/// A change made to a file. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileRevision {
+    pub rev: usize,
+    /// The user who made the change, `None` for the file's original text 
+    pub user_id: Option<String>,
+    /// The `add_no` of the first line changed, or of the first line removed 
+    /// if lines were only removed, `None` if the text didn't change 
+    pub add_no: Option<usize>,
+    /// Seconds since the unix epoch when the change was made 
+    pub at: u64,
+    /// The text of the changed lines before and after 
+    pub old: String,
+    pub new: String,
+    /// Where the changed lines start in the file 
+    #[serde(skip)]
+    index: usize,
+    #[serde(skip)]
+    old_len: usize,
+    #[serde(skip)]
+    new_len: usize
+}
+
+impl FileRevision {
+    fn byte_size(&self) -> usize {
+        self.old.len() + self.new.len()
+    }
+
+    /// Turns the text after this revision back into the text before it. 
+    fn revert(&self, lines: &mut Vec<String>) {
+        let old = match self.old_len {
+            0 => vec![],
+            _ => self.old.split('\n').map(str::to_owned).collect()
+        };
+        lines.splice(self.index..self.index + self.new_len, old);
+    }
+}
+
+/// The revisions of a file still held, sent in reply to `FileHistory`. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileRevisions {
+    pub path: Vec<String>,
+    pub revisions: Vec<FileRevision>
+}
+
+/// A unified diff of a file, sent in reply to `FileDiff`. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileDiffed {
+    pub path: Vec<String>,
+    pub from_rev: usize,
+    pub to_rev: usize,
+    pub diff: String
+}
+
+/// The recent revisions of a file, numbered in the order they were made. 
+/// 
+/// Only the file's current lines are kept whole, each revision holds just 
+/// the lines it changed, so older text is rebuilt by reverting revisions. 
+#[derive(Clone)]
+pub struct RevisionHistory {
+    revisions: VecDeque<FileRevision>,
+    next_rev: usize,
+    /// The file's lines as of the newest revision 
+    current: Vec<NumberedLine>,
+    /// The size of every held revision's changed text 
+    bytes: usize
+}
+
+impl RevisionHistory {
+    /// Starts a history from a file's original lines as revision `0`. 
+    pub fn new(lines: Vec<NumberedLine>) -> Self {
+        let original = FileRevision {
+            rev: 0,
+            user_id: None,
+            add_no: None,
+            at: now_secs(),
+            old: String::new(),
+            new: String::new(),
+            index: 0,
+            old_len: 0,
+            new_len: 0
+        };
+        RevisionHistory { 
+            revisions: VecDeque::from([original]), 
+            next_rev: 1, 
+            current: lines, 
+            bytes: 0 
+        }
+    }
+
+    /// Adds a change, found by comparing the file's lines with those of the 
+    /// newest revision. Old revisions are dropped while the history holds 
+    /// too many or too large changes. 
+    /// 
+    /// # Returns 
+    /// The number of the new revision. 
+    pub fn push(&mut self, user_id: &str, lines: Vec<NumberedLine>) -> usize {
+        let (index, old_len, new_len) = changed_span(&self.current, &lines);
+        let old = &self.current[index..index + old_len];
+        let new = &lines[index..index + new_len];
+        let add_no = new.first().or(old.first()).map(|l| l.add_no);
+        let rev = self.next_rev;
+        self.next_rev += 1;
+        let revision = FileRevision {
+            rev,
+            user_id: Some(user_id.to_owned()),
+            add_no,
+            at: now_secs(),
+            old: join_lines(old),
+            new: join_lines(new),
+            index,
+            old_len,
+            new_len
+        };
+        self.bytes += revision.byte_size();
+        self.revisions.push_back(revision);
+        self.current = lines;
+        while self.revisions.len() > MAX_FILE_REVISIONS 
+            || (self.bytes > MAX_HISTORY_BYTES && self.revisions.len() > 1) {
+            if let Some(dropped) = self.revisions.pop_front() {
+                self.bytes -= dropped.byte_size();
+            }
+        }
+        rev
+    }
+
+    pub fn revisions(&self) -> Vec<FileRevision> {
+        self.revisions.iter().cloned().collect()
+    }
+
+    /// Rebuilds the text of the file at a revision, `None` if it's been 
+    /// dropped or not made yet. 
+    pub fn text_at(&self, rev: usize) -> Option<String> {
+        let oldest = self.revisions.front()?.rev;
+        if rev < oldest || rev >= self.next_rev {
+            return None;
+        }
+        let mut lines: Vec<String> = self.current.iter().map(|l| l.line.clone()).collect();
+        for revision in self.revisions.iter().rev().take_while(|r| r.rev > rev) {
+            revision.revert(&mut lines);
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// Builds a unified diff of the file between two revisions. 
+    /// 
+    /// # Returns 
+    /// * `Err(rev)` - If either revision isn't in the history. 
+    /// * `Ok(diff)` - The diff, empty if the text is the same. 
+    pub fn diff(&self, name: &str, from_rev: usize, to_rev: usize) -> Result<String, usize> {
+        let from = self.text_at(from_rev).ok_or(from_rev)?;
+        let to = self.text_at(to_rev).ok_or(to_rev)?;
+        // Each line ends with a newline when stored, so the last isn't
+        // reported as missing one.
+        let from = format!("{}\n", from);
+        let to = format!("{}\n", to);
+        let diff = TextDiff::from_lines(&from, &to)
+            .unified_diff()
+            .header(&format!("{}@{}", name, from_rev), &format!("{}@{}", name, to_rev))
+            .to_string();
+        Ok(diff)
+    }
+}
+
+/// Finds the lines that differ between two versions of a file, as where 
+/// they start and how many there are in each, after skipping the lines 
+/// both begin and end with. 
+fn changed_span(old: &[NumberedLine], new: &[NumberedLine]) -> (usize, usize, usize) {
+    let prefix = old.iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a.line == b.line)
+        .count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.line == b.line)
+        .count();
+    (prefix, old.len() - prefix - suffix, new.len() - prefix - suffix)
+}
+
+fn join_lines(lines: &[NumberedLine]) -> String {
+    lines.iter()
+        .map(|l| l.line.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(lines: &[(usize, &str)]) -> Vec<NumberedLine> {
+        lines.iter()
+            .map(|(add_no, line)| NumberedLine { add_no: *add_no, line: (*line).to_owned() })
+            .collect()
+    }
+
+    #[test]
+    fn push_records_the_changed_lines() {
+        let mut history = RevisionHistory::new(numbered(&[(0, "a"), (1, "b"), (2, "c")]));
+        history.push("u", numbered(&[(0, "a"), (1, "B"), (2, "c")]));
+        history.push("u", numbered(&[(0, "a"), (1, "B"), (3, "x"), (4, "y"), (2, "c")]));
+        history.push("u", numbered(&[(0, "a"), (2, "c")]));
+
+        let revisions = history.revisions();
+        let changes: Vec<(Option<usize>, &str, &str)> = revisions.iter()
+            .map(|r| (r.add_no, r.old.as_str(), r.new.as_str()))
+            .collect();
+        assert_eq!(changes, vec![
+            (None, "", ""),
+            (Some(1), "b", "B"),
+            (Some(3), "", "x\ny"),
+            (Some(1), "B\nx\ny", "")
+        ]);
+    }
+
+    #[test]
+    fn text_at_rebuilds_older_revisions() {
+        let mut history = RevisionHistory::new(numbered(&[(0, "a"), (1, "b")]));
+        history.push("u", numbered(&[(0, "a"), (1, "b"), (2, "")]));
+        history.push("u", numbered(&[(0, "a"), (1, "b"), (2, "c")]));
+        history.push("u", numbered(&[(1, "b"), (2, "c")]));
+
+        assert_eq!(history.text_at(0).as_deref(), Some("a\nb"));
+        assert_eq!(history.text_at(1).as_deref(), Some("a\nb\n"));
+        assert_eq!(history.text_at(2).as_deref(), Some("a\nb\nc"));
+        assert_eq!(history.text_at(3).as_deref(), Some("b\nc"));
+        assert_eq!(history.text_at(4), None);
+    }
+
+    #[test]
+    fn oldest_revisions_are_dropped() {
+        let mut history = RevisionHistory::new(numbered(&[(0, "")]));
+        for i in 1..=MAX_FILE_REVISIONS {
+            history.push("u", numbered(&[(0, &i.to_string())]));
+        }
+        assert_eq!(history.revisions().len(), MAX_FILE_REVISIONS);
+        assert_eq!(history.text_at(0), None);
+        assert_eq!(history.text_at(1).as_deref(), Some("1"));
+
+        let large = "x".repeat(MAX_HISTORY_BYTES);
+        history.push("u", numbered(&[(0, &large)]));
+        assert_eq!(history.revisions().len(), 1);
+        assert_eq!(history.text_at(MAX_FILE_REVISIONS + 1).as_deref(), Some(large.as_str()));
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use tokio::sync::broadcast;
+
+use serde::{Serialize, Deserialize};
+
+
+/// How many events a slow admin stream may fall behind by before it 
+/// starts missing them. 
+const EVENT_BUFFER: usize = 64;
+
+/// A change in the lifecycle of a session. 
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SessionEvent {
+    Created { session_id: String },
+    /// The last user left the session. 
+    Emptied { session_id: String },
+    /// The session was closed after sitting empty, `persisted` if it 
+    /// was kept so it can be restored. 
+    Reaped { session_id: String, persisted: bool }
+}
+
+impl fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionEvent::Created { session_id } => 
+                write!(f, "session {} created", session_id),
+            SessionEvent::Emptied { session_id } => 
+                write!(f, "session {} emptied", session_id),
+            SessionEvent::Reaped { session_id, persisted: true } => 
+                write!(f, "session {} reaped and persisted", session_id),
+            SessionEvent::Reaped { session_id, persisted: false } => 
+                write!(f, "session {} reaped", session_id)
+        }
+    }
+}
+
+/// Credentials for streaming session lifecycle events. 
+#[derive(Deserialize)]
+pub struct AdminQuery {
+    pub token: String
+}
+
+/// Logs session lifecycle events and broadcasts them to any admin streams. 
+#[derive(Clone)]
+pub struct SessionEvents {
+    sender: broadcast::Sender<SessionEvent>
+}
+
+impl Default for SessionEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        SessionEvents { sender }
+    }
+}
+
+impl SessionEvents {
+    pub fn emit(&self, event: SessionEvent) {
+        log::info!("{}", event);
+        // Having no admin streams listening isn't an error.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,110 @@
+use crate::utils::time::now_secs;
+
+use serde::{Serialize, Deserialize};
+
+
+/// The most tags a session may have, further tags are dropped. 
+pub const MAX_TAGS: usize = 8;
+/// The most characters kept of a tag. 
+pub const MAX_TAG_CHARS: usize = 32;
+/// The most characters kept of a session's title. 
+pub const MAX_TITLE_CHARS: usize = 100;
+/// The most characters kept of a session's description. 
+pub const MAX_DESCRIPTION_CHARS: usize = 1000;
+/// The most sessions returned in a page of the listing. 
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// What a session is about, shown in the session listing. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The name of the user who created the session, `None` if it was imported 
+    /// anonymously or restored from storage 
+    pub creator: Option<String>,
+    /// Seconds since the unix epoch when the session was created 
+    pub created_at: u64,
+    /// Lowercase tags such as the languages used, for filtering the listing 
+    pub tags: Vec<String>
+}
+
+impl Default for SessionMetadata {
+    fn default() -> Self {
+        SessionMetadata {
+            title: None,
+            description: None,
+            creator: None,
+            created_at: now_secs(),
+            tags: Vec::new()
+        }
+    }
+}
+
+impl SessionMetadata {
+    /// Builds a new session's metadata, trimming over long fields. 
+    /// 
+    /// # Arguments
+    /// * `tags` - Comma separated tags, as given in a query. 
+    pub fn new(
+        creator: Option<String>, 
+        title: Option<String>, 
+        description: Option<String>, 
+        tags: Option<&str>
+    ) -> Self {
+        let mut parsed: Vec<String> = Vec::new();
+        for tag in tags.unwrap_or_default().split(',') {
+            let tag: String = tag.trim().to_lowercase().chars().take(MAX_TAG_CHARS).collect();
+            if !tag.is_empty() && !parsed.contains(&tag) && parsed.len() < MAX_TAGS {
+                parsed.push(tag);
+            }
+        }
+        SessionMetadata {
+            title: title.map(|t| t.trim().chars().take(MAX_TITLE_CHARS).collect()),
+            description: description.map(|d| d.trim().chars().take(MAX_DESCRIPTION_CHARS).collect()),
+            creator,
+            tags: parsed,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options when listing sessions. 
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SessionListQuery {
+    /// The page to return, counting from `0` 
+    pub page: usize,
+    pub per_page: usize,
+    /// Only list sessions with this tag 
+    pub tag: Option<String>,
+    /// Only list sessions with at least this many free seats 
+    pub free_seats: usize
+}
+
+impl Default for SessionListQuery {
+    fn default() -> Self {
+        SessionListQuery { page: 0, per_page: 20, tag: None, free_seats: 1 }
+    }
+}
+
+/// A session as shown in the listing. 
+#[derive(Serialize, Deserialize)]
+pub struct SessionListing {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub metadata: SessionMetadata,
+    pub participants: usize,
+    pub max_participants: usize,
+    /// If users must give a password or invite token to join 
+    pub protected: bool
+}
+
+/// A page of the session listing, newest sessions first. 
+#[derive(Serialize, Deserialize)]
+pub struct SessionPage {
+    pub sessions: Vec<SessionListing>,
+    pub page: usize,
+    pub per_page: usize,
+    /// How many sessions matched, across every page 
+    pub total: usize
+}
@@ -5,4 +5,14 @@ pub mod user_activity;
 pub mod server_activity;
 pub mod directory;
 pub mod session_activity;
-pub mod file;
\ No newline at end of file
+pub mod file;
+pub mod crdt;
+pub mod ot;
+pub mod quota;
+pub mod lifecycle;
+pub mod event_log;
+pub mod recording;
+pub mod history;
+pub mod undo;
+pub mod auth;
+pub mod metadata;
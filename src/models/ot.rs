@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize};
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+
+/// The most committed operations a file keeps to transform late edits against.
+pub const MAX_OT_HISTORY: usize = 1024;
+
+/// A single component of a text operation, lengths are counted in chars.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum OtComponent {
+    /// Skips over the next `n` chars, keeping them.
+    Retain(usize),
+    /// Inserts text at the current position.
+    Insert(String),
+    /// Removes the next `n` chars.
+    Delete(usize)
+}
+
+/// Possible errors when applying or transforming an operation.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum OtError {
+    /// The operation doesn't span the whole text it's applied to.
+    LengthMismatch,
+    /// The base revision is newer than the file or older than its
+    /// history, contains the file's current revision.
+    RevisionOutOfRange(usize)
+}
+
+/// A text operation spanning the whole of a file, in the same
+/// form as ot.js operations.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(transparent)]
+pub struct OtOps(pub Vec<OtComponent>);
+
+impl OtOps {
+    pub fn retain(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(OtComponent::Retain(last)) = self.0.last_mut() {
+            *last += n;
+            return;
+        }
+        self.0.push(OtComponent::Retain(n));
+    }
+
+    /// Appends an insert, keeping inserts ahead of deletes so equal
+    /// operations always have the same components.
+    pub fn insert(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let len = self.0.len();
+        match self.0.last_mut() {
+            Some(OtComponent::Insert(last)) => last.push_str(s),
+            Some(OtComponent::Delete(_)) => {
+                if let Some(OtComponent::Insert(prev)) = len.checked_sub(2).and_then(|i| self.0.get_mut(i)) {
+                    prev.push_str(s);
+                } else {
+                    self.0.insert(len - 1, OtComponent::Insert(s.to_owned()));
+                }
+            },
+            _ => self.0.push(OtComponent::Insert(s.to_owned()))
+        }
+    }
+
+    pub fn delete(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(OtComponent::Delete(last)) = self.0.last_mut() {
+            *last += n;
+            return;
+        }
+        self.0.push(OtComponent::Delete(n));
+    }
+
+    /// Builds the operation turning `old` into `new`.
+    pub fn between(old: &str, new: &str) -> Self {
+        let old: Vec<char> = old.chars().collect();
+        let new: Vec<char> = new.chars().collect();
+        let mut ops = OtOps::default();
+        for diff in capture_diff_slices(Algorithm::Myers, &old, &new) {
+            match diff {
+                DiffOp::Equal { len, .. } => ops.retain(len),
+                DiffOp::Delete { old_len, .. } => ops.delete(old_len),
+                DiffOp::Insert { new_index, new_len, .. } =>
+                    ops.insert(&new[new_index..new_index + new_len].iter().collect::<String>()),
+                DiffOp::Replace { old_len, new_index, new_len, .. } => {
+                    ops.insert(&new[new_index..new_index + new_len].iter().collect::<String>());
+                    ops.delete(old_len);
+                }
+            }
+        }
+        ops
+    }
+
+    /// The length of text this operation can be applied to.
+    pub fn base_len(&self) -> usize {
+        self.0.iter()
+            .map(|c| match c {
+                OtComponent::Retain(n) | OtComponent::Delete(n) => *n,
+                OtComponent::Insert(_) => 0
+            })
+            .sum()
+    }
+
+    /// Applies the operation to a text, returning the new text.
+    pub fn apply(&self, text: &str) -> Result<String, OtError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut at = 0;
+        let mut out = String::with_capacity(text.len());
+        for c in self.0.iter() {
+            match c {
+                OtComponent::Retain(n) => {
+                    let end = at + n;
+                    let kept = chars.get(at..end).ok_or(OtError::LengthMismatch)?;
+                    out.extend(kept);
+                    at = end;
+                },
+                OtComponent::Insert(s) => out.push_str(s),
+                OtComponent::Delete(n) => {
+                    if at + n > chars.len() {
+                        return Err(OtError::LengthMismatch);
+                    }
+                    at += n;
+                }
+            }
+        }
+        if at != chars.len() {
+            return Err(OtError::LengthMismatch);
+        }
+        Ok(out)
+    }
+
+    /// Transforms two concurrent operations made against the same text.
+    ///
+    /// # Returns
+    /// `(a', b')` such that applying `self` then `b'` gives the same text
+    /// as applying `other` then `a'`, inserts at the same position place
+    /// `self`'s text first.
+    pub fn transform(&self, other: &OtOps) -> Result<(OtOps, OtOps), OtError> {
+        if self.base_len() != other.base_len() {
+            return Err(OtError::LengthMismatch);
+        }
+        let mut a_prime = OtOps::default();
+        let mut b_prime = OtOps::default();
+        let mut a_iter = self.0.iter().cloned();
+        let mut b_iter = other.0.iter().cloned();
+        let mut a = a_iter.next();
+        let mut b = b_iter.next();
+        loop {
+            if let Some(OtComponent::Insert(s)) = &a {
+                a_prime.insert(s);
+                b_prime.retain(s.chars().count());
+                a = a_iter.next();
+                continue;
+            }
+            if let Some(OtComponent::Insert(s)) = &b {
+                a_prime.retain(s.chars().count());
+                b_prime.insert(s);
+                b = b_iter.next();
+                continue;
+            }
+            let (a_comp, b_comp) = match (&a, &b) {
+                (None, None) => break,
+                (Some(a_comp), Some(b_comp)) => (a_comp.clone(), b_comp.clone()),
+                _ => return Err(OtError::LengthMismatch)
+            };
+            let a_len = component_len(&a_comp);
+            let b_len = component_len(&b_comp);
+            let min = a_len.min(b_len);
+            match (&a_comp, &b_comp) {
+                (OtComponent::Retain(_), OtComponent::Retain(_)) => {
+                    a_prime.retain(min);
+                    b_prime.retain(min);
+                },
+                (OtComponent::Delete(_), OtComponent::Retain(_)) => a_prime.delete(min),
+                (OtComponent::Retain(_), OtComponent::Delete(_)) => b_prime.delete(min),
+                // Both deleted the same text, neither needs to delete it again.
+                _ => ()
+            }
+            a = shorten(a_comp, a_len - min).or_else(|| a_iter.next());
+            b = shorten(b_comp, b_len - min).or_else(|| b_iter.next());
+        }
+        Ok((a_prime, b_prime))
+    }
+}
+
+fn component_len(c: &OtComponent) -> usize {
+    match c {
+        OtComponent::Retain(n) | OtComponent::Delete(n) => *n,
+        OtComponent::Insert(s) => s.chars().count()
+    }
+}
+
+fn shorten(c: OtComponent, remaining: usize) -> Option<OtComponent> {
+    if remaining == 0 {
+        return None;
+    }
+    match c {
+        OtComponent::Retain(_) => Some(OtComponent::Retain(remaining)),
+        OtComponent::Delete(_) => Some(OtComponent::Delete(remaining)),
+        OtComponent::Insert(_) => None
+    }
+}
+
+/// The committed operations of a file, the file's revision is the
+/// number of operations ever committed.
+#[derive(Clone, Default)]
+pub struct OtHistory {
+    /// The revision of the oldest operation still held
+    pub base: usize,
+    pub ops: VecDeque<OtOps>
+}
+
+impl OtHistory {
+    pub fn revision(&self) -> usize {
+        self.base + self.ops.len()
+    }
+
+    /// Gets every operation committed since a revision, `None` if the
+    /// revision is newer than the file or no longer held.
+    pub fn since(&self, revision: usize) -> Option<impl Iterator<Item = &OtOps>> {
+        if revision < self.base || revision > self.revision() {
+            return None;
+        }
+        Some(self.ops.iter().skip(revision - self.base))
+    }
+
+    /// Commits an operation, returning the new revision.
+    pub fn push(&mut self, ops: OtOps) -> usize {
+        self.ops.push_back(ops);
+        if self.ops.len() > MAX_OT_HISTORY {
+            self.ops.pop_front();
+            self.base += 1;
+        }
+        self.revision()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TextOperationAck {
+    pub filepath: Vec<String>,
+    pub revision: usize
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TextOperationApplied {
+    pub filepath: Vec<String>,
+    pub user_id: String,
+    /// The revision of the file after this operation
+    pub revision: usize,
+    pub ops: OtOps
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileOtState {
+    pub filepath: Vec<String>,
+    pub revision: usize,
+    pub text: String
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(components: &[OtComponent]) -> OtOps {
+        let mut ops = OtOps::default();
+        for c in components {
+            match c {
+                OtComponent::Retain(n) => ops.retain(*n),
+                OtComponent::Insert(s) => ops.insert(s),
+                OtComponent::Delete(n) => ops.delete(*n)
+            }
+        }
+        ops
+    }
+
+    fn ins(s: &str) -> OtComponent {
+        OtComponent::Insert(s.to_owned())
+    }
+
+    #[test]
+    fn apply_checks_lengths() {
+        let edit = ops(&[OtComponent::Retain(2), ins("X"), OtComponent::Delete(1), OtComponent::Retain(1)]);
+        assert_eq!(edit.apply("abcd").ok(), Some("abXd".to_owned()));
+        assert!(matches!(edit.apply("abc"), Err(OtError::LengthMismatch)));
+        assert!(matches!(edit.apply("abcde"), Err(OtError::LengthMismatch)));
+    }
+
+    #[test]
+    fn transform_converges() {
+        let text = "hello world";
+        let cases = [
+            (ops(&[OtComponent::Retain(5), ins(","), OtComponent::Retain(6)]),
+             ops(&[OtComponent::Retain(6), OtComponent::Delete(5), ins("there")])),
+            (ops(&[OtComponent::Retain(5), ins("A"), OtComponent::Retain(6)]),
+             ops(&[OtComponent::Retain(5), ins("B"), OtComponent::Retain(6)])),
+            (ops(&[OtComponent::Retain(2), OtComponent::Delete(6), OtComponent::Retain(3)]),
+             ops(&[OtComponent::Retain(4), OtComponent::Delete(5), OtComponent::Retain(2)]))
+        ];
+        for (a, b) in cases.iter() {
+            let (a_prime, b_prime) = a.transform(b).ok().unwrap();
+            let via_a = b_prime.apply(&a.apply(text).ok().unwrap()).ok();
+            let via_b = a_prime.apply(&b.apply(text).ok().unwrap()).ok();
+            assert!(via_a.is_some());
+            assert_eq!(via_a, via_b);
+        }
+        let (a_prime, _) = cases[1].0.transform(&cases[1].1).ok().unwrap();
+        assert_eq!(a_prime.apply("helloB world").ok(), Some("helloAB world".to_owned()));
+
+        let short = ops(&[OtComponent::Retain(3)]);
+        assert!(matches!(cases[0].0.transform(&short), Err(OtError::LengthMismatch)));
+    }
+
+    #[test]
+    fn between_reaches_the_text() {
+        for (old, new) in [("hello world", "hello, there"), ("", "abc"), ("abc", ""), ("same", "same")] {
+            assert_eq!(OtOps::between(old, new).apply(old).ok(), Some(new.to_owned()));
+        }
+        assert_eq!(OtOps::between("same", "same"), ops(&[OtComponent::Retain(4)]));
+    }
+
+    #[test]
+    fn history_keeps_recent_revisions() {
+        let mut history = OtHistory::default();
+        for _ in 0..MAX_OT_HISTORY + 2 {
+            history.push(OtOps::default());
+        }
+        assert_eq!(history.revision(), MAX_OT_HISTORY + 2);
+        assert!(history.since(1).is_none());
+        assert!(history.since(MAX_OT_HISTORY + 3).is_none());
+        assert_eq!(history.since(2).map(|ops| ops.count()), Some(MAX_OT_HISTORY));
+    }
+}
@@ -0,0 +1,97 @@
+use super::directory::DirError;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Tracks the size of a session's project against `max_proj_size_kb`, 
+/// updated by each change rather than recounting the whole project. 
+/// 
+/// Sizes are in bytes of text, each line counts its trailing newline. 
+pub struct ProjectQuota {
+    used: AtomicUsize,
+    limit: usize
+}
+
+impl ProjectQuota {
+    pub fn new(used: usize, limit: usize) -> Self {
+        ProjectQuota { used: AtomicUsize::new(used), limit }
+    }
+
+    /// Reserves space for a change that grows the project. 
+    /// 
+    /// # Returns 
+    /// * `Err(DirError::QuotaExceeded)` - If the project would exceed the limit, nothing is reserved. 
+    /// * `Ok(())` - If the space was reserved. 
+    pub fn grow(&self, bytes: usize) -> Result<(), DirError> {
+        if bytes == 0 {
+            return Ok(());
+        }
+        self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            used.checked_add(bytes).filter(|total| *total <= self.limit)
+        })
+        .map(|_| ())
+        .map_err(|used| DirError::QuotaExceeded { used, limit: self.limit })
+    }
+
+    /// Frees the space of removed text. 
+    pub fn shrink(&self, bytes: usize) {
+        let _ = self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            Some(used.saturating_sub(bytes))
+        });
+    }
+
+    /// Accounts for text changing from `old` to `new` bytes, only growth 
+    /// is checked against the limit. 
+    pub fn resize(&self, old: usize, new: usize) -> Result<(), DirError> {
+        if new > old {
+            return self.grow(new - old);
+        }
+        self.shrink(old - new);
+        Ok(())
+    }
+}
+
+/// The size of a line of text, counted with its trailing newline. 
+pub fn line_size(line: &str) -> usize {
+    line.len() + 1
+}
+
+/// The size of a file's text once split into lines, the same as the sum 
+/// of `line_size` for each line. 
+pub fn text_size(text: &str) -> usize {
+    text.len() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn growth_is_limited() {
+        let quota = ProjectQuota::new(6, 10);
+        assert!(quota.grow(4).is_ok());
+        assert!(matches!(quota.grow(1), Err(DirError::QuotaExceeded { used: 10, limit: 10 })));
+        quota.shrink(3);
+        assert!(quota.grow(3).is_ok());
+        quota.shrink(100);
+        assert!(quota.grow(10).is_ok());
+        assert!(quota.grow(0).is_ok());
+    }
+
+    #[test]
+    fn resize_only_checks_growth() {
+        let quota = ProjectQuota::new(10, 10);
+        assert!(quota.resize(5, 2).is_ok());
+        assert!(matches!(quota.resize(2, 6), Err(DirError::QuotaExceeded { used: 7, .. })));
+        assert!(quota.resize(2, 5).is_ok());
+        assert!(quota.resize(0, 1).is_err());
+    }
+
+    #[test]
+    fn text_size_matches_line_sizes() {
+        let text = "one\n\nthree";
+        let lines: usize = text.split('\n').map(line_size).sum();
+        assert_eq!(text_size(text), lines);
+        assert_eq!(text_size(""), line_size(""));
+    }
+}
@@ -0,0 +1,69 @@
+use super::directory::DirectoryDTO;
+use super::session::{EditEngine, JoinPolicy};
+use super::user_activity::UserActivity;
+
+use serde::{Serialize, Deserialize};
+
+
+/// An entry in a session's recording. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordEntry {
+    /// Milliseconds since the unix epoch when the entry was recorded 
+    pub at_ms: u64,
+    pub event: RecordedEvent
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    /// The project as it was when recording started or resumed, such as 
+    /// after the session was restored. `join_policy` is `None` in recordings 
+    /// made before it was recorded. 
+    Start {
+        engine: EditEngine,
+        project: DirectoryDTO,
+        #[serde(default)]
+        join_policy: Option<JoinPolicy>
+    },
+    /// A user activity that was applied to the session. 
+    Activity { user_id: String, activity: UserActivity }
+}
+
+/// Options when playing back a recorded session. 
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PlaybackQuery {
+    /// How many times faster than real time to play 
+    pub speed: f64,
+    /// If playback waits for a viewer to start it 
+    pub paused: bool
+}
+
+impl Default for PlaybackQuery {
+    fn default() -> Self {
+        PlaybackQuery { speed: 1.0, paused: false }
+    }
+}
+
+/// Sent by a viewer of a playback session to control it, any field left 
+/// out is unchanged. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaybackControl {
+    /// How many times faster than real time to play 
+    #[serde(default)]
+    pub speed: Option<f64>,
+    /// Milliseconds from the start of the recording to jump to 
+    #[serde(default)]
+    pub seek_ms: Option<u64>,
+    #[serde(default)]
+    pub paused: Option<bool>
+}
+
+/// Where a playback session is in its recording, sent to the viewers 
+/// whenever it's changed or the recording ends. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaybackState {
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub speed: f64,
+    pub paused: bool
+}
@@ -15,3 +15,8 @@ impl Count {
         }
     }
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct NewSession {
+    pub session_id: String
+}
@@ -1,7 +1,13 @@
 use super::directory::DirectoryDTO;
 use super::directory::{DirError, DirectoryUpdated};
 use super::session_activity::SessionActivity;
-use super::file::{FileLineLocked, FileLineAdded};
+use super::crdt::{FileCrdtApplied, FileCrdtState};
+use super::ot::{TextOperationAck, TextOperationApplied, FileOtState};
+use super::session::{UserJoined, UserLeft, ResumeToken, RoleChanged, Invite, UserKicked, UserBanned, OwnershipTransferred};
+use super::recording::PlaybackState;
+use super::history::{FileRevisions, FileDiffed};
+use super::undo::FileRestored;
+use super::file::{FileLineLocked, FileLineUnlocked, FileLineAdded, FileLineUpdated, FileLinesRemoved, FileLines};
 
 use serde::{Serialize, Deserialize};
 
@@ -12,7 +18,30 @@ pub enum ServerActivity {
     DirectoryErr(DirError),
     DirectoryUpdate(DirectoryUpdated),
     LineLocked(FileLineLocked),
-    LineAdded(FileLineAdded)
+    LineUnlocked(FileLineUnlocked),
+    LineAdded(FileLineAdded),
+    LineUpdated(FileLineUpdated),
+    LinesRemoved(FileLinesRemoved),
+    FileLines(FileLines),
+    CrdtApplied(FileCrdtApplied),
+    CrdtState(FileCrdtState),
+    OtAck(TextOperationAck),
+    OtApplied(TextOperationApplied),
+    OtState(FileOtState),
+    UserJoined(UserJoined),
+    UserLeft(UserLeft),
+    /// Every user in the session, sent to a user when they join. 
+    UserList(Vec<UserJoined>),
+    ResumeToken(ResumeToken),
+    PlaybackState(PlaybackState),
+    FileHistory(FileRevisions),
+    FileDiff(FileDiffed),
+    FileRestored(FileRestored),
+    RoleChanged(RoleChanged),
+    Invite(Invite),
+    UserKicked(UserKicked),
+    UserBanned(UserBanned),
+    OwnershipTransferred(OwnershipTransferred)
 }
 
 impl ServerActivity {
@@ -1,44 +1,399 @@
-use super::session_activity::SessionActivity;
-use super::directory::Directory;
+use super::session_activity::OutgoingActivity;
+use super::event_log::EventLog;
+use super::directory::{Directory, DirectoryDTO};
+use super::quota::ProjectQuota;
+use super::recording::PlaybackControl;
+use super::undo::UndoHistory;
+use super::metadata::SessionMetadata;
+use crate::utils::storage::StoreWriter;
+use crate::utils::recording::Recorder;
+use crate::utils::archive::ArchiveFormat;
+use crate::utils::time::now_secs;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::sync::{mpsc, RwLock};
 
+use serde::{Serialize, Deserialize};
+
+
+/// How users edit the text of files in a session. 
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditEngine {
+    /// Users lock a line before replacing its text. 
+    #[default]
+    LineLock,
+    /// Users send character level inserts and deletes which are merged. 
+    Crdt,
+    /// Users send text operations against a file revision which are 
+    /// transformed against concurrent operations. 
+    Ot
+}
+
+/// Options when creating a new session. 
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct NewSessionQuery {
+    pub engine: EditEngine,
+    /// Name of the template to start the project from, rather than the 
+    /// default "helloworld.txt" project 
+    pub template: Option<String>,
+    /// Password users must give to join, taken from `PASSWORD_HEADER` 
+    #[serde(skip)]
+    pub password: Option<String>,
+    /// If users may only join with an invite token 
+    pub invite_only: bool,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Comma separated tags, such as the languages used 
+    pub tags: Option<String>
+}
+
+/// Options when creating a new session from an uploaded archive. 
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ImportQuery {
+    pub engine: EditEngine,
+    pub format: ArchiveFormat,
+    /// Password users must give to join, taken from `PASSWORD_HEADER` 
+    #[serde(skip)]
+    pub password: Option<String>,
+    /// If users may only join with an invite token 
+    pub invite_only: bool,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Comma separated tags, such as the languages used 
+    pub tags: Option<String>
+}
+
+/// The header a session's password is given in, when creating or joining 
+/// it, so it's kept out of URLs which may be logged. 
+pub const PASSWORD_HEADER: &str = "x-session-password";
+
+/// Credentials for joining a protected session, or listing it. 
+#[derive(Default)]
+pub struct SessionCredentials {
+    pub password: Option<String>,
+    pub invite: Option<String>
+}
+
+/// An invite token given in the query, as websocket clients can't set headers. 
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct InviteQuery {
+    pub invite: Option<String>
+}
+
+/// Who may join a session, an invite token lets a user join any session. 
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub enum JoinPolicy {
+    #[default]
+    Open,
+    /// Users must give the password, holds its hash from `tokens::password_hash` 
+    Password(String),
+    /// Users must have an invite token 
+    InviteOnly
+}
+
+impl JoinPolicy {
+    /// Checks if the session is hidden from the public listing. 
+    pub fn is_protected(&self) -> bool {
+        !matches!(self, JoinPolicy::Open)
+    }
+}
+
+/// Options when exporting a session's project. 
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ExportQuery {
+    pub format: ArchiveFormat
+}
+
+
+/// What a user may do in a session. 
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    /// Can do anything, including changing the roles of other users. 
+    Owner,
+    /// Can edit the project. 
+    #[default]
+    Editor,
+    /// Can only watch the project, receiving updates and syncing. 
+    Viewer
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer"
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        match s {
+            "owner" => Some(Role::Owner),
+            "editor" => Some(Role::Editor),
+            "viewer" => Some(Role::Viewer),
+            _ => None
+        }
+    }
+}
 
-#[allow(dead_code)]
 pub struct UserState {
-    pub sender: mpsc::UnboundedSender<SessionActivity>,
-    pub name: String
+    pub sender: mpsc::UnboundedSender<OutgoingActivity>,
+    pub name: String,
+    pub role: Role
 }
 
 impl UserState {
-    pub fn new(name: String, sender: mpsc::UnboundedSender<SessionActivity>) -> Self {
-        UserState { sender, name }
+    pub fn new(name: String, sender: mpsc::UnboundedSender<OutgoingActivity>) -> Self {
+        UserState { sender, name, role: Role::default() }
     }
 }
 
-#[derive(Default)]
+/// A user whose connection dropped, who may resume within the grace window. 
+pub struct DepartedUser {
+    /// Queues the activity sent to the user while they're away 
+    pub missed: mpsc::UnboundedReceiver<OutgoingActivity>,
+    pub left_at: Instant
+}
+
+/// A user connected to a session, sent when they join and in the list 
+/// of users given to each new user. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserJoined {
+    pub id: String,
+    pub name: String,
+    pub role: Role
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserLeft {
+    pub id: String
+}
+
+/// An invite token minted by an owner, users join with it by giving it 
+/// as `invite` when joining. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Invite {
+    pub token: String,
+    pub role: Role,
+    /// Seconds since the unix epoch when the token stops working 
+    pub expires_at: u64
+}
+
+/// Sent to every user when a user's role is changed. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleChanged {
+    pub id: String,
+    pub role: Role
+}
+
+/// A user an owner has banned, who's refused if they join with the same id 
+/// or name. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Ban {
+    pub id: String,
+    pub name: String
+}
+
+impl Ban {
+    pub fn refuses(&self, id: &str, name: &str) -> bool {
+        self.id == id || self.name == name
+    }
+}
+
+/// Sent to every user when an owner removes a user from the session, the 
+/// removed user is sent it before their connection is closed. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserKicked {
+    pub id: String
+}
+
+/// Sent to every user when an owner bans a user, the banned user is sent 
+/// it before their connection is closed. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserBanned {
+    pub id: String,
+    pub name: String
+}
+
+/// Sent to every user when an owner hands the session to another user, 
+/// `from` becomes an editor and `to` an owner. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OwnershipTransferred {
+    pub from: String,
+    pub to: String
+}
+
+/// Sent to a user when they join, presenting `token` to `/users/rejoin` 
+/// resumes their identity if their connection drops. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResumeToken {
+    pub user_id: String,
+    pub token: String
+}
+
 pub struct Session {
     pub rootdir: Directory,
-    pub users: RwLock<HashMap<String, UserState>>
+    pub users: RwLock<HashMap<String, UserState>>,
+    pub engine: EditEngine,
+    /// Where changes to `rootdir` are written through to 
+    pub store: StoreWriter,
+    /// The size of `rootdir` against `max_proj_size_kb` 
+    pub quota: Arc<ProjectQuota>,
+    /// When the last user left, `None` while users are connected 
+    pub emptied_at: RwLock<Option<Instant>>,
+    /// Users who've disconnected but can still resume, they're kept in 
+    /// `users` until they resume or their grace window passes 
+    pub departed: RwLock<HashMap<String, DepartedUser>>,
+    /// Every activity broadcast to the session's users 
+    pub log: RwLock<EventLog>,
+    /// Where applied user activities are recorded, `None` if recording is disabled 
+    pub recorder: Option<Recorder>,
+    /// Controls the task playing a recording into this session, `None` unless 
+    /// the session is a read-only playback 
+    pub playback: Option<mpsc::UnboundedSender<PlaybackControl>>,
+    /// The changes each user can undo and redo, by user id 
+    pub undo: RwLock<HashMap<String, UndoHistory>>,
+    pub join_policy: JoinPolicy,
+    /// Users refused when they join 
+    pub bans: RwLock<Vec<Ban>>,
+    pub metadata: SessionMetadata
 }
 
 impl Session {
     pub fn new(
         base_user_name: String, 
         base_user_id: String,
-        sender: mpsc::UnboundedSender<SessionActivity>
+        sender: mpsc::UnboundedSender<OutgoingActivity>,
+        rootdir: Directory,
+        engine: EditEngine,
+        store: StoreWriter,
+        quota: ProjectQuota
     ) -> Self {
+        let mut owner = UserState::new(base_user_name, sender);
+        owner.role = Role::Owner;
         let users = HashMap::from([
-            (base_user_id, UserState::new(base_user_name, sender))
+            (base_user_id, owner)
         ]);
         Session {
-            rootdir: Directory::new_with_file(),
-            users: RwLock::new(users)
+            rootdir,
+            users: RwLock::new(users),
+            engine,
+            store,
+            quota: Arc::new(quota),
+            emptied_at: RwLock::new(None),
+            departed: RwLock::new(HashMap::new()),
+            log: RwLock::new(EventLog::default()),
+            recorder: None,
+            playback: None,
+            undo: RwLock::new(HashMap::new()),
+            join_policy: JoinPolicy::Open,
+            bans: RwLock::new(Vec::new()),
+            metadata: SessionMetadata::default()
         }
     }
+
+    /// Copies the session's project and metadata into a serialisable snapshot. 
+    pub async fn to_snapshot(&self, id: &str) -> SessionSnapshot {
+        SessionSnapshot {
+            id: id.to_owned(),
+            engine: self.engine,
+            saved_at: now_secs(),
+            project: self.rootdir.spool_to_dto().await,
+            join_policy: self.join_policy.clone(),
+            bans: self.bans.read().await.clone(),
+            metadata: self.metadata.clone()
+        }
+    }
+
+    /// Copies what the session was set up with, to store with its project. 
+    pub async fn settings(&self) -> SessionSettings {
+        SessionSettings {
+            engine: self.engine,
+            join_policy: self.join_policy.clone(),
+            bans: self.bans.read().await.clone(),
+            metadata: self.metadata.clone()
+        }
+    }
+
+    /// Rebuilds a session from a stored project, without any connected users. 
+    /// 
+    /// # Arguments
+    /// * `max_bytes` - The most bytes the project may grow to. 
+    pub fn restore(
+        project: DirectoryDTO, 
+        engine: EditEngine, 
+        store: StoreWriter,
+        max_bytes: usize
+    ) -> Self {
+        let quota = Arc::new(ProjectQuota::new(project.byte_size(), max_bytes));
+        Session {
+            rootdir: Directory::from_dto(project),
+            users: RwLock::new(HashMap::new()),
+            engine,
+            store,
+            quota,
+            emptied_at: RwLock::new(Some(Instant::now())),
+            departed: RwLock::new(HashMap::new()),
+            log: RwLock::new(EventLog::default()),
+            recorder: None,
+            playback: None,
+            undo: RwLock::new(HashMap::new()),
+            join_policy: JoinPolicy::Open,
+            bans: RwLock::new(Vec::new()),
+            metadata: SessionMetadata::default()
+        }
+    }
+
+    /// Checks if the session is playing back a recording, rather than being 
+    /// edited by its users. 
+    pub fn is_playback(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Checks if the session has had no users for at least `grace`. 
+    pub async fn idle_for(&self, grace: Duration) -> bool {
+        if !self.users.read().await.is_empty() {
+            return false;
+        }
+        match *self.emptied_at.read().await {
+            Some(at) => at.elapsed() >= grace,
+            None => false
+        }
+    }
+}
+
+/// A serialisable copy of a session, used to persist it across restarts. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub id: String,
+    pub engine: EditEngine,
+    /// Seconds since the unix epoch when the snapshot was taken 
+    pub saved_at: u64,
+    pub project: DirectoryDTO,
+    #[serde(default)]
+    pub join_policy: JoinPolicy,
+    #[serde(default)]
+    pub bans: Vec<Ban>,
+    #[serde(default)]
+    pub metadata: SessionMetadata
+}
+
+/// What a session was set up with, stored alongside its project so a 
+/// session restored from storage alone keeps them. 
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSettings {
+    pub engine: EditEngine,
+    pub join_policy: JoinPolicy,
+    pub bans: Vec<Ban>,
+    pub metadata: SessionMetadata
 }
 
 pub type SessionStore = Arc<RwLock<HashMap<String, Session>>>;
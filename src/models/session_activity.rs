@@ -10,11 +10,43 @@ pub enum SessionActivity {
     ServerActivity(server_activity::ServerActivity),
 }
 
-#[allow(dead_code)]
+/// An activity as sent to a user, broadcasts are stamped with their 
+/// sequence number in the session's event log. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutgoingActivity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(flatten)]
+    pub activity: SessionActivity,
+    /// If the user's connection is closed once this is sent 
+    #[serde(skip)]
+    pub close: bool
+}
+
+impl OutgoingActivity {
+    pub fn unstamped(activity: SessionActivity) -> Self {
+        OutgoingActivity { seq: None, activity, close: false }
+    }
+
+    pub fn stamped(seq: u64, activity: SessionActivity) -> Self {
+        OutgoingActivity { seq: Some(seq), activity, close: false }
+    }
+
+    /// The last activity sent to a user before their connection is closed. 
+    pub fn closing(activity: SessionActivity) -> Self {
+        OutgoingActivity { seq: None, activity, close: true }
+    }
+}
+
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum SendTo {
     ToSameUser(SessionActivity),
     ToOtherUsers(SessionActivity),
     ToAllUsers(SessionActivity),
+    /// Several activities for every user, sent in order. 
+    ToAllUsersEach(Vec<SessionActivity>),
     ToSplit(SessionActivity, SessionActivity),
+    /// Already stamped activities for the same user, such as ones they missed. 
+    ToSameUserReplay(Vec<OutgoingActivity>),
     ToNone
 }
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use serde::{Serialize, Deserialize};
+
+
+/// The most changes each user can undo. 
+pub const MAX_UNDO: usize = 100;
+
+/// A change a user made that can be undone, lines are given by `add_no`. 
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// An empty line was added at index `at`. 
+    LineInserted { filepath: Vec<String>, at: usize, add_no: usize },
+    /// An empty line was removed from index `at`. 
+    LineRemoved { filepath: Vec<String>, at: usize, add_no: usize },
+    LineEdited { filepath: Vec<String>, add_no: usize, old: String, new: String },
+    FileCreated { path: Vec<String>, lines: Vec<String> },
+    FileDeleted { path: Vec<String>, lines: Vec<String> },
+    /// The file's lines were all written at once, from `old` to `new`. 
+    FileReplaced { path: Vec<String>, old: Vec<String>, new: Vec<String> },
+    /// The file at `path` was renamed to `name`. 
+    FileRenamed { path: Vec<String>, name: String }
+}
+
+impl Change {
+    /// Gets the change that reverts this one. 
+    pub fn inverse(self) -> Change {
+        match self {
+            Change::LineInserted { filepath, at, add_no } =>
+                Change::LineRemoved { filepath, at, add_no },
+            Change::LineRemoved { filepath, at, add_no } =>
+                Change::LineInserted { filepath, at, add_no },
+            Change::LineEdited { filepath, add_no, old, new } =>
+                Change::LineEdited { filepath, add_no, old: new, new: old },
+            Change::FileCreated { path, lines } =>
+                Change::FileDeleted { path, lines },
+            Change::FileDeleted { path, lines } =>
+                Change::FileCreated { path, lines },
+            Change::FileReplaced { path, old, new } =>
+                Change::FileReplaced { path, old: new, new: old },
+            Change::FileRenamed { mut path, name } => {
+                let old_name = path.pop().unwrap_or_default();
+                path.push(name);
+                Change::FileRenamed { path, name: old_name }
+            }
+        }
+    }
+
+    /// Points changes to a line at the `add_no` it was given when re-added. 
+    fn renumber(&mut self, file: &[String], from: usize, to: usize) {
+        match self {
+            Change::LineInserted { filepath, add_no, .. } |
+            Change::LineRemoved { filepath, add_no, .. } |
+            Change::LineEdited { filepath, add_no, .. }
+                if filepath == file && *add_no == from => *add_no = to,
+            _ => ()
+        }
+    }
+}
+
+/// The changes a user can undo and redo, most recent last. 
+#[derive(Default)]
+pub struct UndoHistory {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>
+}
+
+impl UndoHistory {
+    /// Adds a change the user has just made, which clears what they can redo. 
+    pub fn push(&mut self, change: Change) {
+        self.redo.clear();
+        self.push_undo(change);
+    }
+
+    pub fn push_undo(&mut self, change: Change) {
+        if self.undo.len() >= MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+    }
+
+    pub fn push_redo(&mut self, change: Change) {
+        self.redo.push(change);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Change> {
+        self.undo.pop_back()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Change> {
+        self.redo.pop()
+    }
+
+    /// Updates every change to a line that was re-added with a new `add_no`. 
+    pub fn renumber(&mut self, filepath: &[String], from: usize, to: usize) {
+        for change in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+            change.renumber(filepath, from, to);
+        }
+    }
+}
+
+/// A file given a whole new set of lines, such as a deleted file brought 
+/// back or one written through the file API, their `add_no` is their index. 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileRestored {
+    pub path: Vec<String>,
+    pub lines: Vec<String>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|p| (*p).to_owned()).collect()
+    }
+
+    #[test]
+    fn inverse_reverts_each_change() {
+        let changes = vec![
+            Change::LineInserted { filepath: path(&["a"]), at: 2, add_no: 7 },
+            Change::LineEdited { filepath: path(&["a"]), add_no: 7, old: "x".to_owned(), new: "y".to_owned() },
+            Change::FileCreated { path: path(&["b"]), lines: vec!["z".to_owned()] },
+            Change::FileRenamed { path: path(&["dir", "c"]), name: "d".to_owned() }
+        ];
+        for change in changes {
+            assert_eq!(change.clone().inverse().inverse(), change);
+        }
+
+        let inserted = Change::LineInserted { filepath: path(&["a"]), at: 2, add_no: 7 };
+        assert_eq!(inserted.inverse(), Change::LineRemoved { filepath: path(&["a"]), at: 2, add_no: 7 });
+        let edited = Change::LineEdited { filepath: path(&["a"]), add_no: 7, old: "x".to_owned(), new: "y".to_owned() };
+        assert_eq!(edited.inverse(), Change::LineEdited { filepath: path(&["a"]), add_no: 7, old: "y".to_owned(), new: "x".to_owned() });
+        let renamed = Change::FileRenamed { path: path(&["dir", "c"]), name: "d".to_owned() };
+        assert_eq!(renamed.inverse(), Change::FileRenamed { path: path(&["dir", "d"]), name: "c".to_owned() });
+    }
+
+    #[test]
+    fn push_clears_redo_and_drops_oldest() {
+        let mut history = UndoHistory::default();
+        for i in 0..=MAX_UNDO {
+            history.push(Change::LineInserted { filepath: path(&["a"]), at: i, add_no: i });
+        }
+        let last = history.pop_undo();
+        assert_eq!(last, Some(Change::LineInserted { filepath: path(&["a"]), at: MAX_UNDO, add_no: MAX_UNDO }));
+        history.push_redo(last.unwrap().inverse());
+
+        history.push(Change::FileCreated { path: path(&["b"]), lines: vec![] });
+        assert_eq!(history.pop_redo(), None);
+        let mut undone = 0;
+        while history.pop_undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO);
+    }
+
+    #[test]
+    fn renumber_follows_re_added_lines() {
+        let mut history = UndoHistory::default();
+        history.push(Change::LineEdited { filepath: path(&["a"]), add_no: 3, old: String::new(), new: "x".to_owned() });
+        history.push(Change::LineEdited { filepath: path(&["b"]), add_no: 3, old: String::new(), new: "y".to_owned() });
+        history.renumber(&path(&["a"]), 3, 9);
+
+        assert_eq!(history.pop_undo(), Some(Change::LineEdited { filepath: path(&["b"]), add_no: 3, old: String::new(), new: "y".to_owned() }));
+        assert_eq!(history.pop_undo(), Some(Change::LineEdited { filepath: path(&["a"]), add_no: 9, old: String::new(), new: "x".to_owned() }));
+    }
+}
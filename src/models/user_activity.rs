@@ -1,12 +1,18 @@
 use super::directory::DirectoryUpdated;
+use super::crdt::CrdtOp;
+use super::ot::OtOps;
+use super::session::{EditEngine, Role};
+use super::recording::PlaybackControl;
 use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FileChanged {
-    path: Vec<String>,
-    line: usize,
-    old: String,
-    new: String
+    pub path: Vec<String>,
+    /// The `add_no` of the line being changed 
+    pub line: usize,
+    /// The text the user expects to be replacing 
+    pub old: String,
+    pub new: String
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -22,9 +28,112 @@ pub struct CreateLine {
 }
 
 #[derive(Serialize, Deserialize, Clone)]
-pub struct UpdateLine {
+pub struct DeleteLine {
     pub filepath: Vec<String>,
-    pub at: usize
+    pub line_no: usize
+}
+
+/// Deletes every line from `from` to `to` inclusive, both given by `add_no`. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeleteRange {
+    pub filepath: Vec<String>,
+    pub from: usize,
+    pub to: usize
+}
+
+/// Character level edits to a file in a session using `EditEngine::Crdt`. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrdtEdit {
+    pub path: Vec<String>,
+    pub ops: Vec<CrdtOp>
+}
+
+/// A text operation made against a revision of a file in a session 
+/// using `EditEngine::Ot`. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TextOperation {
+    pub path: Vec<String>,
+    pub base_revision: usize,
+    pub ops: OtOps
+}
+
+/// Asks for every activity broadcast after `seq`, the last sequence 
+/// number the user saw. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SyncSince {
+    pub seq: u64
+}
+
+/// Asks for the recent revisions of a file. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileHistory {
+    pub path: Vec<String>
+}
+
+/// Asks for a unified diff of a file between two of its revisions. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FileDiff {
+    pub path: Vec<String>,
+    pub from_rev: usize,
+    pub to_rev: usize
+}
+
+/// Writes the whole text of a file, as done through the file API. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WriteFile {
+    pub path: Vec<String>,
+    pub lines: Vec<String>,
+    pub mode: WriteMode
+}
+
+/// How a file is written. 
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum WriteMode {
+    /// The file must not exist yet. 
+    Create,
+    /// The file must already exist, its lines are replaced. 
+    Overwrite
+}
+
+/// Changes the role of another user, only owners may send it. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SetRole {
+    pub id: String,
+    pub role: Role
+}
+
+/// Asks for an invite token to the session, only owners may send it. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CreateInvite {
+    /// The role users joining with the token are given 
+    pub role: Role,
+    /// How long the token works for, `invite_ttl_secs` if not given 
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>
+}
+
+/// Removes a user from the session, only owners may send it. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KickUser {
+    pub id: String
+}
+
+/// Removes a user from the session and refuses them if they join again, 
+/// only owners may send it. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BanUser {
+    pub id: String,
+    /// The name refused, so a user can't join again under a new id, the 
+    /// user's current name if not given 
+    #[serde(default)]
+    pub name: Option<String>
+}
+
+/// Makes another user the owner, the sender becomes an editor. Only owners 
+/// may send it. 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TransferOwnership {
+    pub id: String
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -32,6 +141,103 @@ pub enum UserActivity {
     DirUpdated(DirectoryUpdated),
     FileChanged(FileChanged),
     LockLine(LockLine),
+    UnlockLine(LockLine),
     CreateLine(CreateLine),
-    RequestSync
+    DeleteLine(DeleteLine),
+    DeleteRange(DeleteRange),
+    WriteFile(WriteFile),
+    CrdtEdit(CrdtEdit),
+    RequestCrdtState(Vec<String>),
+    TextOperation(TextOperation),
+    RequestOtState(Vec<String>),
+    /// Asks for every line of a file with its `add_no`. 
+    RequestFileLines(Vec<String>),
+    RequestSync,
+    SyncSince(SyncSince),
+    PlaybackControl(PlaybackControl),
+    FileHistory(FileHistory),
+    FileDiff(FileDiff),
+    /// Reverts the user's most recent change. 
+    Undo,
+    /// Reapplies the user's most recently undone change. 
+    Redo,
+    SetRole(SetRole),
+    CreateInvite(CreateInvite),
+    KickUser(KickUser),
+    BanUser(BanUser),
+    TransferOwnership(TransferOwnership)
+}
+
+impl UserActivity {
+    /// The edit engine a session must use to accept this activity, 
+    /// `None` if it's accepted by any session. 
+    pub fn required_engine(&self) -> Option<EditEngine> {
+        match self {
+            UserActivity::FileChanged(_) |
+            UserActivity::LockLine(_) |
+            UserActivity::UnlockLine(_) |
+            UserActivity::CreateLine(_) |
+            UserActivity::DeleteLine(_) |
+            UserActivity::DeleteRange(_) => Some(EditEngine::LineLock),
+            UserActivity::CrdtEdit(_) |
+            UserActivity::RequestCrdtState(_) => Some(EditEngine::Crdt),
+            UserActivity::TextOperation(_) |
+            UserActivity::RequestOtState(_) => Some(EditEngine::Ot),
+            UserActivity::DirUpdated(_) |
+            UserActivity::WriteFile(_) |
+            UserActivity::RequestFileLines(_) |
+            UserActivity::RequestSync |
+            UserActivity::SyncSince(_) |
+            UserActivity::PlaybackControl(_) |
+            UserActivity::FileHistory(_) |
+            UserActivity::FileDiff(_) |
+            UserActivity::Undo |
+            UserActivity::Redo |
+            UserActivity::SetRole(_) |
+            UserActivity::CreateInvite(_) |
+            UserActivity::KickUser(_) |
+            UserActivity::BanUser(_) |
+            UserActivity::TransferOwnership(_) => None
+        }
+    }
+
+    /// Checks if the activity leaves the session's project unchanged, so it's 
+    /// allowed in a playback session and isn't recorded. 
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, 
+            UserActivity::RequestCrdtState(_) |
+            UserActivity::RequestOtState(_) |
+            UserActivity::RequestFileLines(_) |
+            UserActivity::RequestSync |
+            UserActivity::SyncSince(_) |
+            UserActivity::PlaybackControl(_) |
+            UserActivity::FileHistory(_) |
+            UserActivity::FileDiff(_) |
+            UserActivity::SetRole(_) |
+            UserActivity::CreateInvite(_) |
+            UserActivity::KickUser(_) |
+            UserActivity::BanUser(_) |
+            UserActivity::TransferOwnership(_))
+    }
+
+    /// Checks if the activity may only be sent by owners. 
+    fn owner_only(&self) -> bool {
+        matches!(self, 
+            UserActivity::SetRole(_) |
+            UserActivity::CreateInvite(_) |
+            UserActivity::KickUser(_) |
+            UserActivity::BanUser(_) |
+            UserActivity::TransferOwnership(_))
+    }
+
+    /// Checks if a user with a given role may send the activity. 
+    pub fn permitted_for(&self, role: Role) -> bool {
+        match (role, self) {
+            (Role::Owner, _) => true,
+            (_, msg) if msg.owner_only() => false,
+            (Role::Editor, _) => true,
+            (Role::Viewer, UserActivity::PlaybackControl(_)) => false,
+            (Role::Viewer, msg) => msg.is_read_only()
+        }
+    }
 }
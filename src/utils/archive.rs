@@ -0,0 +1,316 @@
+use crate::models::directory::DirectoryDTO;
+use super::storage::valid_name;
+
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+
+/// The archive formats a project can be exported as.
+#[derive(Clone, Copy, Default, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz"
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip"
+        }
+    }
+}
+
+/// An entry in a flattened project, paths are joined by `/`.
+enum Entry {
+    Dir(String),
+    File(String, String)
+}
+
+fn flatten(dir: &DirectoryDTO, prefix: &str, entries: &mut Vec<Entry>) {
+    let mut files: Vec<_> = dir.files.iter().collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, lines) in files {
+        entries.push(Entry::File(format!("{}{}", prefix, name), lines.join("\n")));
+    }
+    let mut subdirs: Vec<_> = dir.subdirs.iter().collect();
+    subdirs.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, subdir) in subdirs {
+        let path = format!("{}{}/", prefix, name);
+        entries.push(Entry::Dir(path.clone()));
+        flatten(subdir, &path, entries);
+    }
+}
+
+/// Writes a project into an archive, keeping its directory structure.
+pub fn write_archive(project: &DirectoryDTO, format: ArchiveFormat) -> io::Result<Vec<u8>> {
+    let mut entries = vec![];
+    flatten(project, "", &mut entries);
+    match format {
+        ArchiveFormat::Zip => write_zip(&entries),
+        ArchiveFormat::TarGz => write_tar_gz(&entries)
+    }
+}
+
+fn write_zip(entries: &[Entry]) -> io::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let options = SimpleFileOptions::default();
+    for entry in entries {
+        match entry {
+            Entry::Dir(path) => zip.add_directory(path.as_str(), options)?,
+            Entry::File(path, text) => {
+                zip.start_file(path.as_str(), options)?;
+                zip.write_all(text.as_bytes())?;
+            }
+        }
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+fn write_tar_gz(entries: &[Entry]) -> io::Result<Vec<u8>> {
+    let encoder = GzEncoder::new(vec![], Compression::default());
+    let mut tar = tar::Builder::new(encoder);
+    for entry in entries {
+        let mut header = tar::Header::new_gnu();
+        match entry {
+            Entry::Dir(path) => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                tar.append_data(&mut header, path, io::empty())?;
+            },
+            Entry::File(path, text) => {
+                header.set_mode(0o644);
+                header.set_size(text.len() as u64);
+                tar.append_data(&mut header, path, text.as_bytes())?;
+            }
+        }
+    }
+    tar.into_inner()?.finish()
+}
+
+/// Limits enforced while unpacking an uploaded archive.
+pub struct ImportLimits {
+    /// The most bytes all unpacked files may total
+    pub max_bytes: usize,
+    pub max_files: usize
+}
+
+/// Possible errors when unpacking an archive.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The archive couldn't be read.
+    Invalid(io::Error),
+    /// An entry's path escapes the project or has an unusable name.
+    UnsafePath(String),
+    /// A file isn't valid UTF-8 text.
+    NotText(String),
+    TooLarge,
+    TooManyFiles
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Invalid(e) => write!(f, "unreadable archive: {}", e),
+            ImportError::UnsafePath(path) => write!(f, "unsafe entry path {:?}", path),
+            ImportError::NotText(path) => write!(f, "{:?} isn't a text file", path),
+            ImportError::TooLarge => write!(f, "project is too large"),
+            ImportError::TooManyFiles => write!(f, "archive has too many files")
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Invalid(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ImportError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ImportError::Invalid(e.into())
+    }
+}
+
+/// Unpacks an archive into a project, every entry path is checked so it
+/// stays inside the project and files are counted against `limits`.
+pub fn read_archive(
+    archive: &[u8],
+    format: ArchiveFormat,
+    limits: &ImportLimits
+) -> Result<DirectoryDTO, ImportError> {
+    let mut unpacker = Unpacker { limits, project: DirectoryDTO::default(), bytes: 0, files: 0 };
+    match format {
+        ArchiveFormat::Zip => unpacker.read_zip(archive)?,
+        ArchiveFormat::TarGz => unpacker.read_tar_gz(archive)?
+    }
+    Ok(unpacker.project)
+}
+
+struct Unpacker<'a> {
+    limits: &'a ImportLimits,
+    project: DirectoryDTO,
+    bytes: usize,
+    files: usize
+}
+
+impl Unpacker<'_> {
+    fn read_zip(&mut self, archive: &[u8]) -> Result<(), ImportError> {
+        let mut zip = ZipArchive::new(Cursor::new(archive))?;
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)?;
+            let path = entry.name()?.into_owned();
+            if entry.is_dir() {
+                self.add_dir(&path)?;
+            } else if entry.is_file() {
+                self.add_file(&path, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_tar_gz(&mut self, archive: &[u8]) -> Result<(), ImportError> {
+        let mut tar = tar::Archive::new(GzDecoder::new(archive));
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            match entry.header().entry_type() {
+                tar::EntryType::Directory => self.add_dir(&path)?,
+                tar::EntryType::Regular => self.add_file(&path, entry)?,
+                // Links and special files can't be represented in a project.
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+
+    fn add_dir(&mut self, path: &str) -> Result<(), ImportError> {
+        let names = safe_names(path)?;
+        self.project.subdir_mut(&names);
+        Ok(())
+    }
+
+    fn add_file<R: Read>(&mut self, path: &str, reader: R) -> Result<(), ImportError> {
+        let names = safe_names(path)?;
+        let (file_name, dir_names) = match names.split_last() {
+            Some(v) => v,
+            None => return Err(ImportError::UnsafePath(path.to_owned()))
+        };
+        self.files += 1;
+        if self.files > self.limits.max_files {
+            return Err(ImportError::TooManyFiles);
+        }
+        // Reads at most one byte past the limit, so oversized files are caught
+        // without unpacking all of them.
+        let remaining = self.limits.max_bytes - self.bytes;
+        let mut data = vec![];
+        reader.take(remaining as u64 + 1).read_to_end(&mut data)?;
+        if data.len() > remaining {
+            return Err(ImportError::TooLarge);
+        }
+        self.bytes += data.len();
+        let text = match String::from_utf8(data) {
+            Ok(v) => v,
+            Err(_) => return Err(ImportError::NotText(path.to_owned()))
+        };
+        let lines = text.split('\n').map(str::to_owned).collect();
+        self.project.subdir_mut(dir_names).files.insert(file_name.clone(), lines);
+        Ok(())
+    }
+}
+
+fn safe_names(path: &str) -> Result<Vec<String>, ImportError> {
+    let mut names = vec![];
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => (),
+            Component::Normal(name) => match name.to_str() {
+                Some(name) if valid_name(name) => names.push(name.to_owned()),
+                _ => return Err(ImportError::UnsafePath(path.to_owned()))
+            },
+            _ => return Err(ImportError::UnsafePath(path.to_owned()))
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ImportLimits {
+        ImportLimits { max_bytes: 1024, max_files: 8 }
+    }
+
+    fn project() -> DirectoryDTO {
+        let mut project = DirectoryDTO::default();
+        project.files.insert("a.txt".to_owned(), vec!["one".to_owned(), "two".to_owned()]);
+        project.subdir_mut(&["src", "bin"]).files.insert("main.rs".to_owned(), vec!["fn main() {}".to_owned()]);
+        project.subdir_mut(&["empty"]);
+        project
+    }
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (path, text) in files {
+            zip.start_file(*path, SimpleFileOptions::default()).unwrap();
+            zip.write_all(text.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn archives_round_trip() {
+        for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+            let archive = write_archive(&project(), format).unwrap();
+            let mut read = read_archive(&archive, format, &limits()).unwrap();
+            assert_eq!(read.files, project().files);
+            assert_eq!(read.subdir_mut(&["src", "bin"]).files, project().subdir_mut(&["src", "bin"]).files);
+            assert!(read.subdirs.contains_key("empty"));
+        }
+    }
+
+    #[test]
+    fn unsafe_paths_are_refused() {
+        assert_eq!(safe_names("./src/a.txt").unwrap(), vec!["src", "a.txt"]);
+        for path in ["../a.txt", "src/../../a.txt", "/etc/passwd"] {
+            assert!(matches!(safe_names(path), Err(ImportError::UnsafePath(_))), "{}", path);
+            let archive = zip_of(&[(path, "x")]);
+            assert!(matches!(read_archive(&archive, ArchiveFormat::Zip, &limits()), Err(ImportError::UnsafePath(_))));
+        }
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let large = "x".repeat(1025);
+        let archive = zip_of(&[("a.txt", &large)]);
+        assert!(matches!(read_archive(&archive, ArchiveFormat::Zip, &limits()), Err(ImportError::TooLarge)));
+
+        let names: Vec<String> = (0..9).map(|i| format!("{}.txt", i)).collect();
+        let files: Vec<(&str, &str)> = names.iter().map(|n| (n.as_str(), "")).collect();
+        let archive = zip_of(&files);
+        assert!(matches!(read_archive(&archive, ArchiveFormat::Zip, &limits()), Err(ImportError::TooManyFiles)));
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("bin", SimpleFileOptions::default()).unwrap();
+        zip.write_all(&[0xff, 0xfe]).unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+        assert!(matches!(read_archive(&archive, ArchiveFormat::Zip, &limits()), Err(ImportError::NotText(_))));
+    }
+}
@@ -0,0 +1,80 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::Deserialize;
+use serde_json::{to_vec as to_json_vec, from_slice};
+
+use crate::models::auth::Claims;
+use super::tokens;
+
+
+/// The header of every token issued, only HS256 tokens are accepted. 
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String
+}
+
+/// Creates an HS256 JSON web token holding the claims. 
+pub fn encode(secret: &str, claims: &Claims) -> String {
+    let header = URL_SAFE_NO_PAD.encode(HEADER);
+    // Serialising plain strings and numbers can't fail.
+    let payload = URL_SAFE_NO_PAD.encode(to_json_vec(claims).unwrap_or_default());
+    let signed = format!("{}.{}", header, payload);
+    let signature = tokens::sign(secret, signed.as_bytes());
+    format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// Checks an HS256 JSON web token was signed with the secret and hasn't 
+/// expired by `now`, returning its claims. 
+pub fn decode(secret: &str, token: &str, now: u64) -> Option<Claims> {
+    let (signed, signature) = token.rsplit_once('.')?;
+    let (header, payload) = signed.split_once('.')?;
+    let header: Header = from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    if header.alg != "HS256" {
+        return None;
+    }
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    if !tokens::verify(secret, signed.as_bytes(), &signature) {
+        return None;
+    }
+    let claims: Claims = from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    if now >= claims.exp {
+        return None;
+    }
+    Some(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> Claims {
+        Claims { sub: "u-alice".to_owned(), name: "Alice".to_owned(), iat: 10, exp: 100 }
+    }
+
+    #[test]
+    fn tokens_decode_until_they_expire() {
+        let token = encode("secret", &claims());
+        let decoded = decode("secret", &token, 99).map(|c| (c.sub, c.name, c.iat, c.exp));
+        assert_eq!(decoded, Some(("u-alice".to_owned(), "Alice".to_owned(), 10, 100)));
+        assert!(decode("secret", &token, 100).is_none());
+        assert!(decode("other", &token, 0).is_none());
+    }
+
+    #[test]
+    fn tampered_tokens_are_refused() {
+        let token = encode("secret", &claims());
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+
+        let mut forged = claims();
+        forged.sub = "u-bob".to_owned();
+        let payload = URL_SAFE_NO_PAD.encode(to_json_vec(&forged).unwrap());
+        assert!(decode("secret", &format!("{}.{}.{}", header, payload, signature), 0).is_none());
+
+        let none = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+        let unsigned = format!("{}.{}", none, rest.split_once('.').unwrap().0);
+        assert!(decode("secret", &format!("{}.", unsigned), 0).is_none());
+        assert!(decode("secret", "not a token", 0).is_none());
+    }
+}
@@ -1 +1,9 @@
-pub mod settings;
\ No newline at end of file
+pub mod settings;
+pub mod persistence;
+pub mod storage;
+pub mod archive;
+pub mod templates;
+pub mod tokens;
+pub mod recording;
+pub mod jwt;
+pub mod time;
@@ -0,0 +1,80 @@
+use crate::models::session::SessionSnapshot;
+use super::settings::AppSettings;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{to_vec as to_json_vec, from_slice};
+
+
+/// Somewhere session snapshots can be saved to and restored from.
+pub trait SessionPersistence: Send + Sync {
+    /// Saves a snapshot, replacing any previous snapshot of the same session.
+    fn save(&self, snapshot: &SessionSnapshot) -> io::Result<()>;
+    /// Loads every saved snapshot.
+    fn load_all(&self) -> io::Result<Vec<SessionSnapshot>>;
+    /// Removes a session's snapshot, if it has one.
+    fn remove(&self, session_id: &str) -> io::Result<()>;
+}
+
+/// Creates the persistence configured in `AppSettings`, `None` if sessions
+/// shouldn't be persisted.
+pub fn from_settings(settings: &AppSettings) -> Option<Arc<dyn SessionPersistence>> {
+    let root = settings.snapshot_dir.as_ref()?;
+    Some(Arc::new(DirPersistence::new(root)))
+}
+
+/// Saves each session as a JSON file named after its id in a local directory.
+pub struct DirPersistence {
+    root: PathBuf
+}
+
+impl DirPersistence {
+    pub fn new(root: &str) -> Self {
+        DirPersistence { root: PathBuf::from(root) }
+    }
+
+    fn snapshot_path(&self, session_id: &str) -> PathBuf {
+        self.root.join(format!("{}.json", session_id))
+    }
+}
+
+impl SessionPersistence for DirPersistence {
+    fn save(&self, snapshot: &SessionSnapshot) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        let path = self.snapshot_path(&snapshot.id);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, to_json_vec(snapshot)?)?;
+        // Renaming over the old snapshot means a crash mid write never loses it.
+        fs::rename(tmp_path, path)
+    }
+
+    fn load_all(&self) -> io::Result<Vec<SessionSnapshot>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e)
+        };
+        let mut snapshots = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match from_slice::<SessionSnapshot>(&fs::read(&path)?) {
+                Ok(v) => snapshots.push(v),
+                Err(e) => log::warn!("skipping unreadable snapshot {:?}: {}", path, e)
+            }
+        }
+        Ok(snapshots)
+    }
+
+    fn remove(&self, session_id: &str) -> io::Result<()> {
+        match fs::remove_file(self.snapshot_path(session_id)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::models::recording::{RecordEntry, RecordedEvent};
+use super::storage::valid_name;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use serde_json::{to_vec as to_json_vec, from_str};
+
+
+/// Appends a session's entries to a JSON lines file named after its id. 
+/// 
+/// Entries are queued to a task which writes them in order, so recording 
+/// never waits on the disk. 
+pub struct Recorder {
+    entries: UnboundedSender<RecordEntry>
+}
+
+impl Recorder {
+    /// Opens a session's recording and spawns the task writing to it, 
+    /// which stops once the recorder is dropped. 
+    pub fn open(recordings_dir: &str, session_id: &str) -> io::Result<Self> {
+        fs::create_dir_all(recordings_dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(recording_path(recordings_dir, session_id)?)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::task::spawn(write_entries(session_id.to_owned(), tokio::fs::File::from_std(file), rx));
+        Ok(Recorder { entries: tx })
+    }
+
+    /// Records an event, timestamped now. 
+    pub fn record(&self, event: RecordedEvent) -> io::Result<()> {
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.entries.send(RecordEntry { at_ms, event })
+            .map_err(|_| io::Error::other("recording writer has stopped"))
+    }
+}
+
+async fn write_entries(
+    session_id: String,
+    file: tokio::fs::File,
+    rx: UnboundedReceiver<RecordEntry>
+) {
+    let mut file = file;
+    let mut rx = rx;
+    while let Some(entry) = rx.recv().await {
+        let mut line = match to_json_vec(&entry) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("failed to record session {}: {}", session_id, e);
+                continue;
+            }
+        };
+        line.push(b'\n');
+        // Written as a single call so a crash can only cut off the last line,
+        // and flushed so a playback started now reads it.
+        let res = match file.write_all(&line).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e)
+        };
+        if let Err(e) = res {
+            log::error!("failed to record session {}: {}", session_id, e);
+        }
+    }
+}
+
+/// Loads every entry of a session's recording, `None` if it has none. 
+pub fn load_recording(recordings_dir: &str, session_id: &str) -> io::Result<Option<Vec<RecordEntry>>> {
+    let file = match fs::File::open(recording_path(recordings_dir, session_id)?) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match from_str::<RecordEntry>(&line) {
+            Ok(v) => entries.push(v),
+            Err(e) => log::warn!("skipping unreadable entry in recording of {}: {}", session_id, e)
+        }
+    }
+    Ok(Some(entries))
+}
+
+fn recording_path(recordings_dir: &str, session_id: &str) -> io::Result<PathBuf> {
+    if !valid_name(session_id) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid session id {:?}", session_id)));
+    }
+    Ok(Path::new(recordings_dir).join(format!("{}.jsonl", session_id)))
+}
@@ -1,13 +1,60 @@
 use std::env;
 extern crate dotenv;
 use dotenv::dotenv;
+use uuid::Uuid;
 
 
+/// Where session projects are stored. 
+#[derive(Clone, Copy)]
+pub enum StorageKind {
+    /// Only in memory, lost when the server stops. 
+    Memory,
+    /// As real files under `storage_path`. 
+    Disk,
+    /// In an SQLite database at `storage_path`. 
+    Sqlite
+}
+
 #[derive(Clone)]
 pub struct AppSettings {
     pub max_sessions: usize,
     pub max_sess_users: usize,
-    pub max_proj_size_kb: usize
+    pub max_proj_size_kb: usize,
+    /// The most files an imported archive may contain 
+    pub max_import_files: usize,
+    /// Seconds a line lock may sit idle before it's released, `0` disables expiry 
+    pub lock_timeout_secs: u64,
+    /// Directory sessions are snapshotted to, `None` disables persistence 
+    pub snapshot_dir: Option<String>,
+    /// Seconds between session snapshots 
+    pub snapshot_interval_secs: u64,
+    pub storage_kind: StorageKind,
+    pub storage_path: String,
+    /// Directory holding starter projects, one subdirectory per template 
+    pub templates_dir: String,
+    /// Seconds a session may sit with no users before it's closed, `0` disables closing 
+    pub idle_session_secs: u64,
+    /// If idle sessions are kept in storage and snapshotted when closed, 
+    /// rather than removed 
+    pub persist_idle_sessions: bool,
+    /// Token required to stream session lifecycle events, `None` disables the stream 
+    pub admin_token: Option<String>,
+    /// Secret tokens issued to users are signed with, random each start if unset 
+    pub token_secret: String,
+    /// Seconds a disconnected user may rejoin as themselves, `0` removes them immediately 
+    pub resume_grace_secs: u64,
+    /// Directory sessions are recorded to for playback, `None` disables recording 
+    pub recordings_dir: Option<String>,
+    /// Seconds an invite token works for when its owner doesn't say 
+    pub invite_ttl_secs: u64,
+    /// Secret bearer tokens are signed with. Unset by default, which disables 
+    /// authentication so anyone who can reach the server may create, join and 
+    /// edit sessions, named by whatever they ask to be called 
+    pub jwt_secret: Option<String>,
+    /// JSON file of the users `/auth/token` issues tokens to, `None` disables it 
+    pub auth_users_file: Option<String>,
+    /// Seconds a bearer token issued by `/auth/token` works for 
+    pub auth_token_ttl_secs: u64
 }
 
 impl AppSettings {
@@ -26,12 +73,91 @@ impl AppSettings {
             Ok(v) => v.parse::<usize>().unwrap_or(1024),
             Err(_) => 1024,
         };
+        let max_import_files = match env::var("max_import_files") {
+            Ok(v) => v.parse::<usize>().unwrap_or(512),
+            Err(_) => 512,
+        };
+        let lock_timeout_secs = match env::var("lock_timeout_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(300),
+            Err(_) => 300,
+        };
+        let snapshot_dir = env::var("snapshot_dir").ok();
+        let snapshot_interval_secs = match env::var("snapshot_interval_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(60),
+            Err(_) => 60,
+        };
+        let storage_kind = match env::var("storage_backend").as_deref() {
+            Ok("disk") => StorageKind::Disk,
+            Ok("sqlite") => StorageKind::Sqlite,
+            _ => StorageKind::Memory,
+        };
+        let storage_path = match env::var("storage_path") {
+            Ok(v) => v,
+            Err(_) => match storage_kind {
+                StorageKind::Sqlite => "codealong.db".to_owned(),
+                _ => "projects".to_owned(),
+            },
+        };
+        let templates_dir = match env::var("templates_dir") {
+            Ok(v) => v,
+            Err(_) => "templates".to_owned(),
+        };
+        let idle_session_secs = match env::var("idle_session_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(600),
+            Err(_) => 600,
+        };
+        let persist_idle_sessions = match env::var("persist_idle_sessions") {
+            Ok(v) => v.parse::<bool>().unwrap_or(true),
+            Err(_) => true,
+        };
+        let admin_token = env::var("admin_token").ok();
+        let token_secret = match env::var("token_secret") {
+            Ok(v) => v,
+            Err(_) => format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        };
+        let resume_grace_secs = match env::var("resume_grace_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(60),
+            Err(_) => 60,
+        };
+        let recordings_dir = env::var("recordings_dir").ok();
+        let invite_ttl_secs = match env::var("invite_ttl_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(86400),
+            Err(_) => 86400,
+        };
+        let jwt_secret = env::var("jwt_secret").ok();
+        let auth_users_file = env::var("auth_users_file").ok();
+        let auth_token_ttl_secs = match env::var("auth_token_ttl_secs") {
+            Ok(v) => v.parse::<u64>().unwrap_or(3600),
+            Err(_) => 3600,
+        };
 
         AppSettings {
             max_sessions,
             max_sess_users,
-            max_proj_size_kb
+            max_proj_size_kb,
+            max_import_files,
+            lock_timeout_secs,
+            snapshot_dir,
+            snapshot_interval_secs,
+            storage_kind,
+            storage_path,
+            templates_dir,
+            idle_session_secs,
+            persist_idle_sessions,
+            admin_token,
+            token_secret,
+            resume_grace_secs,
+            recordings_dir,
+            invite_ttl_secs,
+            jwt_secret,
+            auth_users_file,
+            auth_token_ttl_secs
         }
     }
+
+    /// The most bytes of text a session's project may hold. 
+    pub fn max_proj_bytes(&self) -> usize {
+        self.max_proj_size_kb.saturating_mul(1024)
+    }
 }
 
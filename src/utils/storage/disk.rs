@@ -0,0 +1,187 @@
+use crate::models::directory::DirectoryDTO;
+use crate::models::session::SessionSettings;
+use super::{ProjectStore, StorageBackend, valid_name};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+
+/// Mirrors each project as real files in a directory named after its
+/// session id under a root path, its settings are kept beside it in
+/// `<session id>.json`.
+pub struct DiskStorage {
+    root: PathBuf
+}
+
+impl DiskStorage {
+    pub fn new(root: &str) -> Self {
+        DiskStorage { root: PathBuf::from(root) }
+    }
+}
+
+impl StorageBackend for DiskStorage {
+    fn open_project(&self, session_id: &str) -> io::Result<Arc<dyn ProjectStore>> {
+        if !valid_name(session_id) {
+            return Err(invalid_name(session_id));
+        }
+        let root = self.root.join(session_id);
+        fs::create_dir_all(&root)?;
+        let settings = settings_path(&self.root, session_id);
+        Ok(Arc::new(DiskProject { root, settings }))
+    }
+
+    fn stored_projects(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e)
+        };
+        let mut ids = vec![];
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Ok(id) = entry.file_name().into_string() {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn remove_project(&self, session_id: &str) -> io::Result<()> {
+        if !valid_name(session_id) {
+            return Err(invalid_name(session_id));
+        }
+        ignore_not_found(fs::remove_dir_all(self.root.join(session_id)))?;
+        ignore_not_found(fs::remove_file(settings_path(&self.root, session_id)))
+    }
+}
+
+fn settings_path(root: &Path, session_id: &str) -> PathBuf {
+    root.join(format!("{}.json", session_id))
+}
+
+pub struct DiskProject {
+    root: PathBuf,
+    settings: PathBuf
+}
+
+impl DiskProject {
+    fn full_path(&self, path: &[String]) -> io::Result<PathBuf> {
+        if path.is_empty() {
+            return Err(invalid_name(""));
+        }
+        let mut full = self.root.clone();
+        for name in path {
+            if !valid_name(name) {
+                return Err(invalid_name(name));
+            }
+            full.push(name);
+        }
+        Ok(full)
+    }
+}
+
+impl ProjectStore for DiskProject {
+    fn load(&self) -> io::Result<Option<DirectoryDTO>> {
+        if !self.root.is_dir() {
+            return Ok(None);
+        }
+        read_dir_dto(&self.root).map(Some)
+    }
+
+    fn write_file(&self, path: &[String], text: &str) -> io::Result<()> {
+        let full = self.full_path(path)?;
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(full, text)
+    }
+
+    fn remove_file(&self, path: &[String]) -> io::Result<()> {
+        ignore_not_found(fs::remove_file(self.full_path(path)?))
+    }
+
+    fn create_dir(&self, path: &[String]) -> io::Result<()> {
+        fs::create_dir_all(self.full_path(path)?)
+    }
+
+    fn remove_dir(&self, path: &[String]) -> io::Result<()> {
+        ignore_not_found(fs::remove_dir_all(self.full_path(path)?))
+    }
+
+    fn rename(&self, path: &[String], name: &str) -> io::Result<()> {
+        if !valid_name(name) {
+            return Err(invalid_name(name));
+        }
+        let from = self.full_path(path)?;
+        let to = from.with_file_name(name);
+        fs::rename(from, to)
+    }
+
+    fn load_settings(&self) -> io::Result<Option<SessionSettings>> {
+        let text = match fs::read_to_string(&self.settings) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        serde_json::from_str(&text).map(Some).map_err(io::Error::other)
+    }
+
+    fn write_settings(&self, settings: &SessionSettings) -> io::Result<()> {
+        let text = serde_json::to_string(settings).map_err(io::Error::other)?;
+        fs::write(&self.settings, text)
+    }
+}
+
+/// Reads a directory on disk into a project, skipping anything that isn't 
+/// a text file. 
+pub fn read_dir_dto(dir: &Path) -> io::Result<DirectoryDTO> {
+    let mut files = HashMap::new();
+    let mut subdirs = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(v) => v,
+            Err(_) => continue
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            subdirs.insert(name, read_dir_dto(&entry.path())?);
+        } else if file_type.is_file() {
+            // Only text files can be edited, anything else is left on disk untouched.
+            if let Ok(text) = fs::read_to_string(entry.path()) {
+                files.insert(name, text.split('\n').map(str::to_owned).collect());
+            }
+        }
+    }
+    Ok(DirectoryDTO { files, subdirs })
+}
+
+fn ignore_not_found(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res
+    }
+}
+
+fn invalid_name(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid path name {:?}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::storage::tests::check_round_trip;
+
+    #[test]
+    fn projects_round_trip() {
+        let root = std::env::temp_dir().join(format!("codealong-disk-{}", uuid::Uuid::new_v4()));
+        check_round_trip(&DiskStorage::new(root.to_str().unwrap()));
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,61 @@
+use crate::models::directory::DirectoryDTO;
+use crate::models::session::SessionSettings;
+use super::{ProjectStore, StorageBackend};
+
+use std::io;
+use std::sync::Arc;
+
+
+/// Keeps projects only in the session's in-memory `Directory`, so
+/// they're lost when the server stops.
+pub struct MemoryStorage;
+
+impl StorageBackend for MemoryStorage {
+    fn open_project(&self, _session_id: &str) -> io::Result<Arc<dyn ProjectStore>> {
+        Ok(Arc::new(MemoryProject))
+    }
+
+    fn stored_projects(&self) -> io::Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    fn remove_project(&self, _session_id: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct MemoryProject;
+
+impl ProjectStore for MemoryProject {
+    fn load(&self) -> io::Result<Option<DirectoryDTO>> {
+        Ok(None)
+    }
+
+    fn write_file(&self, _path: &[String], _text: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_file(&self, _path: &[String]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn create_dir(&self, _path: &[String]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_dir(&self, _path: &[String]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, _path: &[String], _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn load_settings(&self) -> io::Result<Option<SessionSettings>> {
+        Ok(None)
+    }
+
+    fn write_settings(&self, _settings: &SessionSettings) -> io::Result<()> {
+        Ok(())
+    }
+}
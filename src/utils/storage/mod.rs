@@ -0,0 +1,241 @@
+pub mod memory;
+pub mod disk;
+pub mod sqlite;
+
+use crate::models::directory::DirectoryDTO;
+use crate::models::session::SessionSettings;
+use super::settings::{AppSettings, StorageKind};
+
+use std::io;
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, oneshot};
+
+
+/// Where the files of a single session's project are stored, the logic
+/// layer writes through to this after each change to the in-memory tree.
+///
+/// Paths are relative to the project root, in the same form as the
+/// paths in user activities.
+pub trait ProjectStore: Send + Sync {
+    /// Loads the stored project, `None` if nothing has been stored yet.
+    fn load(&self) -> io::Result<Option<DirectoryDTO>>;
+    /// Creates or overwrites a file with the given text.
+    fn write_file(&self, path: &[String], text: &str) -> io::Result<()>;
+    fn remove_file(&self, path: &[String]) -> io::Result<()>;
+    fn create_dir(&self, path: &[String]) -> io::Result<()>;
+    /// Removes a directory and everything in it.
+    fn remove_dir(&self, path: &[String]) -> io::Result<()>;
+    /// Renames a file or directory, keeping it in the same parent directory.
+    fn rename(&self, path: &[String], name: &str) -> io::Result<()>;
+    /// Loads the stored session settings, `None` if none have been stored.
+    fn load_settings(&self) -> io::Result<Option<SessionSettings>>;
+    /// Creates or overwrites the session's settings.
+    fn write_settings(&self, settings: &SessionSettings) -> io::Result<()>;
+
+    /// Writes every file and directory in a project, such as a newly
+    /// created session's starting project.
+    fn write_project(&self, project: &DirectoryDTO) -> io::Result<()> {
+        write_dto(self, &mut vec![], project)
+    }
+}
+
+fn write_dto<S: ProjectStore + ?Sized>(
+    store: &S,
+    path: &mut Vec<String>,
+    dir: &DirectoryDTO
+) -> io::Result<()> {
+    for (name, lines) in dir.files.iter() {
+        path.push(name.clone());
+        let res = store.write_file(path, &lines.join("\n"));
+        path.pop();
+        res?;
+    }
+    for (name, subdir) in dir.subdirs.iter() {
+        path.push(name.clone());
+        let res = store.create_dir(path)
+            .and_then(|_| write_dto(store, path, subdir));
+        path.pop();
+        res?;
+    }
+    Ok(())
+}
+
+/// Opens the project store of each session.
+pub trait StorageBackend: Send + Sync {
+    /// Opens the store for a session's project, creating it if needed.
+    fn open_project(&self, session_id: &str) -> io::Result<Arc<dyn ProjectStore>>;
+    /// Lists the ids of every session with a stored project.
+    fn stored_projects(&self) -> io::Result<Vec<String>>;
+    /// Removes everything stored for a session's project.
+    fn remove_project(&self, session_id: &str) -> io::Result<()>;
+}
+
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// Creates the storage backend configured in `AppSettings`.
+pub fn from_settings(settings: &AppSettings) -> io::Result<Storage> {
+    let storage: Storage = match settings.storage_kind {
+        StorageKind::Memory => Arc::new(memory::MemoryStorage),
+        StorageKind::Disk => Arc::new(disk::DiskStorage::new(&settings.storage_path)),
+        StorageKind::Sqlite => Arc::new(sqlite::SqliteStorage::open(&settings.storage_path)?)
+    };
+    Ok(storage)
+}
+
+/// A change to write through to a project store, queued by `StoreWriter`. 
+pub enum StoreWrite {
+    WriteFile(Vec<String>, String),
+    RemoveFile(Vec<String>),
+    CreateDir(Vec<String>),
+    RemoveDir(Vec<String>),
+    Rename(Vec<String>, String),
+    WriteProject(DirectoryDTO),
+    WriteSettings(SessionSettings)
+}
+
+enum Queued {
+    Write(StoreWrite),
+    /// Replied to once every write queued before it has been made, `false` 
+    /// if any failed since the last flush. 
+    Flush(oneshot::Sender<bool>)
+}
+
+/// Writes changes through to a session's project store from a task of its 
+/// own, in the order they were queued, so the logic layer never waits on 
+/// the store while it holds the project's locks. 
+#[derive(Clone)]
+pub struct StoreWriter {
+    queue: mpsc::UnboundedSender<Queued>
+}
+
+impl StoreWriter {
+    /// Spawns the task writing to a store, it stops once every 
+    /// `StoreWriter` for it has been dropped. 
+    pub fn spawn(store: Arc<dyn ProjectStore>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::task::spawn(write_queued(store, rx));
+        StoreWriter { queue: tx }
+    }
+
+    /// Queues a write, any failure is logged rather than returned since the 
+    /// in-memory tree has already changed. 
+    pub fn write(&self, write: StoreWrite) {
+        if self.queue.send(Queued::Write(write)).is_err() {
+            log::error!("failed to write to project store: its writer has stopped");
+        }
+    }
+
+    /// Waits for every write queued so far to be made. 
+    /// 
+    /// # Returns 
+    /// * `true` - If none of them failed since the last flush. 
+    /// * `false` - If any failed, or the writer has stopped. 
+    pub async fn flush(&self) -> bool {
+        let (tx, rx) = oneshot::channel();
+        if self.queue.send(Queued::Flush(tx)).is_err() {
+            return false;
+        }
+        rx.await.unwrap_or(false)
+    }
+}
+
+async fn write_queued(store: Arc<dyn ProjectStore>, rx: mpsc::UnboundedReceiver<Queued>) {
+    let mut rx = rx;
+    let mut failed = false;
+    while let Some(queued) = rx.recv().await {
+        // Writes queued together are made in one blocking call.
+        let mut batch = vec![queued];
+        while let Ok(queued) = rx.try_recv() {
+            batch.push(queued);
+        }
+        let store = store.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let mut failed = failed;
+            for queued in batch {
+                match queued {
+                    Queued::Write(write) => failed |= apply_write(&*store, write).is_err(),
+                    Queued::Flush(reply) => {
+                        let _ = reply.send(!failed);
+                        failed = false;
+                    }
+                }
+            }
+            failed
+        }).await;
+        failed = match res {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("failed to write to project store: {}", e);
+                true
+            }
+        };
+    }
+}
+
+fn apply_write(store: &dyn ProjectStore, write: StoreWrite) -> io::Result<()> {
+    let res = match write {
+        StoreWrite::WriteFile(path, text) => store.write_file(&path, &text),
+        StoreWrite::RemoveFile(path) => store.remove_file(&path),
+        StoreWrite::CreateDir(path) => store.create_dir(&path),
+        StoreWrite::RemoveDir(path) => store.remove_dir(&path),
+        StoreWrite::Rename(path, name) => store.rename(&path, &name),
+        StoreWrite::WriteProject(project) => store.write_project(&project),
+        StoreWrite::WriteSettings(settings) => store.write_settings(&settings)
+    };
+    if let Err(e) = &res {
+        log::error!("failed to write to project store: {}", e);
+    }
+    res
+}
+
+/// Checks a file or directory name can be safely used as a path component.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::session::EditEngine;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn json(project: Option<DirectoryDTO>) -> serde_json::Value {
+        serde_json::to_value(project).unwrap()
+    }
+
+    /// Writes a project and its settings to a backend, checking they load 
+    /// back as they were written and are gone once the project is removed. 
+    pub(super) fn check_round_trip(storage: &dyn StorageBackend) {
+        let store = storage.open_project("s1").unwrap();
+        let mut project = DirectoryDTO::default();
+        project.files.insert("a.txt".to_owned(), strings(&["one", "two"]));
+        project.subdir_mut(&["src"]).files.insert("main.rs".to_owned(), strings(&["fn main() {}", ""]));
+        project.subdir_mut(&["empty"]);
+        store.write_project(&project).unwrap();
+        let settings = SessionSettings { engine: EditEngine::Crdt, ..Default::default() };
+        store.write_settings(&settings).unwrap();
+
+        assert_eq!(json(store.load().unwrap()), json(Some(project)));
+        let engine = store.load_settings().unwrap().map(|s| s.engine);
+        assert!(engine == Some(EditEngine::Crdt));
+
+        store.rename(&strings(&["src"]), "lib").unwrap();
+        store.remove_file(&strings(&["a.txt"])).unwrap();
+        let mut expected = DirectoryDTO::default();
+        expected.subdir_mut(&["lib"]).files.insert("main.rs".to_owned(), strings(&["fn main() {}", ""]));
+        expected.subdir_mut(&["empty"]);
+        assert_eq!(json(store.load().unwrap()), json(Some(expected)));
+
+        assert!(storage.stored_projects().unwrap().contains(&"s1".to_owned()));
+        storage.remove_project("s1").unwrap();
+        assert!(!storage.stored_projects().unwrap().contains(&"s1".to_owned()));
+        assert!(storage.open_project("s1").unwrap().load_settings().unwrap().is_none());
+    }
+}
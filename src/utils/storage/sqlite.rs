@@ -0,0 +1,218 @@
+use crate::models::directory::DirectoryDTO;
+use crate::models::session::SessionSettings;
+use super::{ProjectStore, StorageBackend};
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS files (
+        session TEXT NOT NULL,
+        path TEXT NOT NULL,
+        content TEXT NOT NULL,
+        PRIMARY KEY (session, path)
+    );
+    CREATE TABLE IF NOT EXISTS dirs (
+        session TEXT NOT NULL,
+        path TEXT NOT NULL,
+        PRIMARY KEY (session, path)
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        session TEXT NOT NULL PRIMARY KEY,
+        settings TEXT NOT NULL
+    );
+";
+
+/// Stores every project in a single embedded SQLite database, paths
+/// are stored joined by `/`.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>
+}
+
+impl SqliteStorage {
+    pub fn open(db_path: &str) -> io::Result<Self> {
+        let conn = Connection::open(db_path).map_err(to_io)?;
+        conn.execute_batch(SCHEMA).map_err(to_io)?;
+        Ok(SqliteStorage { conn: Arc::new(Mutex::new(conn)) })
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn open_project(&self, session_id: &str) -> io::Result<Arc<dyn ProjectStore>> {
+        Ok(Arc::new(SqliteProject {
+            conn: self.conn.clone(),
+            session_id: session_id.to_owned()
+        }))
+    }
+
+    fn stored_projects(&self) -> io::Result<Vec<String>> {
+        let conn = lock(&self.conn)?;
+        let mut stmt = conn.prepare("SELECT session FROM files UNION SELECT session FROM dirs")
+            .map_err(to_io)?;
+        let ids = stmt.query_map([], |row| row.get::<_, String>(0))
+            .map_err(to_io)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(to_io)?;
+        Ok(ids)
+    }
+
+    fn remove_project(&self, session_id: &str) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        for table in ["files", "dirs", "settings"] {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE session = ?1", table),
+                params![session_id]
+            ).map_err(to_io)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct SqliteProject {
+    conn: Arc<Mutex<Connection>>,
+    session_id: String
+}
+
+impl ProjectStore for SqliteProject {
+    fn load(&self) -> io::Result<Option<DirectoryDTO>> {
+        let conn = lock(&self.conn)?;
+        let mut project = DirectoryDTO::default();
+        let mut found = false;
+
+        let mut stmt = conn.prepare("SELECT path FROM dirs WHERE session = ?1").map_err(to_io)?;
+        let dirs = stmt.query_map(params![self.session_id], |row| row.get::<_, String>(0))
+            .map_err(to_io)?;
+        for path in dirs {
+            let path = path.map_err(to_io)?;
+            let names: Vec<&str> = path.split('/').collect();
+            project.subdir_mut(&names);
+            found = true;
+        }
+
+        let mut stmt = conn.prepare("SELECT path, content FROM files WHERE session = ?1").map_err(to_io)?;
+        let files = stmt.query_map(params![self.session_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        }).map_err(to_io)?;
+        for file in files {
+            let (path, content) = file.map_err(to_io)?;
+            let names: Vec<&str> = path.split('/').collect();
+            let (file_name, dir_names) = match names.split_last() {
+                Some(v) => v,
+                None => continue
+            };
+            let lines = content.split('\n').map(str::to_owned).collect();
+            project.subdir_mut(dir_names).files.insert(file_name.to_string(), lines);
+            found = true;
+        }
+
+        Ok(if found { Some(project) } else { None })
+    }
+
+    fn write_file(&self, path: &[String], text: &str) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        conn.execute(
+            "INSERT INTO files (session, path, content) VALUES (?1, ?2, ?3)
+                ON CONFLICT (session, path) DO UPDATE SET content = excluded.content",
+            params![self.session_id, path.join("/"), text]
+        ).map_err(to_io)?;
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &[String]) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        conn.execute(
+            "DELETE FROM files WHERE session = ?1 AND path = ?2",
+            params![self.session_id, path.join("/")]
+        ).map_err(to_io)?;
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &[String]) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO dirs (session, path) VALUES (?1, ?2)",
+            params![self.session_id, path.join("/")]
+        ).map_err(to_io)?;
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &[String]) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        let path = path.join("/");
+        let prefix = format!("{}/", path);
+        for table in ["files", "dirs"] {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE session = ?1
+                    AND (path = ?2 OR substr(path, 1, length(?3)) = ?3)", table),
+                params![self.session_id, path, prefix]
+            ).map_err(to_io)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&self, path: &[String], name: &str) -> io::Result<()> {
+        let conn = lock(&self.conn)?;
+        let from = path.join("/");
+        let mut renamed = path.to_vec();
+        if let Some(last) = renamed.last_mut() {
+            *last = name.to_owned();
+        }
+        let to = renamed.join("/");
+        let prefix = format!("{}/", from);
+        for table in ["files", "dirs"] {
+            // Moves the item itself along with everything beneath it if it's a directory.
+            conn.execute(
+                &format!("UPDATE {} SET path = ?3 || substr(path, length(?2) + 1)
+                    WHERE session = ?1 AND (path = ?2 OR substr(path, 1, length(?4)) = ?4)", table),
+                params![self.session_id, from, to, prefix]
+            ).map_err(to_io)?;
+        }
+        Ok(())
+    }
+
+    fn load_settings(&self) -> io::Result<Option<SessionSettings>> {
+        let conn = lock(&self.conn)?;
+        let text = conn.query_row(
+            "SELECT settings FROM settings WHERE session = ?1",
+            params![self.session_id],
+            |row| row.get::<_, String>(0)
+        ).optional().map_err(to_io)?;
+        match text {
+            Some(text) => serde_json::from_str(&text).map(Some).map_err(io::Error::other),
+            None => Ok(None)
+        }
+    }
+
+    fn write_settings(&self, settings: &SessionSettings) -> io::Result<()> {
+        let text = serde_json::to_string(settings).map_err(io::Error::other)?;
+        let conn = lock(&self.conn)?;
+        conn.execute(
+            "INSERT INTO settings (session, settings) VALUES (?1, ?2)
+                ON CONFLICT (session) DO UPDATE SET settings = excluded.settings",
+            params![self.session_id, text]
+        ).map_err(to_io)?;
+        Ok(())
+    }
+}
+
+fn lock(conn: &Mutex<Connection>) -> io::Result<std::sync::MutexGuard<'_, Connection>> {
+    conn.lock().map_err(|_| io::Error::other("storage database lock poisoned"))
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::storage::tests::check_round_trip;
+
+    #[test]
+    fn projects_round_trip() {
+        check_round_trip(&SqliteStorage::open(":memory:").unwrap());
+    }
+}
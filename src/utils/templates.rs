@@ -0,0 +1,41 @@
+use crate::models::directory::DirectoryDTO;
+use super::storage::{disk::read_dir_dto, valid_name};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+/// Lists the starter projects in the templates directory, each template 
+/// is a subdirectory named after it. 
+pub fn list_templates(templates_dir: &str) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(templates_dir) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e)
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Ok(name) = entry.file_name().into_string() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Reads a template's project, `None` if there's no template by that name. 
+pub fn load_template(templates_dir: &str, name: &str) -> io::Result<Option<DirectoryDTO>> {
+    if !valid_name(name) {
+        return Ok(None);
+    }
+    let path = Path::new(templates_dir).join(name);
+    if !path.is_dir() {
+        return Ok(None);
+    }
+    read_dir_dto(&path).map(Some)
+}
@@ -0,0 +1,10 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Seconds since the unix epoch, `0` if the clock is set before it. 
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,162 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use rand::RngCore;
+
+use crate::models::session::Role;
+
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs a message with HMAC-SHA256, returning the signature. 
+pub fn sign(secret: &str, message: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Checks a signature made by `sign`, in constant time. 
+pub fn verify(secret: &str, message: &[u8], signature: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.verify_slice(signature).is_ok()
+}
+
+/// Compares two secrets in constant time, so how much of one was guessed 
+/// can't be timed. Only their lengths are leaked. 
+pub fn secrets_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+fn resume_message(session_id: &str, user_id: &str) -> Vec<u8> {
+    format!("resume:{}:{}", session_id, user_id).into_bytes()
+}
+
+/// Creates a token a user can present to resume their identity in a 
+/// session after their connection drops. 
+pub fn resume_token(secret: &str, session_id: &str, user_id: &str) -> String {
+    let signature = sign(secret, &resume_message(session_id, user_id));
+    format!("{}.{}", user_id, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// Checks a resume token was issued for a session, returning the user id 
+/// it was issued to. 
+pub fn verify_resume_token(secret: &str, session_id: &str, token: &str) -> Option<String> {
+    // User ids may hold dots, the signature's encoding never does.
+    let (user_id, signature) = token.rsplit_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    if !verify(secret, &resume_message(session_id, user_id), &signature) {
+        return None;
+    }
+    Some(user_id.to_owned())
+}
+
+fn invite_message(session_id: &str, role: Role, expires_at: u64) -> Vec<u8> {
+    format!("invite:{}:{}:{}", session_id, role.as_str(), expires_at).into_bytes()
+}
+
+/// Creates a token that lets a user join a session with a given role 
+/// until `expires_at`, in seconds since the unix epoch. 
+pub fn invite_token(secret: &str, session_id: &str, role: Role, expires_at: u64) -> String {
+    let signature = sign(secret, &invite_message(session_id, role, expires_at));
+    format!("{}.{}.{}", role.as_str(), expires_at, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// Checks an invite token was issued for a session and hasn't expired by 
+/// `now`, returning the role it grants. 
+pub fn verify_invite_token(secret: &str, session_id: &str, token: &str, now: u64) -> Option<Role> {
+    let mut parts = token.splitn(3, '.');
+    let role = Role::parse(parts.next()?)?;
+    let expires_at = parts.next()?.parse::<u64>().ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(parts.next()?).ok()?;
+    if now >= expires_at || !verify(secret, &invite_message(session_id, role, expires_at), &signature) {
+        return None;
+    }
+    Some(role)
+}
+
+/// Rounds of PBKDF2 a join password is hashed with. 
+const PASSWORD_ROUNDS: u32 = 100_000;
+const PASSWORD_SALT_BYTES: usize = 16;
+const PASSWORD_HASH_BYTES: usize = 32;
+
+/// Hashes a session's join password with PBKDF2-HMAC-SHA256 and a random 
+/// salt, as `<rounds>.<salt>.<hash>`. 
+pub fn password_hash(password: &str) -> String {
+    let mut salt = [0u8; PASSWORD_SALT_BYTES];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let hash = derive_password(password, &salt, PASSWORD_ROUNDS);
+    format!("{}.{}.{}", PASSWORD_ROUNDS, URL_SAFE_NO_PAD.encode(salt), URL_SAFE_NO_PAD.encode(hash))
+}
+
+/// Checks a password against a hash made by `password_hash`, in constant time. 
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    let mut parts = hash.splitn(3, '.');
+    let parsed = (
+        parts.next().and_then(|r| r.parse::<u32>().ok()),
+        parts.next().and_then(|s| URL_SAFE_NO_PAD.decode(s).ok()),
+        parts.next().and_then(|h| URL_SAFE_NO_PAD.decode(h).ok())
+    );
+    let (rounds, salt, expected) = match parsed {
+        (Some(rounds), Some(salt), Some(expected)) if rounds > 0 => (rounds, salt, expected),
+        _ => return false
+    };
+    derive_password(password, &salt, rounds).ct_eq(&expected).into()
+}
+
+fn derive_password(password: &str, salt: &[u8], rounds: u32) -> [u8; PASSWORD_HASH_BYTES] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, PASSWORD_HASH_BYTES>(password.as_bytes(), salt, rounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_tokens_are_bound_to_their_session() {
+        let token = resume_token("secret", "session", "user");
+        assert_eq!(verify_resume_token("secret", "session", &token), Some("user".to_owned()));
+        assert_eq!(verify_resume_token("secret", "other", &token), None);
+        assert_eq!(verify_resume_token("other", "session", &token), None);
+
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_eq!(verify_resume_token("secret", "session", &format!("admin.{}", signature)), None);
+        assert_eq!(verify_resume_token("secret", "session", "user"), None);
+    }
+
+    #[test]
+    fn resume_tokens_keep_dotted_user_ids() {
+        let token = resume_token("secret", "session", "jane.doe@example.com");
+        assert_eq!(verify_resume_token("secret", "session", &token), Some("jane.doe@example.com".to_owned()));
+
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert_eq!(verify_resume_token("secret", "session", &format!("jane.{}", signature)), None);
+    }
+
+    #[test]
+    fn invite_tokens_grant_their_role_until_they_expire() {
+        let token = invite_token("secret", "session", Role::Viewer, 100);
+        assert!(verify_invite_token("secret", "session", &token, 99) == Some(Role::Viewer));
+        assert!(verify_invite_token("secret", "session", &token, 100).is_none());
+        assert!(verify_invite_token("secret", "other", &token, 0).is_none());
+
+        let forged = token.replacen("viewer", "owner", 1);
+        assert!(verify_invite_token("secret", "session", &forged, 0).is_none());
+        let extended = token.replacen("100", "200", 1);
+        assert!(verify_invite_token("secret", "session", &extended, 150).is_none());
+    }
+
+    #[test]
+    fn password_hashes_are_salted() {
+        let hash = password_hash("hunter2");
+        assert!(verify_password(&hash, "hunter2"));
+        assert!(!verify_password(&hash, "hunter3"));
+        assert_ne!(hash, password_hash("hunter2"));
+
+        assert!(!verify_password("hunter2", "hunter2"));
+        assert!(!verify_password("0.c2FsdA.aGFzaA", "hunter2"));
+    }
+}